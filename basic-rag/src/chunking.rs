@@ -0,0 +1,578 @@
+// src/chunking.rs
+
+//! Chunking strategies for Basic RAG
+//!
+//! A [`Chunker`] splits the blocks of one section into chunk texts. Code
+//! blocks are never split below their lines, and every strategy renders them
+//! as Markdown fences. The strategy is chosen with `--chunker`:
+//! - [`TokenWindows`]: fixed windows of `--chunk-size` tokens with overlap
+//! - [`SentencePacker`]: whole sentences packed up to the chunk size, ending at
+//!   paragraph breaks where possible
+//! - [`RecursiveSplitter`]: whole paragraphs, falling back to sentences and then
+//!   words only for pieces larger than a chunk
+//! - [`SemanticChunker`]: new chunks where adjacent sentences' embeddings diverge
+
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::cli::{ChunkStrategy, Cli};
+use crate::embedder::{self, Embedder};
+use crate::embeddings::cosine_similarity;
+use crate::ingest::Block;
+use crate::tokenizer::Tokenizer;
+
+/// Code blocks up to this many chunk sizes stay whole in an oversized chunk;
+/// longer blocks are split at line boundaries
+const CODE_BLOCK_LIMIT_FACTOR: usize = 2;
+
+/// Chunks under `chunk_size / MIN_CHUNK_DIVISOR` tokens are merged into the previous chunk
+const MIN_CHUNK_DIVISOR: usize = 4;
+
+/// Words ending in a period that do not end a sentence (compared lowercase)
+const ABBREVIATIONS: &[&str] = &[
+    "e.g.", "i.e.", "cf.", "vs.", "approx.", "incl.", "fig.", "no.", "mr.", "mrs.", "ms.", "dr.", "st.",
+];
+
+/// Splits the content of a section into chunk texts
+pub trait Chunker {
+    /// Chunk texts for a section's blocks, in document order
+    fn split(&self, blocks: &[Block]) -> Result<Vec<String>>;
+}
+
+/// Build the chunker selected on the command line, measuring chunks with `tokenizer`
+pub fn from_cli(cli: &Cli, tokenizer: Arc<dyn Tokenizer>) -> Result<Box<dyn Chunker>> {
+    if cli.chunk_size <= cli.chunk_overlap {
+        bail!("chunk_size must be greater than chunk_overlap");
+    }
+    let (chunk_size, chunk_overlap) = (cli.chunk_size, cli.chunk_overlap);
+    debug!("Chunking with {:?} strategy ({} {} tokens, {} overlap)",
+           cli.chunker, chunk_size, tokenizer.name(), chunk_overlap);
+
+    Ok(match cli.chunker {
+        ChunkStrategy::Fixed => Box::new(TokenWindows { tokenizer, chunk_size, chunk_overlap }),
+        ChunkStrategy::Sentence => Box::new(SentencePacker { tokenizer, chunk_size, chunk_overlap }),
+        ChunkStrategy::Recursive => Box::new(RecursiveSplitter { tokenizer, chunk_size, chunk_overlap }),
+        ChunkStrategy::Semantic => Box::new(SemanticChunker {
+            embedder: embedder::from_cli(cli)?,
+            tokenizer,
+            chunk_size,
+            breakpoint_percentile: cli.semantic_breakpoint,
+        }),
+    })
+}
+
+/// Fixed windows of tokens; the last window ends at the section's end
+pub struct TokenWindows {
+    pub tokenizer: Arc<dyn Tokenizer>,
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+}
+
+impl Chunker for TokenWindows {
+    fn split(&self, blocks: &[Block]) -> Result<Vec<String>> {
+        let units = units(blocks, self.tokenizer.as_ref(), self.chunk_size, |prose| {
+            prose.split_whitespace().map(|word| (word, false)).collect()
+        });
+        let weights: Vec<usize> = units.iter().map(Unit::tokens).collect();
+        let windows = pack_windows(&weights, self.chunk_size, self.chunk_overlap);
+        Ok(render_windows(&units, windows))
+    }
+}
+
+/// Whole sentences up to the chunk size; a window that is at least half full
+/// ends at a paragraph break rather than splitting the next paragraph
+pub struct SentencePacker {
+    pub tokenizer: Arc<dyn Tokenizer>,
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+}
+
+impl Chunker for SentencePacker {
+    fn split(&self, blocks: &[Block]) -> Result<Vec<String>> {
+        let units = units(blocks, self.tokenizer.as_ref(), self.chunk_size, split_sentences);
+        let windows = pack_units(&units, self.chunk_size, self.chunk_overlap);
+        Ok(render_windows(&units, windows))
+    }
+}
+
+/// Paragraphs that fit a chunk stay whole; larger ones are split into
+/// sentences, and sentences larger than a chunk into words
+pub struct RecursiveSplitter {
+    pub tokenizer: Arc<dyn Tokenizer>,
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+}
+
+impl Chunker for RecursiveSplitter {
+    fn split(&self, blocks: &[Block]) -> Result<Vec<String>> {
+        let (tokenizer, chunk_size) = (self.tokenizer.as_ref(), self.chunk_size);
+        let units = units(blocks, tokenizer, chunk_size, |paragraph| {
+            if tokenizer.count(paragraph) <= chunk_size {
+                return vec![(paragraph, true)];
+            }
+            sentences(paragraph).into_iter()
+                .flat_map(|sentence| match tokenizer.count(sentence) <= chunk_size {
+                    true => vec![(sentence, true)],
+                    false => sentence.split_whitespace()
+                        .enumerate()
+                        .map(|(index, word)| (word, index == 0))
+                        .collect(),
+                })
+                .collect()
+        });
+        let windows = pack_units(&units, chunk_size, self.chunk_overlap);
+        Ok(render_windows(&units, windows))
+    }
+}
+
+/// Starts a chunk between sentences whose embedding distance is above the
+/// given percentile of the section's distances; groups larger than a chunk
+/// are packed by sentence
+pub struct SemanticChunker {
+    pub embedder: Box<dyn Embedder>,
+    pub tokenizer: Arc<dyn Tokenizer>,
+    pub chunk_size: usize,
+    /// Percentile (0-100) of adjacent-sentence distances that marks a breakpoint
+    pub breakpoint_percentile: f32,
+}
+
+impl SemanticChunker {
+    /// Unit indices that start a new topic
+    fn breakpoints(&self, units: &[Unit]) -> Result<Vec<usize>> {
+        // Two sentences always have one "largest" distance; it says nothing
+        if units.len() < 3 {
+            return Ok(Vec::new());
+        }
+
+        let texts: Vec<String> = units.iter().map(|unit| unit.text().to_string()).collect();
+        let embeddings = self.embedder.encode_batch(&texts)?;
+        if embeddings.len() != texts.len() {
+            bail!("Embedding backend returned {} vectors for {} sentences", embeddings.len(), texts.len());
+        }
+
+        let distances: Vec<f32> = embeddings.windows(2)
+            .map(|pair| 1.0 - cosine_similarity(&pair[0], &pair[1]))
+            .collect();
+        let threshold = percentile(&distances, self.breakpoint_percentile);
+        Ok(distances.iter()
+            .enumerate()
+            .filter(|(_, distance)| **distance > threshold)
+            .map(|(index, _)| index + 1)
+            .collect())
+    }
+}
+
+impl Chunker for SemanticChunker {
+    fn split(&self, blocks: &[Block]) -> Result<Vec<String>> {
+        let units = units(blocks, self.tokenizer.as_ref(), self.chunk_size, split_sentences);
+        let weights: Vec<usize> = units.iter().map(Unit::tokens).collect();
+
+        let windows = match self.breakpoints(&units) {
+            Ok(breakpoints) => {
+                let bounds: Vec<usize> = std::iter::once(0)
+                    .chain(breakpoints)
+                    .chain(std::iter::once(units.len()))
+                    .collect();
+                let mut windows = Vec::new();
+                for group in bounds.windows(2).map(|pair| pair[0]..pair[1]) {
+                    if weights[group.clone()].iter().sum::<usize>() <= self.chunk_size {
+                        windows.push(group);
+                    } else {
+                        let offset = group.start;
+                        windows.extend(pack_units(&units[group], self.chunk_size, 0)
+                            .into_iter()
+                            .map(|window| window.start + offset..window.end + offset));
+                    }
+                }
+                merge_short_windows(windows, &weights, self.chunk_size)
+            }
+            Err(e) => {
+                warn!("Semantic chunking failed, packing sentences instead: {:#}", e);
+                pack_units(&units, self.chunk_size, 0)
+            }
+        };
+
+        Ok(render_windows(&units, windows))
+    }
+}
+
+/// Split a paragraph after `.`, `!` or `?` when the next word does not start in lowercase
+fn sentences(paragraph: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut word_start = 0;
+    for (index, _) in paragraph.match_indices(' ') {
+        let word = &paragraph[word_start..index];
+        let next = paragraph[index + 1..].chars().next();
+        word_start = index + 1;
+        if ends_sentence(word) && next.is_some_and(|c| !c.is_lowercase()) {
+            sentences.push(paragraph[start..index].trim());
+            start = index + 1;
+        }
+    }
+    sentences.push(paragraph[start..].trim());
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+fn ends_sentence(word: &str) -> bool {
+    let word = word.trim_end_matches(['"', '\'', ')', ']', '\u{201d}', '\u{2019}']);
+    if word.ends_with(['!', '?', '\u{2026}']) {
+        return true;
+    }
+    let Some(stem) = word.strip_suffix('.') else {
+        return false;
+    };
+    // Initials ("J.") and abbreviations ("e.g.") do not end sentences
+    stem.chars().count() > 1 && !ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
+
+/// Sentences of a paragraph, each marked as starting a sentence
+fn split_sentences(paragraph: &str) -> Vec<(&str, bool)> {
+    sentences(paragraph).into_iter().map(|sentence| (sentence, true)).collect()
+}
+
+/// Value at `percentile` (0-100) of `values`, interpolating between ranks
+fn percentile(values: &[f32], percentile: f32) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let rank = percentile.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f32;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+/// Smallest piece of a section a chunker keeps together
+#[derive(Debug)]
+enum Unit<'a> {
+    /// A word, sentence or paragraph, depending on the strategy
+    Text { text: &'a str, starts_paragraph: bool, starts_sentence: bool, tokens: usize },
+    Code { language: Option<&'a str>, code: String, tokens: usize },
+}
+
+impl Unit<'_> {
+    fn tokens(&self) -> usize {
+        match self {
+            Unit::Text { tokens, .. } | Unit::Code { tokens, .. } => *tokens,
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            Unit::Text { text, .. } => text,
+            Unit::Code { code, .. } => code,
+        }
+    }
+
+    /// Whether a chunk may end right before this unit without splitting a paragraph
+    fn starts_paragraph(&self) -> bool {
+        match self {
+            Unit::Text { starts_paragraph, .. } => *starts_paragraph,
+            Unit::Code { .. } => true,
+        }
+    }
+
+    /// Whether a chunk may end right before this unit without splitting a sentence
+    fn starts_sentence(&self) -> bool {
+        match self {
+            Unit::Text { starts_sentence, .. } => *starts_sentence,
+            Unit::Code { .. } => true,
+        }
+    }
+}
+
+/// Break blocks into units, splitting each prose paragraph with `split_prose`
+/// (which marks pieces that start a sentence) and code blocks over the hard
+/// limit at line boundaries
+fn units<'a>(
+    blocks: &'a [Block],
+    tokenizer: &dyn Tokenizer,
+    chunk_size: usize,
+    split_prose: impl Fn(&'a str) -> Vec<(&'a str, bool)>,
+) -> Vec<Unit<'a>> {
+    let mut units = Vec::new();
+    for block in blocks {
+        match block {
+            Block::Prose(prose) => units.extend(split_prose(prose)
+                .into_iter()
+                .enumerate()
+                .map(|(index, (text, starts_sentence))| Unit::Text {
+                    text,
+                    starts_paragraph: index == 0,
+                    starts_sentence: starts_sentence || index == 0,
+                    tokens: tokenizer.count(text).max(1),
+                })),
+            Block::Code { language, code } => {
+                let language = language.as_deref();
+                let tokens = tokenizer.count(code);
+                if tokens <= chunk_size * CODE_BLOCK_LIMIT_FACTOR {
+                    units.push(Unit::Code { language, code: code.clone(), tokens: tokens.max(1) });
+                    continue;
+                }
+                // Greedily group whole lines into pieces of at most `chunk_size` tokens
+                let mut piece: Vec<&str> = Vec::new();
+                let mut piece_tokens = 0;
+                for line in code.lines() {
+                    let line_tokens = tokenizer.count(line);
+                    if !piece.is_empty() && piece_tokens + line_tokens > chunk_size {
+                        units.push(Unit::Code { language, code: piece.join("\n"), tokens: piece_tokens.max(1) });
+                        piece.clear();
+                        piece_tokens = 0;
+                    }
+                    piece.push(line);
+                    piece_tokens += line_tokens;
+                }
+                if !piece.is_empty() {
+                    units.push(Unit::Code { language, code: piece.join("\n"), tokens: piece_tokens.max(1) });
+                }
+            }
+        }
+    }
+    units
+}
+
+/// Group units into windows of at most `chunk_size` tokens that repeat up to
+/// `chunk_overlap` tokens of the previous window.
+///
+/// A unit larger than `chunk_size` gets a window of its own, and the last
+/// window ends at the final unit rather than leaving a short tail.
+fn pack_windows(weights: &[usize], chunk_size: usize, chunk_overlap: usize) -> Vec<Range<usize>> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+
+    let mut windows = Vec::new();
+    let mut start = 0;
+
+    loop {
+        let mut end = start;
+        let mut size = 0;
+        while end < weights.len() && (end == start || size + weights[end] <= chunk_size) {
+            size += weights[end];
+            end += 1;
+        }
+
+        if end == weights.len() {
+            let mut tail_start = start;
+            while tail_start > 0 && size + weights[tail_start - 1] <= chunk_size {
+                tail_start -= 1;
+                size += weights[tail_start];
+            }
+            windows.push(tail_start..end);
+            return windows;
+        }
+        windows.push(start..end);
+
+        // Step back over at most `chunk_overlap` tokens, always moving forward
+        start = overlap_start(weights, start, end, chunk_overlap);
+    }
+}
+
+/// Group whole units into windows of at most `chunk_size` tokens, ending a
+/// window that is at least half full at its last paragraph break (or else its
+/// last sentence start), and merge a short final window into the one before it
+fn pack_units(units: &[Unit], chunk_size: usize, chunk_overlap: usize) -> Vec<Range<usize>> {
+    let weights: Vec<usize> = units.iter().map(Unit::tokens).collect();
+    let mut windows = Vec::new();
+    let mut start = 0;
+
+    while start < units.len() {
+        let mut end = start;
+        let mut size = 0;
+        while end < units.len() && (end == start || size + weights[end] <= chunk_size) {
+            size += weights[end];
+            end += 1;
+        }
+
+        if end < units.len() {
+            let mut filled = weights[start];
+            let (mut paragraph_break, mut sentence_break) = (None, None);
+            for index in start + 1..end {
+                if filled * 2 >= chunk_size {
+                    if units[index].starts_paragraph() {
+                        paragraph_break = Some(index);
+                    }
+                    if units[index].starts_sentence() {
+                        sentence_break = Some(index);
+                    }
+                }
+                filled += weights[index];
+            }
+            end = paragraph_break.or(sentence_break).unwrap_or(end);
+        }
+        windows.push(start..end);
+
+        if end == units.len() {
+            break;
+        }
+        start = overlap_start(&weights, start, end, chunk_overlap);
+    }
+
+    merge_short_windows(windows, &weights, chunk_size)
+}
+
+/// Start of the window after `start..end`: back over at most `chunk_overlap`
+/// tokens of whole units, but always past `start`
+fn overlap_start(weights: &[usize], start: usize, end: usize, chunk_overlap: usize) -> usize {
+    let mut next = end;
+    let mut overlap = 0;
+    while next > start + 1 && overlap + weights[next - 1] <= chunk_overlap {
+        next -= 1;
+        overlap += weights[next];
+    }
+    next
+}
+
+/// Fold windows under the minimum chunk size into the window before them
+fn merge_short_windows(windows: Vec<Range<usize>>, weights: &[usize], chunk_size: usize) -> Vec<Range<usize>> {
+    let min_tokens = chunk_size / MIN_CHUNK_DIVISOR;
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(windows.len());
+    for window in windows {
+        let tokens: usize = weights[window.clone()].iter().sum();
+        match merged.last_mut() {
+            Some(previous) if tokens < min_tokens => previous.end = window.end,
+            _ => merged.push(window),
+        }
+    }
+    merged
+}
+
+fn render_windows(units: &[Unit], windows: Vec<Range<usize>>) -> Vec<String> {
+    windows.into_iter().map(|window| render_units(&units[window])).collect()
+}
+
+/// Render units as chunk text: prose joined by spaces with blank lines between
+/// paragraphs, code as Markdown fences
+fn render_units(units: &[Unit]) -> String {
+    let mut text = String::new();
+    let mut after_code = false;
+    for unit in units {
+        match unit {
+            Unit::Text { text: fragment, starts_paragraph, .. } => {
+                if !text.is_empty() {
+                    text.push_str(if after_code || *starts_paragraph { "\n\n" } else { " " });
+                }
+                text.push_str(fragment);
+                after_code = false;
+            }
+            Unit::Code { language, code, .. } => {
+                if !text.is_empty() {
+                    text.push_str("\n\n");
+                }
+                let fence = code_fence(code);
+                text.push_str(&format!("{}{}\n{}\n{}", fence, language.unwrap_or(""), code, fence));
+                after_code = true;
+            }
+        }
+    }
+    text
+}
+
+/// A backtick fence longer than any backtick run inside `code`
+fn code_fence(code: &str) -> String {
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest_run.max(2) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::Words;
+
+    fn prose(paragraphs: &[&str]) -> Vec<Block> {
+        paragraphs.iter().map(|paragraph| Block::Prose(paragraph.to_string())).collect()
+    }
+
+    /// Embeds each sentence by the topics it mentions
+    struct TopicEmbedder;
+
+    impl Embedder for TopicEmbedder {
+        fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter()
+                .map(|text| ["cache", "deploy"].iter()
+                    .map(|topic| if text.contains(topic) { 1.0 } else { 0.1 })
+                    .collect())
+                .collect())
+        }
+
+        fn dimension(&self) -> usize {
+            2
+        }
+
+        fn model_id(&self) -> &str {
+            "topics"
+        }
+    }
+
+    #[test]
+    fn test_sentences_respect_abbreviations() {
+        assert_eq!(
+            sentences("Use a flag, e.g. --fast. It helps! Does it? Yes (mostly.) see J. Doe. version 2.5 is out. done"),
+            vec!["Use a flag, e.g. --fast.", "It helps!", "Does it?", "Yes (mostly.) see J. Doe. version 2.5 is out. done"]
+        );
+    }
+
+    #[test]
+    fn test_sentence_packer_keeps_sentences_whole() {
+        let blocks = prose(&[
+            "One two three four. Five six seven eight.",
+            "Nine ten eleven twelve thirteen fourteen fifteen sixteen seventeen.",
+        ]);
+        let chunker = SentencePacker { tokenizer: Arc::new(Words), chunk_size: 10, chunk_overlap: 0 };
+        assert_eq!(chunker.split(&blocks).unwrap(), vec![
+            "One two three four. Five six seven eight.",
+            "Nine ten eleven twelve thirteen fourteen fifteen sixteen seventeen.",
+        ]);
+
+        // A paragraph break beats filling the window with the next paragraph's first sentence
+        let blocks = prose(&["A b c d e f.", "G h. I j k l m n o p."]);
+        assert_eq!(chunker.split(&blocks).unwrap(), vec!["A b c d e f.", "G h. I j k l m n o p."]);
+    }
+
+    #[test]
+    fn test_recursive_splitter_falls_back_to_finer_separators() {
+        let blocks = prose(&[
+            "Short paragraph here.",
+            "First sentence of a long one. Then a sentence that is far too long to fit in one chunk at all.",
+        ]);
+        let chunks = RecursiveSplitter { tokenizer: Arc::new(Words), chunk_size: 8, chunk_overlap: 0 }.split(&blocks).unwrap();
+        assert_eq!(chunks, vec![
+            "Short paragraph here.",
+            "First sentence of a long one.",
+            "Then a sentence that is far too long",
+            "to fit in one chunk at all.",
+        ]);
+    }
+
+    #[test]
+    fn test_short_tails_merge_into_previous_chunk() {
+        let blocks = prose(&["One two three four five six seven eight. Nine."]);
+        let chunks = SentencePacker { tokenizer: Arc::new(Words), chunk_size: 8, chunk_overlap: 0 }.split(&blocks).unwrap();
+        assert_eq!(chunks, vec!["One two three four five six seven eight. Nine."]);
+    }
+
+    #[test]
+    fn test_semantic_chunker_breaks_between_topics() {
+        let blocks = prose(&[
+            "The cache keeps hot pages. Evict the cache when full. Size the cache for peaks. \
+             To deploy, build the image. Then deploy it to staging. Finally deploy to production.",
+        ]);
+        let chunker = SemanticChunker {
+            embedder: Box::new(TopicEmbedder),
+            tokenizer: Arc::new(Words),
+            chunk_size: 40,
+            breakpoint_percentile: 90.0,
+        };
+        assert_eq!(chunker.split(&blocks).unwrap(), vec![
+            "The cache keeps hot pages. Evict the cache when full. Size the cache for peaks.",
+            "To deploy, build the image. Then deploy it to staging. Finally deploy to production.",
+        ]);
+    }
+
+    #[test]
+    fn test_code_fence_outgrows_backticks_in_code() {
+        assert_eq!(code_fence("plain"), "```");
+        assert_eq!(code_fence("use ```md``` here"), "````");
+    }
+}
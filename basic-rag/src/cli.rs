@@ -1,6 +1,11 @@
 use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::embedding_store::Quantization;
+use crate::retriever::ContextExpansion;
+use crate::sources::FileTypeRule;
+
 #[derive(Parser, Debug)]
 #[command(name = "basic-rag")]
 #[command(about = "A basic RAG system with hybrid search", long_about = None)]
@@ -21,6 +26,54 @@ pub struct Cli {
     #[arg(long, default_value = "50")]
     pub chunk_overlap: usize,
     
+    /// Tokenizer used to size chunks and prompts (auto picks the LLM's encoding)
+    #[arg(long, default_value = "auto")]
+    pub tokenizer: TokenizerKind,
+    
+    /// Directory holding cl100k_base.tiktoken / o200k_base.tiktoken vocabularies
+    #[arg(long, env = "BASIC_RAG_TOKENIZER_DIR", default_value = "./tokenizers")]
+    pub tokenizer_dir: PathBuf,
+    
+    /// How sections are split into chunks
+    #[arg(long, default_value = "fixed")]
+    pub chunker: ChunkStrategy,
+    
+    /// Percentile of sentence distances that starts a new chunk with --chunker semantic
+    #[arg(long, default_value = "90")]
+    pub semantic_breakpoint: f32,
+    
+    /// Include cell outputs when ingesting Jupyter notebooks
+    #[arg(long)]
+    pub notebook_outputs: bool,
+    
+    /// Estimated similarity at which a chunk is folded into an earlier near-duplicate
+    #[arg(long, default_value = "0.9")]
+    pub dedup_threshold: f32,
+    
+    /// Keep near-duplicate chunks instead of folding them together
+    #[arg(long)]
+    pub no_dedup: bool,
+    
+    /// JSON or YAML file with include/exclude globs and file-type rules
+    #[arg(long, env = "BASIC_RAG_INGEST_CONFIG")]
+    pub ingest_config: Option<PathBuf>,
+    
+    /// Only ingest files matching these globs, relative to the docs directory
+    #[arg(long, value_delimiter = ',')]
+    pub include: Vec<String>,
+    
+    /// Skip files matching these globs, relative to the docs directory
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+    
+    /// Process files matching a glob with a given processor, e.g. '*.mdx=markdown'
+    #[arg(long)]
+    pub file_type: Vec<FileTypeRule>,
+    
+    /// Ingest files listed in .gitignore/.ragignore files too
+    #[arg(long)]
+    pub no_ignore_files: bool,
+    
     /// Number of top results to retrieve
     #[arg(short = 'k', long, default_value = "5")]
     pub top_k: usize,
@@ -29,6 +82,43 @@ pub struct Cli {
     #[arg(long, env = "OPENAI_API_KEY")]
     pub openai_api_key: String,
     
+    /// Embedding backend used for indexing and semantic search
+    #[arg(long, default_value = "worker")]
+    pub embedder: EmbedderBackend,
+    
+    /// Embedding model name (sentence-transformers model or HTTP model id); also
+    /// names the model of a --model-path directory in the embedding store
+    #[arg(long, default_value = "all-MiniLM-L6-v2")]
+    pub embedding_model: String,
+    
+    /// Base URL of an OpenAI-compatible embeddings API (for --embedder http)
+    #[arg(long, default_value = "https://api.openai.com")]
+    pub embedding_url: String,
+    
+    /// API key for the embeddings endpoint (defaults to the OpenAI API key)
+    #[arg(long, env = "EMBEDDING_API_KEY")]
+    pub embedding_api_key: Option<String>,
+    
+    /// Local sentence-transformer directory (for --embedder native)
+    #[arg(long, env = "EMBEDDING_MODEL_PATH")]
+    pub model_path: Option<PathBuf>,
+    
+    /// Pooling applied to token embeddings (for --embedder native)
+    #[arg(long, default_value = "mean")]
+    pub pooling: Pooling,
+    
+    /// Skip L2 normalization of native embeddings
+    #[arg(long)]
+    pub no_normalize: bool,
+    
+    /// HNSW candidate list size at query time (higher = better recall, slower)
+    #[arg(long, default_value = "64")]
+    pub ef_search: usize,
+    
+    /// Scan every embedding instead of using the HNSW index
+    #[arg(long)]
+    pub exact_search: bool,
+    
     #[command(subcommand)]
     pub command: Command,
 }
@@ -40,6 +130,54 @@ pub enum Command {
         /// Skip embedding generation (BM25 only)
         #[arg(long)]
         skip_embeddings: bool,
+        
+        /// Git repository to clone docs from (omit to use --docs-dir as-is)
+        #[arg(long, env = "DOCS_REPO_URL")]
+        repo_url: Option<String>,
+        
+        /// Branch to check out from the docs repository
+        #[arg(long, default_value = "main")]
+        branch: String,
+        
+        /// Subfolder of the repository to check out (sparse checkout)
+        #[arg(long)]
+        sparse_path: Option<String>,
+        
+        /// Shallow fetch depth (omit for full history)
+        #[arg(long)]
+        depth: Option<i32>,
+        
+        /// Chunks sent to the embedding backend per request
+        #[arg(long, default_value = "32")]
+        embed_batch_size: usize,
+        
+        /// Embedding batches processed concurrently
+        #[arg(long, default_value = "2")]
+        embed_concurrency: usize,
+        
+        /// Retries for a failed embedding batch before skipping it
+        #[arg(long, default_value = "3")]
+        embed_retries: usize,
+        
+        /// HNSW links per node (layer 0 keeps twice as many); at least 2
+        #[arg(long, default_value = "16", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(2..))]
+        hnsw_m: usize,
+        
+        /// HNSW candidate list size while building the graph
+        #[arg(long, default_value = "200")]
+        hnsw_ef_construction: usize,
+        
+        /// Skip building the HNSW index (semantic search scans all embeddings)
+        #[arg(long)]
+        no_hnsw: bool,
+        
+        /// Encoding of stored vectors: none (f32), int8 or binary
+        #[arg(long, default_value = "none")]
+        quantization: Quantization,
+        
+        /// Keep full-precision vectors to rescore quantized search results
+        #[arg(long)]
+        rescore: bool,
     },
     
     /// Query the index
@@ -54,6 +192,45 @@ pub enum Command {
         /// Custom alpha value for hybrid search (0.0-1.0, where 1.0 = pure BM25)
         #[arg(long)]
         alpha: Option<f32>,
+        
+        /// How hybrid search merges BM25 and semantic results
+        #[arg(long, default_value = "linear")]
+        fusion: Fusion,
+        
+        /// RRF rank constant k (larger values flatten rank differences)
+        #[arg(long, default_value = "60")]
+        rrf_k: f32,
+        
+        /// RRF weight of the BM25 result list
+        #[arg(long, default_value = "1.0")]
+        bm25_weight: f32,
+        
+        /// RRF weight of the semantic result list
+        #[arg(long, default_value = "1.0")]
+        semantic_weight: f32,
+        
+        /// Text given to the LLM for each match: none, neighbors:N (N chunks either side) or section
+        #[arg(long, default_value = "none")]
+        context_expansion: ContextExpansion,
+    },
+    
+    /// Measure HNSW recall against an exact scan of the stored embeddings
+    BenchAnn {
+        /// Number of stored embeddings used as queries
+        #[arg(long, default_value = "200")]
+        queries: usize,
+        
+        /// Query-time ef values to evaluate (comma separated)
+        #[arg(long, value_delimiter = ',', default_value = "16,32,64,128,256")]
+        ef: Vec<usize>,
+        
+        /// Build a fresh graph with this M (at least 2) instead of loading the saved one
+        #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(2..))]
+        m: Option<usize>,
+        
+        /// ef_construction for a fresh graph (with --m)
+        #[arg(long, default_value = "200")]
+        ef_construction: usize,
     },
 }
 
@@ -67,4 +244,60 @@ pub enum SearchStrategy {
     Semantic,
     /// Use hybrid search with balanced weights
     Hybrid,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkStrategy {
+    /// Fixed token windows with overlap
+    #[default]
+    Fixed,
+    /// Whole sentences, ending chunks at paragraph breaks
+    Sentence,
+    /// Paragraphs, then sentences, then words, whichever fits
+    Recursive,
+    /// New chunks where the topic shifts between sentences (uses the embedder)
+    Semantic,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum TokenizerKind {
+    /// The LLM's BPE encoding if its vocabulary is available, else the heuristic
+    Auto,
+    /// Estimate 4 bytes per token
+    Heuristic,
+    /// cl100k_base BPE (GPT-4, GPT-3.5)
+    Cl100k,
+    /// o200k_base BPE (GPT-4o and newer)
+    O200k,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum Fusion {
+    /// Blend normalized scores using alpha
+    Linear,
+    /// Reciprocal Rank Fusion over result ranks
+    Rrf,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum EmbedderBackend {
+    /// Run sentence-transformers in a new Python process per call
+    Python,
+    /// Keep a persistent Python worker speaking JSON lines
+    Worker,
+    /// Call an OpenAI-compatible /v1/embeddings endpoint
+    Http,
+    /// Run a local sentence-transformer in-process (no Python needed)
+    Native,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum Pooling {
+    /// Average of token embeddings, ignoring padding
+    Mean,
+    /// Embedding of the first ([CLS]) token
+    Cls,
+    /// Element-wise maximum over tokens
+    Max,
 }
\ No newline at end of file
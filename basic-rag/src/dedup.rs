@@ -0,0 +1,239 @@
+// src/dedup.rs
+
+//! Near-duplicate chunk detection for Basic RAG
+//!
+//! Scraped docs repeat boilerplate (licence footers, "see also" lists,
+//! re-exported item pages) that would otherwise crowd out useful results.
+//! Each chunk gets a MinHash signature over its word shingles; signatures are
+//! bucketed by LSH bands so only likely matches are compared, and a chunk
+//! whose estimated Jaccard similarity to an earlier chunk reaches the
+//! threshold is folded into it, leaving its source as an alternate.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+use crate::ingest::{chunk_file, Chunk};
+
+/// Number of MinHash functions per signature
+const NUM_HASHES: usize = 64;
+
+/// LSH bands; chunks sharing any band are compared. 16 bands of 4 rows find
+/// pairs above roughly 50% similarity, well below usable thresholds.
+const BANDS: usize = 16;
+const ROWS: usize = NUM_HASHES / BANDS;
+
+/// Words per shingle
+const SHINGLE_WORDS: usize = 3;
+
+/// MinHash signature of a chunk's text
+struct Signature([u64; NUM_HASHES]);
+
+impl Signature {
+    fn of(text: &str) -> Self {
+        let words: Vec<String> = text.split_whitespace()
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let mut mins = [u64::MAX; NUM_HASHES];
+        // Texts shorter than a shingle are one shingle
+        for shingle in words.windows(SHINGLE_WORDS.min(words.len()).max(1)) {
+            let base = hash_of(&shingle);
+            for (i, min) in mins.iter_mut().enumerate() {
+                *min = (*min).min(mix(base ^ mix(i as u64 + 1)));
+            }
+        }
+        Signature(mins)
+    }
+
+    /// Estimated Jaccard similarity of the two shingle sets
+    fn similarity(&self, other: &Signature) -> f32 {
+        let equal = self.0.iter().zip(&other.0).filter(|(a, b)| a == b).count();
+        equal as f32 / NUM_HASHES as f32
+    }
+
+    fn band_keys(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.0.chunks(ROWS).enumerate().map(|(band, rows)| (band, hash_of(&rows)))
+    }
+}
+
+fn hash_of(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// SplitMix64 finalizer, used to derive independent hash functions
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Chunks left after folding near-duplicates into their first occurrence
+#[derive(Debug, Default)]
+pub struct Deduplicated {
+    /// Indexed chunks that gained an alternate source, then the kept new chunks
+    pub chunks: Vec<Chunk>,
+    /// Number of chunks folded into an earlier one
+    pub removed: usize,
+    /// Pairs of different files that shared a duplicate (canonical file first)
+    pub links: BTreeSet<(String, String)>,
+}
+
+/// Fold chunks at least `threshold` similar to an earlier chunk into it.
+///
+/// The first chunk of each group is kept, in order, and records the citations
+/// of the others in `alternate_sources`. `indexed` chunks, already in the
+/// index for files that were not re-read, come before all of `chunks` and are
+/// never folded; they are only returned when they gained an alternate source.
+pub fn deduplicate(indexed: Vec<Chunk>, chunks: Vec<Chunk>, threshold: f32) -> Deduplicated {
+    let mut result = Deduplicated::default();
+    let mut signatures: Vec<Signature> = Vec::new();
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+
+    let seeded = indexed.len();
+    for chunk in indexed {
+        let signature = Signature::of(&chunk.text);
+        for key in signature.band_keys() {
+            buckets.entry(key).or_default().push(result.chunks.len());
+        }
+        signatures.push(signature);
+        result.chunks.push(chunk);
+    }
+    let mut updated = vec![false; seeded];
+
+    for chunk in chunks {
+        let signature = Signature::of(&chunk.text);
+        let keys: Vec<(usize, u64)> = signature.band_keys().collect();
+
+        let canonical = keys.iter()
+            .filter_map(|key| buckets.get(key))
+            .flatten()
+            .copied()
+            .filter(|&kept| signatures[kept].similarity(&signature) >= threshold)
+            .min();
+
+        match canonical {
+            Some(kept) => {
+                let original = &mut result.chunks[kept];
+                log::debug!("Chunk {} is a near-duplicate of {}", chunk.id, original.id);
+                let citation = chunk.citation();
+                if citation != original.citation() && !original.alternate_sources.contains(&citation) {
+                    original.alternate_sources.push(citation);
+                    if kept < seeded {
+                        updated[kept] = true;
+                    }
+                }
+                let (original_file, file) = (chunk_file(&original.id), chunk_file(&chunk.id));
+                if original_file != file {
+                    result.links.insert((original_file.to_string(), file.to_string()));
+                }
+                result.removed += 1;
+            }
+            None => {
+                let kept = result.chunks.len();
+                for key in keys {
+                    buckets.entry(key).or_default().push(kept);
+                }
+                signatures.push(signature);
+                result.chunks.push(chunk);
+            }
+        }
+    }
+
+    result.chunks = result.chunks.into_iter()
+        .enumerate()
+        .filter(|&(kept, _)| kept >= seeded || updated[kept])
+        .map(|(_, chunk)| chunk)
+        .collect();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::DocMetadata;
+
+    fn chunk(id: &str, text: &str) -> Chunk {
+        Chunk {
+            id: id.to_string(),
+            text: text.to_string(),
+            source: chunk_file(id).to_string(),
+            heading: None,
+            position: 0,
+            page: None,
+            alternate_sources: Vec::new(),
+            metadata: DocMetadata::default(),
+        }
+    }
+
+    const FOOTER: &str = "Licensed under either of Apache License, Version 2.0 or MIT license at your option. \
+                          Unless you explicitly state otherwise, any contribution intentionally submitted \
+                          for inclusion in the work shall be dual licensed as above, without any additional terms.";
+
+    #[test]
+    fn test_similarity_tracks_overlap() {
+        let footer = Signature::of(FOOTER);
+        assert_eq!(footer.similarity(&Signature::of(FOOTER)), 1.0);
+        assert_eq!(footer.similarity(&Signature::of(&FOOTER.to_uppercase())), 1.0);
+
+        let edited = FOOTER.replace("at your option", "at the user's option");
+        let similarity = footer.similarity(&Signature::of(&edited));
+        assert!(similarity > 0.6 && similarity < 1.0, "similarity {}", similarity);
+
+        let unrelated = Signature::of("Configure the server port and the log level in config.toml.");
+        assert!(footer.similarity(&unrelated) < 0.1);
+    }
+
+    #[test]
+    fn test_duplicates_fold_into_first_occurrence() {
+        let footer_with_typo = FOOTER.replace("explicitly", "explicity");
+        let result = deduplicate(Vec::new(), vec![
+            chunk("a.md:chunk0", "Install with cargo install basic-rag."),
+            chunk("a.md:chunk1", FOOTER),
+            chunk("b.md:chunk0", "Query the index with the query command."),
+            chunk("b.md:chunk1", FOOTER),
+            chunk("c.md:chunk0", &footer_with_typo),
+            chunk("c.md:chunk1", FOOTER),
+        ], 0.8);
+
+        let ids: Vec<&str> = result.chunks.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(ids, vec!["a.md:chunk0", "a.md:chunk1", "b.md:chunk0"]);
+        assert_eq!(result.removed, 3);
+        assert_eq!(result.chunks[1].alternate_sources, vec!["b.md", "c.md"]);
+        assert_eq!(result.links, BTreeSet::from([
+            ("a.md".to_string(), "b.md".to_string()),
+            ("a.md".to_string(), "c.md".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn test_threshold_one_keeps_near_duplicates() {
+        let edited = FOOTER.replace("at your option", "at the user's option");
+        let result = deduplicate(Vec::new(), vec![chunk("a.md:chunk0", FOOTER), chunk("b.md:chunk0", &edited)], 1.0);
+        assert_eq!(result.chunks.len(), 2);
+        assert_eq!(result.removed, 0);
+    }
+
+    #[test]
+    fn test_indexed_chunks_stay_canonical() {
+        let indexed = vec![
+            chunk("a.md:chunk0", "Install with cargo install basic-rag."),
+            chunk("a.md:chunk1", FOOTER),
+            chunk("b.md:chunk0", FOOTER),
+        ];
+        let result = deduplicate(indexed, vec![
+            chunk("c.md:chunk0", "Query the index with the query command."),
+            chunk("c.md:chunk1", FOOTER),
+        ], 0.8);
+
+        let ids: Vec<&str> = result.chunks.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(ids, vec!["a.md:chunk1", "c.md:chunk0"], "only updated indexed chunks are returned");
+        assert_eq!(result.chunks[0].alternate_sources, vec!["c.md"]);
+        assert_eq!(result.removed, 1);
+        assert_eq!(result.links, BTreeSet::from([("a.md".to_string(), "c.md".to_string())]));
+    }
+}
@@ -0,0 +1,563 @@
+// src/embedder.rs
+
+//! Embedding backends for Basic RAG
+//!
+//! The [`Embedder`] trait abstracts over how text is turned into vectors so
+//! indexing and hybrid search can swap backends by configuration:
+//! - [`PythonScriptEmbedder`]: runs sentence-transformers in a fresh `python3` process per call
+//! - [`PythonWorkerEmbedder`]: keeps one `python3` worker alive, speaking JSON lines
+//! - [`HttpEmbedder`]: calls an OpenAI-compatible `/v1/embeddings` endpoint
+//! - [`NativeEmbedder`]: runs a local sentence-transformer in-process with Candle
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::cli::{Cli, EmbedderBackend};
+use crate::native_embedder::NativeEmbedder;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A text embedding backend
+pub trait Embedder: Send + Sync {
+    /// Encode a single text into a vector
+    fn encode(&self, text: &str) -> Result<Vec<f32>> {
+        self.encode_batch(&[text.to_string()])?
+            .pop()
+            .ok_or_else(|| anyhow!("Embedding backend returned no vector"))
+    }
+
+    /// Encode several texts, returning one vector per input in order
+    fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    /// Dimension of the produced vectors
+    fn dimension(&self) -> usize;
+
+    /// Identifier of the underlying model, used to detect incompatible stores
+    fn model_id(&self) -> &str;
+}
+
+impl<E: Embedder + ?Sized> Embedder for Box<E> {
+    fn encode(&self, text: &str) -> Result<Vec<f32>> {
+        (**self).encode(text)
+    }
+
+    fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        (**self).encode_batch(texts)
+    }
+
+    fn dimension(&self) -> usize {
+        (**self).dimension()
+    }
+
+    fn model_id(&self) -> &str {
+        (**self).model_id()
+    }
+}
+
+/// Build the embedding backend selected on the command line
+pub fn from_cli(cli: &Cli) -> Result<Box<dyn Embedder>> {
+    info!("Initializing {:?} embedding backend ({})", cli.embedder, cli.embedding_model);
+
+    let embedder: Box<dyn Embedder> = match cli.embedder {
+        EmbedderBackend::Python => Box::new(PythonScriptEmbedder::new(&cli.embedding_model)?),
+        EmbedderBackend::Worker => Box::new(PythonWorkerEmbedder::new(&cli.embedding_model)?),
+        EmbedderBackend::Http => {
+            let api_key = cli.embedding_api_key.as_deref().unwrap_or(&cli.openai_api_key);
+            Box::new(HttpEmbedder::new(&cli.embedding_url, &cli.embedding_model, Some(api_key))?)
+        }
+        EmbedderBackend::Native => {
+            let model_path = cli.model_path.as_ref()
+                .ok_or_else(|| anyhow!("--embedder native requires --model-path <DIR>"))?;
+            Box::new(NativeEmbedder::load(model_path, &cli.embedding_model, cli.pooling.clone(), !cli.no_normalize)?)
+        }
+    };
+
+    info!("✅ Embedding backend ready: {} ({} dimensions)", embedder.model_id(), embedder.dimension());
+    Ok(embedder)
+}
+
+/// One-shot script: reads a JSON list of texts on stdin, prints a JSON list of vectors
+const EMBED_SCRIPT: &str = r#"
+import sys
+import json
+from sentence_transformers import SentenceTransformer
+
+model = SentenceTransformer(sys.argv[1])
+texts = json.load(sys.stdin)
+print(json.dumps(model.encode(texts).tolist()))
+"#;
+
+/// Long-running worker: one JSON request per line in, one JSON response per line out
+const WORKER_SCRIPT: &str = r#"
+import sys
+import json
+from sentence_transformers import SentenceTransformer
+
+model = SentenceTransformer(sys.argv[1])
+print(json.dumps({"dimension": model.get_sentence_embedding_dimension()}), flush=True)
+
+for line in sys.stdin:
+    try:
+        request = json.loads(line)
+        embeddings = model.encode(request["texts"])
+        print(json.dumps({"embeddings": embeddings.tolist()}), flush=True)
+    except Exception as e:
+        print(json.dumps({"error": str(e)}), flush=True)
+"#;
+
+/// Runs sentence-transformers in a new `python3` process for every call.
+///
+/// Slow (the model is reloaded each time) but has no state to manage.
+pub struct PythonScriptEmbedder {
+    model: String,
+    dimension: usize,
+}
+
+impl PythonScriptEmbedder {
+    pub fn new(model: &str) -> Result<Self> {
+        info!("Testing Python embedding model...");
+        let mut embedder = Self { model: model.to_string(), dimension: 0 };
+
+        let probe = embedder.encode("test text")
+            .context("Python embedding script failed. Try: pip install sentence-transformers")?;
+        embedder.dimension = probe.len();
+
+        info!("✅ Python embedding model working correctly");
+        Ok(embedder)
+    }
+}
+
+impl Embedder for PythonScriptEmbedder {
+    fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        debug!("Encoding {} texts with Python script", texts.len());
+
+        let mut command = Command::new("python3");
+        command.arg("-c").arg(EMBED_SCRIPT).arg(&self.model);
+
+        // Texts go over stdin so large chunks don't hit argument length limits
+        let stdout = run_script(command, serde_json::to_vec(texts)?)?;
+        let embeddings: Vec<Vec<f32>> = serde_json::from_slice(&stdout)
+            .context("Failed to parse embedding JSON")?;
+        check_batch(texts, &embeddings)?;
+
+        Ok(embeddings)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Run `command` with `input` on stdin, returning its stdout.
+///
+/// Stdin is written from another thread while stdout and stderr are drained,
+/// so a script that exits early or fills stderr first cannot block the
+/// write; its stderr is reported when it fails.
+fn run_script(mut command: Command, input: Vec<u8>) -> Result<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to execute Python embedding script. Make sure Python 3 and sentence-transformers are installed.")?;
+    let mut stdin = child.stdin.take()
+        .ok_or_else(|| anyhow!("Failed to open stdin of embedding script"))?;
+
+    let (written, output) = std::thread::scope(|scope| {
+        // Dropping stdin at the end of the write closes the pipe
+        let writer = scope.spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output();
+        (writer.join().expect("embedding script stdin writer panicked"), output)
+    });
+    let output = output.context("Failed to wait for embedding script")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Embedding failed: {}", stderr));
+    }
+    written.context("Failed to send texts to embedding script")?;
+    Ok(output.stdout)
+}
+
+/// Request sent to the Python worker
+#[derive(Serialize)]
+struct WorkerRequest<'a> {
+    texts: &'a [String],
+}
+
+/// Response line from the Python worker
+#[derive(Deserialize)]
+struct WorkerResponse {
+    #[serde(default)]
+    dimension: Option<usize>,
+    #[serde(default)]
+    embeddings: Option<Vec<Vec<f32>>>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Pipes to a running worker process
+struct WorkerIo {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl WorkerIo {
+    fn read_response(&mut self) -> Result<WorkerResponse> {
+        let mut line = String::new();
+        let read = self.stdout.read_line(&mut line)
+            .context("Failed to read from embedding worker")?;
+        if read == 0 {
+            return Err(anyhow!("Embedding worker exited unexpectedly"));
+        }
+
+        let response: WorkerResponse = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid response from embedding worker: {}", line.trim()))?;
+        if let Some(error) = response.error {
+            return Err(anyhow!("Embedding worker error: {}", error));
+        }
+        Ok(response)
+    }
+}
+
+/// Keeps a single `python3` worker alive and talks to it over JSON lines.
+///
+/// The model is loaded once, so per-call cost is just the encoding itself.
+pub struct PythonWorkerEmbedder {
+    model: String,
+    dimension: usize,
+    io: Mutex<WorkerIo>,
+}
+
+impl PythonWorkerEmbedder {
+    pub fn new(model: &str) -> Result<Self> {
+        let mut command = Command::new("python3");
+        command.arg("-u").arg("-c").arg(WORKER_SCRIPT).arg(model);
+        Self::spawn(command, model)
+            .context("Failed to start Python embedding worker. Try: pip install sentence-transformers")
+    }
+
+    /// Spawn `command` as the worker; it must print a `{"dimension": N}` line once ready
+    pub fn spawn(mut command: Command, model: &str) -> Result<Self> {
+        info!("Starting embedding worker for {}", model);
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .context("Failed to spawn embedding worker")?;
+
+        let stdin = child.stdin.take()
+            .ok_or_else(|| anyhow!("Failed to open stdin of embedding worker"))?;
+        let stdout = BufReader::new(child.stdout.take()
+            .ok_or_else(|| anyhow!("Failed to open stdout of embedding worker"))?);
+        let mut io = WorkerIo { child, stdin, stdout };
+
+        let dimension = io.read_response()?
+            .dimension
+            .ok_or_else(|| anyhow!("Embedding worker did not report its dimension"))?;
+
+        info!("✅ Embedding worker ready ({} dimensions)", dimension);
+        Ok(Self { model: model.to_string(), dimension, io: Mutex::new(io) })
+    }
+}
+
+impl Embedder for PythonWorkerEmbedder {
+    fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        debug!("Encoding {} texts with embedding worker", texts.len());
+
+        let mut io = self.io.lock()
+            .map_err(|_| anyhow!("Embedding worker lock poisoned"))?;
+
+        let mut request = serde_json::to_string(&WorkerRequest { texts })?;
+        request.push('\n');
+        io.stdin.write_all(request.as_bytes())
+            .and_then(|_| io.stdin.flush())
+            .context("Failed to send texts to embedding worker")?;
+
+        let embeddings = io.read_response()?
+            .embeddings
+            .ok_or_else(|| anyhow!("Embedding worker response missing 'embeddings'"))?;
+        check_batch(texts, &embeddings)?;
+
+        Ok(embeddings)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+}
+
+impl Drop for PythonWorkerEmbedder {
+    fn drop(&mut self) {
+        if let Ok(io) = self.io.get_mut() {
+            if let Err(e) = io.child.kill() {
+                warn!("Failed to stop embedding worker: {}", e);
+            }
+            let _ = io.child.wait();
+        }
+    }
+}
+
+/// OpenAI-compatible embeddings request
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+/// OpenAI-compatible embeddings response
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+/// Calls an OpenAI-compatible `POST {base_url}/v1/embeddings` endpoint
+pub struct HttpEmbedder {
+    client: reqwest::blocking::Client,
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+    dimension: usize,
+}
+
+impl HttpEmbedder {
+    pub fn new(base_url: &str, model: &str, api_key: Option<&str>) -> Result<Self> {
+        let client = off_runtime(|| reqwest::blocking::Client::builder().timeout(HTTP_TIMEOUT).build())
+            .context("Failed to create HTTP client")?;
+
+        let mut embedder = Self {
+            client,
+            endpoint: format!("{}/v1/embeddings", base_url.trim_end_matches('/')),
+            model: model.to_string(),
+            api_key: api_key.filter(|key| !key.is_empty()).map(str::to_string),
+            dimension: 0,
+        };
+
+        let probe = embedder.encode("test text")
+            .with_context(|| format!("Embedding endpoint {} is not usable", embedder.endpoint))?;
+        embedder.dimension = probe.len();
+
+        Ok(embedder)
+    }
+}
+
+impl Embedder for HttpEmbedder {
+    fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        debug!("Encoding {} texts via {}", texts.len(), self.endpoint);
+
+        let mut request = self.client
+            .post(&self.endpoint)
+            .json(&EmbeddingsRequest { model: &self.model, input: texts });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let mut response: EmbeddingsResponse = off_runtime(|| {
+            request.send()
+                .and_then(|response| response.error_for_status())
+                .with_context(|| format!("Embedding request to {} failed", self.endpoint))?
+                .json()
+                .context("Failed to parse embeddings response")
+        })?;
+
+        // The API may return items out of order; `index` is authoritative
+        response.data.sort_by_key(|item| item.index);
+        let embeddings: Vec<Vec<f32>> = response.data.into_iter().map(|item| item.embedding).collect();
+        check_batch(texts, &embeddings)?;
+
+        Ok(embeddings)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Run `f` off the async runtime's threads, where reqwest's blocking client
+/// refuses to block (`init` and `query` create and call embedders from async code)
+fn off_runtime<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    if tokio::runtime::Handle::try_current().is_err() {
+        return f();
+    }
+    std::thread::scope(|scope| scope.spawn(f).join().expect("embedding request thread panicked"))
+}
+
+/// Ensure a backend returned exactly one vector per input text
+fn check_batch(texts: &[String], embeddings: &[Vec<f32>]) -> Result<()> {
+    if texts.len() != embeddings.len() {
+        return Err(anyhow!(
+            "Embedding backend returned {} vectors for {} texts",
+            embeddings.len(),
+            texts.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// Stand-in worker that embeds each text as [length, word count]
+    const FAKE_WORKER: &str = r#"
+import sys, json
+print(json.dumps({"dimension": 2}), flush=True)
+for line in sys.stdin:
+    texts = json.loads(line)["texts"]
+    if "fail" in texts:
+        print(json.dumps({"error": "boom"}), flush=True)
+    else:
+        print(json.dumps({"embeddings": [[len(t), len(t.split())] for t in texts]}), flush=True)
+"#;
+
+    fn python_available() -> bool {
+        Command::new("python3").arg("--version").output().is_ok()
+    }
+
+    #[test]
+    fn test_python_worker_protocol() {
+        if !python_available() {
+            return;
+        }
+
+        let mut command = Command::new("python3");
+        command.arg("-u").arg("-c").arg(FAKE_WORKER);
+        let embedder = PythonWorkerEmbedder::spawn(command, "fake").unwrap();
+
+        assert_eq!(embedder.dimension(), 2);
+        assert_eq!(embedder.model_id(), "fake");
+        assert_eq!(embedder.encode("hello world").unwrap(), vec![11.0, 2.0]);
+
+        // The same worker keeps serving requests
+        let batch = embedder.encode_batch(&["a".to_string(), "b c".to_string()]).unwrap();
+        assert_eq!(batch, vec![vec![1.0, 1.0], vec![3.0, 2.0]]);
+
+        let error = embedder.encode("fail").unwrap_err();
+        assert!(error.to_string().contains("boom"));
+    }
+
+    #[test]
+    fn test_run_script_reports_stderr_of_early_exit() {
+        if !python_available() {
+            return;
+        }
+
+        // Fills the stderr pipe and exits without reading a batch larger than the stdin pipe
+        let mut command = Command::new("python3");
+        command.arg("-c").arg(
+            "import sys; sys.stderr.write('warning\\n' * 20000); sys.stderr.write('ImportError: no sentence_transformers'); sys.exit(1)",
+        );
+        let error = run_script(command, vec![b' '; 1 << 20]).unwrap_err();
+        assert!(error.to_string().contains("ImportError: no sentence_transformers"), "{}", error);
+
+        let mut command = Command::new("python3");
+        command.arg("-c").arg("import sys; print(len(sys.stdin.read()))");
+        assert_eq!(run_script(command, vec![b' '; 1 << 20]).unwrap(), b"1048576\n");
+    }
+
+    /// Serve `responses` in order, one HTTP connection each, returning the raw requests
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for body in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buffer = vec![0u8; 65536];
+                let mut request = String::new();
+                // Read headers, then as much body as Content-Length announces
+                loop {
+                    let n = stream.read(&mut buffer).unwrap();
+                    request.push_str(&String::from_utf8_lossy(&buffer[..n]));
+                    if let Some(header_end) = request.find("\r\n\r\n") {
+                        let content_length = request.lines()
+                            .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        if request.len() >= header_end + 4 + content_length {
+                            break;
+                        }
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn test_http_embedder_orders_by_index() {
+        let (url, server) = serve(vec![
+            r#"{"data": [{"embedding": [0.5, 0.5, 0.0], "index": 0}]}"#,
+            r#"{"data": [{"embedding": [0.0, 1.0, 0.0], "index": 1}, {"embedding": [1.0, 0.0, 0.0], "index": 0}]}"#,
+        ]);
+
+        let embedder = HttpEmbedder::new(&url, "text-embedding-3-small", Some("secret")).unwrap();
+        assert_eq!(embedder.dimension(), 3);
+        assert_eq!(embedder.model_id(), "text-embedding-3-small");
+
+        let batch = embedder.encode_batch(&["first".to_string(), "second".to_string()]).unwrap();
+        assert_eq!(batch, vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0]]);
+
+        let requests = server.join().unwrap();
+        assert!(requests[1].starts_with("POST /v1/embeddings"));
+        assert!(requests[1].contains("Bearer secret"));
+        let body: serde_json::Value = serde_json::from_str(requests[1].split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["model"], "text-embedding-3-small");
+        assert_eq!(body["input"], serde_json::json!(["first", "second"]));
+    }
+
+    #[tokio::test]
+    async fn test_http_embedder_inside_async_runtime() {
+        let (url, server) = serve(vec![r#"{"data": [{"embedding": [1.0, 0.0], "index": 0}]}"#]);
+
+        let embedder = HttpEmbedder::new(&url, "text-embedding-3-small", None).unwrap();
+        assert_eq!(embedder.dimension(), 2);
+        drop(embedder);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_check_batch_length_mismatch() {
+        let texts = vec!["a".to_string(), "b".to_string()];
+        assert!(check_batch(&texts, &[vec![1.0]]).is_err());
+        assert!(check_batch(&texts, &[vec![1.0], vec![2.0]]).is_ok());
+    }
+}
@@ -0,0 +1,871 @@
+// src/embedding_store.rs
+
+//! Binary, memory-mapped embedding store
+//!
+//! Embeddings live in `embeddings.bin` next to the Tantivy index. Chunk text is
+//! not duplicated here; callers resolve ids against the index. Layout (all
+//! integers little-endian):
+//!
+//! | offset | field                                      |
+//! |--------|--------------------------------------------|
+//! | 0      | magic `BRAGEMB\0`                          |
+//! | 8      | format version (`u32`)                     |
+//! | 12     | dimension (`u32`)                          |
+//! | 16     | vector count (`u64`)                       |
+//! | 24     | quantization (`u8`), flags (`u8`), 2 spare |
+//! | 28     | matrix offset (`u64`)                      |
+//! | 36     | row parameter offset (`u64`, 0 if none)    |
+//! | 44     | full-precision offset (`u64`, 0 if none)   |
+//! | 52     | id table offset (`u64`)                    |
+//! | 60     | model id length (`u32`), then UTF-8 bytes  |
+//!
+//! Sections start at 64-byte aligned offsets so they can be used straight from
+//! the mapping:
+//! - matrix: one row per vector, `f32`s, `i8`s or sign bits packed in `u64`s
+//!   depending on the quantization
+//! - row parameters: `f32` `norm` per row for unquantized stores, `(scale,
+//!   norm)` pair per row for int8, absent for binary
+//! - full precision (optional): `f32` copy of a quantized matrix, read only
+//!   when rescoring the top candidates
+//! - id table: one `(u32 len, id, u32 len, text hash)` record per row
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"BRAGEMB\0";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 64;
+const SECTION_ALIGN: usize = 64;
+
+/// Flag bit: a full-precision matrix follows the quantized one
+const FLAG_FULL_PRECISION: u8 = 1;
+
+/// Quantized searches rescore this many times `top_k` candidates exactly
+const RESCORE_FACTOR: usize = 4;
+
+/// How stored vectors are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Quantization {
+    /// 32-bit floats
+    None,
+    /// One signed byte per dimension with a per-vector scale (4x smaller)
+    Int8,
+    /// One sign bit per dimension, compared by Hamming distance (32x smaller)
+    Binary,
+}
+
+impl Quantization {
+    fn code(self) -> u8 {
+        match self {
+            Quantization::None => 0,
+            Quantization::Int8 => 1,
+            Quantization::Binary => 2,
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(Quantization::None),
+            1 => Ok(Quantization::Int8),
+            2 => Ok(Quantization::Binary),
+            other => bail!("Unknown quantization code {}", other),
+        }
+    }
+
+    /// `f32` parameters stored per row alongside the matrix
+    fn row_params(self) -> usize {
+        match self {
+            Quantization::None => 1,
+            Quantization::Int8 => 2,
+            Quantization::Binary => 0,
+        }
+    }
+
+    /// Bytes per matrix row
+    fn row_bytes(self, dimension: usize) -> usize {
+        match self {
+            Quantization::None => dimension * 4,
+            Quantization::Int8 => dimension,
+            Quantization::Binary => dimension.div_ceil(64) * 8,
+        }
+    }
+}
+
+/// On-disk encoding chosen at `init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreFormat {
+    pub quantization: Quantization,
+    /// Keep an `f32` copy of quantized vectors for rescoring
+    pub full_precision: bool,
+}
+
+impl Default for StoreFormat {
+    fn default() -> Self {
+        Self { quantization: Quantization::None, full_precision: false }
+    }
+}
+
+/// Query prepared for comparison against stored rows
+pub struct QueryVector {
+    values: Vec<f32>,
+    norm: f32,
+    /// Sign bits, only filled for binary stores
+    bits: Vec<u64>,
+}
+
+/// Positions of the sections of a mapped store
+struct Layout {
+    matrix_offset: usize,
+    full_offset: Option<usize>,
+    row_bytes: usize,
+}
+
+/// Row storage: built in memory as `f32`s, or borrowed from a mapped file
+enum Vectors {
+    Owned(Vec<f32>),
+    Mapped { mmap: Mmap, layout: Layout },
+}
+
+/// Fixed-dimension embedding matrix keyed by chunk id
+pub struct EmbeddingStore {
+    model_id: String,
+    dimension: usize,
+    format: StoreFormat,
+    ids: Vec<String>,
+    text_hashes: Vec<String>,
+    rows: HashMap<String, usize>,
+    /// L2 norm of each row as stored (dequantized for int8; unused for binary)
+    norms: Vec<f32>,
+    /// Int8 dequantization factor of each row
+    scales: Vec<f32>,
+    vectors: Vectors,
+}
+
+impl EmbeddingStore {
+    /// Empty in-memory store for vectors produced by `model_id`
+    pub fn new(model_id: &str, dimension: usize) -> Self {
+        Self {
+            model_id: model_id.to_string(),
+            dimension,
+            format: StoreFormat::default(),
+            ids: Vec::new(),
+            text_hashes: Vec::new(),
+            rows: HashMap::new(),
+            norms: Vec::new(),
+            scales: Vec::new(),
+            vectors: Vectors::Owned(Vec::new()),
+        }
+    }
+
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Encoding of the vectors as loaded
+    pub fn format(&self) -> StoreFormat {
+        self.format
+    }
+
+    /// Whether [`EmbeddingStore::vector`] returns the original embeddings
+    /// rather than a lossy reconstruction
+    pub fn has_exact_vectors(&self) -> bool {
+        self.format.quantization == Quantization::None || self.format.full_precision
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Add or replace the embedding for `id`; `text_hash` identifies the embedded text
+    pub fn insert(&mut self, id: &str, text_hash: &str, embedding: &[f32]) -> Result<()> {
+        if embedding.len() != self.dimension {
+            bail!(
+                "Embedding for {} has {} dimensions, store expects {}",
+                id, embedding.len(), self.dimension
+            );
+        }
+
+        let dimension = self.dimension;
+        let row = self.rows.get(id).copied();
+        let matrix = self.owned_matrix();
+        match row {
+            Some(row) => {
+                matrix[row * dimension..(row + 1) * dimension].copy_from_slice(embedding);
+                self.norms[row] = norm(embedding);
+                self.text_hashes[row] = text_hash.to_string();
+            }
+            None => {
+                matrix.extend_from_slice(embedding);
+                self.norms.push(norm(embedding));
+                self.rows.insert(id.to_string(), self.ids.len());
+                self.ids.push(id.to_string());
+                self.text_hashes.push(text_hash.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Look up the stored embedding for a chunk id
+    pub fn embedding(&self, id: &str) -> Option<Cow<'_, [f32]>> {
+        self.rows.get(id).map(|&row| self.vector(row))
+    }
+
+    /// Hash of the text the stored embedding for `id` was computed from
+    pub fn text_hash(&self, id: &str) -> Option<&str> {
+        self.rows.get(id).map(|&row| self.text_hashes[row].as_str())
+    }
+
+    /// Chunk id stored in `row`
+    pub fn id(&self, row: usize) -> &str {
+        &self.ids[row]
+    }
+
+    /// Embedding stored in `row`, reconstructed from the quantized form if no
+    /// full-precision copy was kept
+    pub fn vector(&self, row: usize) -> Cow<'_, [f32]> {
+        if let Some(full) = self.full_row(row) {
+            return Cow::Borrowed(full);
+        }
+        match self.format.quantization {
+            Quantization::None => unreachable!("unquantized rows are always full precision"),
+            Quantization::Int8 => Cow::Owned(
+                self.int8_row(row).iter().map(|&q| q as f32 * self.scales[row]).collect(),
+            ),
+            Quantization::Binary => Cow::Owned(
+                (0..self.dimension)
+                    .map(|i| if bit(self.binary_row(row), i) { 1.0 } else { -1.0 })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Prepare `query` for repeated [`EmbeddingStore::similarity`] calls
+    pub fn prepare(&self, query: &[f32]) -> QueryVector {
+        let bits = if self.format.quantization == Quantization::Binary {
+            sign_bits(query)
+        } else {
+            Vec::new()
+        };
+        QueryVector { values: query.to_vec(), norm: norm(query), bits }
+    }
+
+    /// Similarity of `query` to `row` in the stored encoding: cosine for float
+    /// and int8 rows, `1 - 2 * hamming / dimension` for binary rows
+    pub fn similarity(&self, query: &QueryVector, row: usize) -> f32 {
+        match self.format.quantization {
+            Quantization::None => {
+                let full = self.full_row(row).expect("unquantized rows are always full precision");
+                cosine(dot(&query.values, full), query.norm, self.norms[row])
+            }
+            Quantization::Int8 => {
+                let dot: f32 = query.values.iter()
+                    .zip(self.int8_row(row))
+                    .map(|(x, &q)| x * q as f32)
+                    .sum();
+                cosine(dot * self.scales[row], query.norm, self.norms[row])
+            }
+            Quantization::Binary => {
+                let hamming: u32 = query.bits.iter()
+                    .zip(self.binary_row(row))
+                    .map(|(a, b)| (a ^ b).count_ones())
+                    .sum();
+                1.0 - 2.0 * hamming as f32 / self.dimension.max(1) as f32
+            }
+        }
+    }
+
+    /// How many candidates a search should collect before [`EmbeddingStore::rescore`]
+    pub fn candidate_count(&self, top_k: usize) -> usize {
+        if self.rescores() { top_k * RESCORE_FACTOR } else { top_k }
+    }
+
+    /// Sort `candidates` best first and keep `top_k`, recomputing exact cosine
+    /// similarity from the full-precision copy when the store has one
+    pub fn rescore(&self, query: &QueryVector, mut candidates: Vec<(usize, f32)>, top_k: usize) -> Vec<(usize, f32)> {
+        if self.rescores() {
+            for (row, similarity) in &mut candidates {
+                if let Some(full) = self.full_row(*row) {
+                    *similarity = cosine(dot(&query.values, full), query.norm, norm(full));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.truncate(top_k);
+        candidates
+    }
+
+    /// Scan every row; returns `(row, similarity)`, best first
+    pub fn search_rows(&self, query: &QueryVector, top_k: usize) -> Vec<(usize, f32)> {
+        let mut scored: Vec<(usize, f32)> = (0..self.len())
+            .map(|row| (row, self.similarity(query, row)))
+            .collect();
+
+        let candidates = self.candidate_count(top_k);
+        if scored.len() > candidates {
+            scored.select_nth_unstable_by(candidates, |a, b| b.1.total_cmp(&a.1));
+            scored.truncate(candidates);
+        }
+        self.rescore(query, scored, top_k)
+    }
+
+    /// Brute-force search by raw embedding; returns `(chunk id, similarity)`, best first
+    #[cfg(test)]
+    pub fn similarity_search(&self, query_embedding: &[f32], top_k: usize) -> Vec<(&str, f32)> {
+        self.search_rows(&self.prepare(query_embedding), top_k)
+            .into_iter()
+            .map(|(row, similarity)| (self.id(row), similarity))
+            .collect()
+    }
+
+    /// FNV-1a hash of the model, dimension and every row's id and text hash.
+    ///
+    /// Indexes derived from the store (e.g. HNSW graphs) record it so a stale
+    /// derivative is detected after the store is rebuilt.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        feed(self.model_id.as_bytes());
+        feed(&(self.dimension as u64).to_le_bytes());
+        for (id, text_hash) in self.ids.iter().zip(&self.text_hashes) {
+            feed(id.as_bytes());
+            feed(&[0]);
+            feed(text_hash.as_bytes());
+            feed(&[0]);
+        }
+        hash
+    }
+
+    /// Write the store to `path` in `format`, replacing any existing file atomically
+    pub fn save(&self, path: &Path, format: StoreFormat) -> Result<()> {
+        let count = self.len();
+        let model_id = self.model_id.as_bytes();
+        let keep_full = format.full_precision && format.quantization != Quantization::None;
+
+        let matrix_offset = (HEADER_LEN + model_id.len()).next_multiple_of(SECTION_ALIGN);
+        let mut end = matrix_offset + count * format.quantization.row_bytes(self.dimension);
+        let params_offset = (format.quantization.row_params() > 0).then(|| {
+            let offset = end.next_multiple_of(SECTION_ALIGN);
+            end = offset + count * format.quantization.row_params() * 4;
+            offset
+        });
+        let full_offset = keep_full.then(|| {
+            let offset = end.next_multiple_of(SECTION_ALIGN);
+            end = offset + count * self.dimension * 4;
+            offset
+        });
+        let table_offset = end.next_multiple_of(SECTION_ALIGN);
+
+        let tmp_path = path.with_extension("bin.tmp");
+        let file = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {:?}", tmp_path))?;
+        let mut out = PositionedWriter { out: BufWriter::new(file), pos: 0 };
+
+        out.write(MAGIC)?;
+        out.write(&FORMAT_VERSION.to_le_bytes())?;
+        out.write(&(self.dimension as u32).to_le_bytes())?;
+        out.write(&(count as u64).to_le_bytes())?;
+        out.write(&[format.quantization.code(), if keep_full { FLAG_FULL_PRECISION } else { 0 }, 0, 0])?;
+        for offset in [Some(matrix_offset), params_offset, full_offset, Some(table_offset)] {
+            out.write(&(offset.unwrap_or(0) as u64).to_le_bytes())?;
+        }
+        out.write(&(model_id.len() as u32).to_le_bytes())?;
+        out.write(model_id)?;
+
+        out.pad_to(matrix_offset)?;
+        let mut row_params = Vec::new();
+        for row in 0..count {
+            let vector = self.vector(row);
+            match format.quantization {
+                Quantization::None => {
+                    for value in vector.iter() {
+                        out.write(&value.to_le_bytes())?;
+                    }
+                    row_params.push(norm(&vector));
+                }
+                Quantization::Int8 => {
+                    let (quantized, scale) = quantize_int8(&vector);
+                    let dequantized: Vec<f32> = quantized.iter().map(|&q| q as f32 * scale).collect();
+                    out.write(bytemuck::cast_slice(&quantized))?;
+                    row_params.extend([scale, norm(&dequantized)]);
+                }
+                Quantization::Binary => {
+                    for word in sign_bits(&vector) {
+                        out.write(&word.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+
+        if let Some(offset) = params_offset {
+            out.pad_to(offset)?;
+            for param in row_params {
+                out.write(&param.to_le_bytes())?;
+            }
+        }
+
+        if let Some(offset) = full_offset {
+            out.pad_to(offset)?;
+            for row in 0..count {
+                for value in self.vector(row).iter() {
+                    out.write(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        out.pad_to(table_offset)?;
+        for (id, text_hash) in self.ids.iter().zip(&self.text_hashes) {
+            for field in [id, text_hash] {
+                out.write(&(field.len() as u32).to_le_bytes())?;
+                out.write(field.as_bytes())?;
+            }
+        }
+
+        out.out.into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|file| file.sync_all())
+            .context("Failed to write embedding store")?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to move embedding store into place at {:?}", path))?;
+
+        info!(
+            "Saved {} embeddings ({} dimensions, {:?} quantization{}) to {:?}",
+            count, self.dimension, format.quantization,
+            if keep_full { " with full-precision rescoring" } else { "" }, path
+        );
+        Ok(())
+    }
+
+    /// Memory-map a store written by [`EmbeddingStore::save`]
+    pub fn open(path: &Path) -> Result<Self> {
+        if cfg!(target_endian = "big") {
+            bail!("The embedding store format requires a little-endian host");
+        }
+
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open embedding store {:?}", path))?;
+        // SAFETY: stores are only ever replaced by rename, never modified in place
+        let mmap = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("Failed to map embedding store {:?}", path))?;
+
+        let mut reader = ByteReader { bytes: &mmap, pos: 0 };
+        if reader.take(8)? != MAGIC {
+            bail!("{:?} is not an embedding store", path);
+        }
+        let version = reader.u32()?;
+        let dimension = reader.u32()? as usize;
+        let count = reader.u64()? as usize;
+
+        if version != FORMAT_VERSION {
+            bail!("Unsupported embedding store version {} (expected {})", version, FORMAT_VERSION);
+        }
+        let quantization = Quantization::from_code(reader.u8()?)?;
+        let flags = reader.u8()?;
+        reader.take(2)?;
+        let mut offsets = [0usize; 4];
+        for offset in &mut offsets {
+            *offset = reader.u64()? as usize;
+        }
+        let [matrix_offset, params_offset, full_offset, table_offset] = offsets;
+        let format = StoreFormat { quantization, full_precision: flags & FLAG_FULL_PRECISION != 0 };
+        let params_offset = Some(params_offset).filter(|&offset| offset != 0);
+        let full_offset = Some(full_offset).filter(|&offset| offset != 0);
+        let model_id = reader.string()?;
+
+        // Sections must match the format, be aligned and end before the id table
+        let row_bytes = format.quantization.row_bytes(dimension);
+        let sections = [
+            Some((matrix_offset, row_bytes)),
+            params_offset.map(|offset| (offset, format.quantization.row_params() * 4)),
+            full_offset.map(|offset| (offset, dimension * 4)),
+        ];
+        let sections_valid = sections.into_iter().flatten().all(|(offset, bytes_per_row)| {
+            let end = count.checked_mul(bytes_per_row).and_then(|len| len.checked_add(offset));
+            offset.is_multiple_of(SECTION_ALIGN) && end.is_some_and(|end| end <= table_offset)
+        });
+        if !sections_valid
+            || (format.quantization.row_params() > 0) != params_offset.is_some()
+            || format.full_precision != full_offset.is_some()
+        {
+            bail!("Embedding store {:?} has an invalid layout", path);
+        }
+
+        let mut reader = ByteReader { bytes: &mmap, pos: table_offset };
+        let mut ids = Vec::with_capacity(count);
+        let mut text_hashes = Vec::with_capacity(count);
+        let mut rows = HashMap::with_capacity(count);
+        for row in 0..count {
+            let id = reader.string()?;
+            text_hashes.push(reader.string()?);
+            rows.insert(id.clone(), row);
+            ids.push(id);
+        }
+
+        let mut scales = Vec::new();
+        let mut norms = Vec::new();
+        if let Some(offset) = params_offset {
+            let mut reader = ByteReader { bytes: &mmap, pos: offset };
+            for _ in 0..count {
+                if format.quantization == Quantization::Int8 {
+                    scales.push(f32::from_bits(reader.u32()?));
+                }
+                norms.push(f32::from_bits(reader.u32()?));
+            }
+        }
+
+        // Validate alignment once so later row access cannot fail
+        let matrix = &mmap[matrix_offset..matrix_offset + count * row_bytes];
+        let aligned = match format.quantization {
+            Quantization::None => bytemuck::try_cast_slice::<u8, f32>(matrix).is_ok(),
+            Quantization::Int8 => true,
+            Quantization::Binary => bytemuck::try_cast_slice::<u8, u64>(matrix).is_ok(),
+        } && full_offset.is_none_or(|offset| {
+            bytemuck::try_cast_slice::<u8, f32>(&mmap[offset..offset + count * dimension * 4]).is_ok()
+        });
+        if !aligned {
+            bail!("Embedding store {:?} is misaligned", path);
+        }
+
+        debug!("Mapped {} embeddings ({:?}) from {:?}", count, format, path);
+        Ok(Self {
+            model_id,
+            dimension,
+            format,
+            ids,
+            text_hashes,
+            rows,
+            norms,
+            scales,
+            vectors: Vectors::Mapped {
+                mmap,
+                layout: Layout { matrix_offset, full_offset, row_bytes },
+            },
+        })
+    }
+
+    fn rescores(&self) -> bool {
+        self.format.quantization != Quantization::None && self.format.full_precision
+    }
+
+    /// Row of the `f32` matrix (unquantized stores) or of the full-precision copy
+    fn full_row(&self, row: usize) -> Option<&[f32]> {
+        let start = row * self.dimension;
+        match &self.vectors {
+            Vectors::Owned(matrix) => Some(&matrix[start..start + self.dimension]),
+            Vectors::Mapped { mmap, layout } => {
+                let offset = match self.format.quantization {
+                    Quantization::None => layout.matrix_offset,
+                    _ => layout.full_offset?,
+                };
+                let bytes = &mmap[offset + start * 4..offset + (start + self.dimension) * 4];
+                Some(bytemuck::cast_slice(bytes))
+            }
+        }
+    }
+
+    fn quantized_row(&self, row: usize) -> &[u8] {
+        match &self.vectors {
+            Vectors::Mapped { mmap, layout } => {
+                let start = layout.matrix_offset + row * layout.row_bytes;
+                &mmap[start..start + layout.row_bytes]
+            }
+            Vectors::Owned(_) => unreachable!("in-memory stores are unquantized"),
+        }
+    }
+
+    fn int8_row(&self, row: usize) -> &[i8] {
+        bytemuck::cast_slice(self.quantized_row(row))
+    }
+
+    fn binary_row(&self, row: usize) -> &[u64] {
+        bytemuck::cast_slice(self.quantized_row(row))
+    }
+
+    /// Switch to owned `f32` storage (decoding a mapped matrix) so rows can be modified
+    fn owned_matrix(&mut self) -> &mut Vec<f32> {
+        if let Vectors::Mapped { .. } = self.vectors {
+            let matrix: Vec<f32> = (0..self.len()).flat_map(|row| self.vector(row).into_owned()).collect();
+            self.norms = (0..self.len())
+                .map(|row| norm(&matrix[row * self.dimension..(row + 1) * self.dimension]))
+                .collect();
+            self.scales.clear();
+            self.format = StoreFormat::default();
+            self.vectors = Vectors::Owned(matrix);
+        }
+        match &mut self.vectors {
+            Vectors::Owned(matrix) => matrix,
+            Vectors::Mapped { .. } => unreachable!(),
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn norm(vector: &[f32]) -> f32 {
+    dot(vector, vector).sqrt()
+}
+
+fn cosine(dot: f32, norm_a: f32, norm_b: f32) -> f32 {
+    let denominator = norm_a * norm_b;
+    if denominator == 0.0 { 0.0 } else { dot / denominator }
+}
+
+/// Symmetric int8 quantization: `value ≈ q * scale` with `|q| <= 127`
+fn quantize_int8(vector: &[f32]) -> (Vec<i8>, f32) {
+    let max_abs = vector.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+    if max_abs == 0.0 {
+        return (vec![0; vector.len()], 0.0);
+    }
+    let scale = max_abs / 127.0;
+    let quantized = vector.iter()
+        .map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+        .collect();
+    (quantized, scale)
+}
+
+/// Pack positive components as set bits, 64 dimensions per word
+fn sign_bits(vector: &[f32]) -> Vec<u64> {
+    let mut words = vec![0u64; vector.len().div_ceil(64)];
+    for (i, &value) in vector.iter().enumerate() {
+        if value > 0.0 {
+            words[i / 64] |= 1 << (i % 64);
+        }
+    }
+    words
+}
+
+fn bit(words: &[u64], i: usize) -> bool {
+    words[i / 64] & (1 << (i % 64)) != 0
+}
+
+/// `Write` wrapper that tracks the offset so sections can be padded into place
+struct PositionedWriter<W: Write> {
+    out: W,
+    pos: usize,
+}
+
+impl<W: Write> PositionedWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.pos += bytes.len();
+        self.out.write_all(bytes)
+    }
+
+    fn pad_to(&mut self, offset: usize) -> std::io::Result<()> {
+        self.write(&vec![0u8; offset - self.pos])
+    }
+}
+
+/// Bounds-checked little-endian cursor over an index file
+pub(crate) struct ByteReader<'a> {
+    pub bytes: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .context("File is truncated")?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).context("File contains invalid UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample_store() -> EmbeddingStore {
+        let mut store = EmbeddingStore::new("test-model", 3);
+        store.insert("a", "hash-a", &[1.0, 0.0, 0.0]).unwrap();
+        store.insert("b", "hash-b", &[0.0, 1.0, 0.0]).unwrap();
+        store.insert("c", "hash-c", &[0.7, 0.7, 0.0]).unwrap();
+        store
+    }
+
+    /// 64 spread-out vectors of dimension 70 (more than one binary word)
+    fn wide_store() -> EmbeddingStore {
+        let mut store = EmbeddingStore::new("test-model", 70);
+        for i in 0..64 {
+            let vector: Vec<f32> = (0..70)
+                .map(|d| ((i * 7 + d * 13) % 17) as f32 - 8.0 + 0.25 * (i as f32).sin())
+                .collect();
+            store.insert(&format!("chunk-{}", i), "hash", &vector).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn test_save_and_open_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.bin");
+        sample_store().save(&path, StoreFormat::default()).unwrap();
+
+        let store = EmbeddingStore::open(&path).unwrap();
+        assert_eq!(store.model_id(), "test-model");
+        assert_eq!(store.dimension(), 3);
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.format(), StoreFormat::default());
+        assert_eq!(store.embedding("b").as_deref(), Some(&[0.0, 1.0, 0.0][..]));
+        assert_eq!(store.text_hash("c"), Some("hash-c"));
+        assert!(store.embedding("missing").is_none());
+
+        let results = store.similarity_search(&[1.0, 0.1, 0.0], 2);
+        assert_eq!(results[0].0, "a");
+        assert_eq!(results[1].0, "c");
+    }
+
+    #[test]
+    fn test_open_reads_stored_norms_without_scanning_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.bin");
+        let original = wide_store();
+        original.save(&path, StoreFormat::default()).unwrap();
+
+        // Zero the matrix: norms computed from the rows would all be 0
+        let mut bytes = fs::read(&path).unwrap();
+        let matrix_offset = u64::from_le_bytes(bytes[28..36].try_into().unwrap()) as usize;
+        bytes[matrix_offset..matrix_offset + original.len() * 70 * 4].fill(0);
+        fs::write(&path, &bytes).unwrap();
+
+        let store = EmbeddingStore::open(&path).unwrap();
+        assert_eq!(store.norms, original.norms);
+    }
+
+    #[test]
+    fn test_insert_replaces_rows_and_checks_dimension() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.bin");
+        sample_store().save(&path, StoreFormat::default()).unwrap();
+
+        let mut store = EmbeddingStore::open(&path).unwrap();
+        store.insert("a", "hash-a2", &[0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.embedding("a").as_deref(), Some(&[0.0, 0.0, 1.0][..]));
+        assert_eq!(store.text_hash("a"), Some("hash-a2"));
+        assert!(store.insert("d", "hash-d", &[1.0, 2.0]).is_err());
+    }
+
+    #[test]
+    fn test_open_rejects_corrupt_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.bin");
+
+        fs::write(&path, b"{\"not\": \"a store\"}").unwrap();
+        assert!(EmbeddingStore::open(&path).is_err());
+
+        sample_store().save(&path, StoreFormat::default()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(EmbeddingStore::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_quantized_stores_shrink_and_keep_ranking() {
+        let temp_dir = TempDir::new().unwrap();
+        let original = wide_store();
+        let query = original.vector(5).into_owned();
+        let expected: Vec<&str> = original.similarity_search(&query, 5).into_iter().map(|(id, _)| id).collect();
+
+        let mut sizes = HashMap::new();
+        for quantization in [Quantization::None, Quantization::Int8, Quantization::Binary] {
+            for full_precision in [false, true] {
+                let format = StoreFormat { quantization, full_precision };
+                let path = temp_dir.path().join(format!("{:?}-{}.bin", quantization, full_precision));
+                original.save(&path, format).unwrap();
+                sizes.insert((quantization, full_precision), fs::metadata(&path).unwrap().len());
+
+                let store = EmbeddingStore::open(&path).unwrap();
+                let expected_format = StoreFormat {
+                    quantization,
+                    full_precision: full_precision && quantization != Quantization::None,
+                };
+                assert_eq!(store.format(), expected_format);
+
+                let results = store.similarity_search(&query, 5);
+                assert_eq!(results[0].0, "chunk-5", "{:?}", format);
+                if store.has_exact_vectors() {
+                    // Exact vectors (or exact rescoring) reproduce the float ranking
+                    let ids: Vec<&str> = results.iter().map(|(id, _)| *id).collect();
+                    assert_eq!(ids, expected, "{:?}", format);
+                    assert_eq!(store.vector(5).as_ref(), query.as_slice());
+                }
+            }
+        }
+
+        let float_size = sizes[&(Quantization::None, false)];
+        assert!(sizes[&(Quantization::Int8, false)] * 3 < float_size);
+        // The id table is stored as-is, so binary stays short of the full 32x saving here
+        assert!(sizes[&(Quantization::Binary, false)] * 6 < float_size);
+        assert!(sizes[&(Quantization::Int8, true)] > float_size);
+    }
+
+    #[test]
+    fn test_int8_quantization_error_is_small() {
+        let vector = vec![0.5, -1.0, 0.25, 0.0, 0.125];
+        let (quantized, scale) = quantize_int8(&vector);
+        assert_eq!(quantized[1], -127);
+        for (q, x) in quantized.iter().zip(&vector) {
+            assert!((*q as f32 * scale - x).abs() <= scale / 2.0 + 1e-6);
+        }
+        assert_eq!(quantize_int8(&[0.0, 0.0]), (vec![0, 0], 0.0));
+    }
+
+    #[test]
+    fn test_binary_similarity_counts_matching_signs() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.bin");
+        let mut store = EmbeddingStore::new("test-model", 4);
+        store.insert("same", "h", &[1.0, -1.0, 1.0, -1.0]).unwrap();
+        store.insert("half", "h", &[1.0, 1.0, -1.0, -1.0]).unwrap();
+        store.insert("opposite", "h", &[-1.0, 1.0, -1.0, 1.0]).unwrap();
+        store.save(&path, StoreFormat { quantization: Quantization::Binary, full_precision: false }).unwrap();
+
+        let store = EmbeddingStore::open(&path).unwrap();
+        let query = store.prepare(&[0.3, -0.2, 0.9, -0.1]);
+        assert_eq!(store.similarity(&query, 0), 1.0);
+        assert_eq!(store.similarity(&query, 1), 0.0);
+        assert_eq!(store.similarity(&query, 2), -1.0);
+        assert_eq!(store.vector(1).as_ref(), &[1.0, 1.0, -1.0, -1.0]);
+        assert!(!store.has_exact_vectors());
+    }
+}
@@ -5,14 +5,22 @@
 //! This module handles text embeddings for semantic similarity search,
 //! integrating with the existing BM25 search for hybrid retrieval.
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::process::Command;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::{info, debug, warn};
-use crate::ingest::Chunk;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::embedder::Embedder;
+use crate::embedding_store::{EmbeddingStore, StoreFormat};
+use crate::hnsw::{Hnsw, HnswParams};
+use crate::ingest::{Changeset, Chunk, DocMetadata};
 
 /// Enhanced chunk with embedding capability
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +30,12 @@ pub struct EnhancedChunk {
     pub source: String,
     pub heading: Option<String>,
     pub position: usize,
+    #[serde(default)]
+    pub page: Option<usize>,
+    #[serde(default)]
+    pub alternate_sources: Vec<String>,
+    #[serde(default)]
+    pub metadata: DocMetadata,
     pub embedding: Option<Vec<f32>>,
 }
 
@@ -33,6 +47,9 @@ impl From<Chunk> for EnhancedChunk {
             source: chunk.source,
             heading: chunk.heading,
             position: chunk.position,
+            page: chunk.page,
+            alternate_sources: chunk.alternate_sources,
+            metadata: chunk.metadata,
             embedding: None,
         }
     }
@@ -46,6 +63,9 @@ impl From<&EnhancedChunk> for Chunk {
             source: enhanced.source.clone(),
             heading: enhanced.heading.clone(),
             position: enhanced.position,
+            page: enhanced.page,
+            alternate_sources: enhanced.alternate_sources.clone(),
+            metadata: enhanced.metadata.clone(),
         }
     }
 }
@@ -72,179 +92,135 @@ pub enum SearchStrategy {
     PureSemantic,
 }
 
-/// Simple embedding model using sentence-transformers via Python
-pub struct EmbeddingModel {
-    python_script: String,
+/// How BM25 and semantic candidate lists are merged into one ranking
+#[derive(Debug, Clone, Copy)]
+pub enum FusionMode {
+    /// Weighted sum of normalized scores, `alpha` being the BM25 weight
+    Linear,
+    /// Reciprocal Rank Fusion: sum of `weight / (k + rank)` over both lists
+    Rrf(RrfParams),
 }
 
-impl EmbeddingModel {
-    pub fn new() -> Result<Self> {
-        let python_script = Self::create_embedding_script()?;
-        
-        // Test if the model works
-        Self::test_python_embedding(&python_script)?;
-        
-        Ok(Self { python_script })
-    }
-    
-    fn create_embedding_script() -> Result<String> {
-        let script = r#"
-import sys
-import json
-import numpy as np
-from sentence_transformers import SentenceTransformer
+/// Parameters for Reciprocal Rank Fusion
+#[derive(Debug, Clone, Copy)]
+pub struct RrfParams {
+    /// Rank constant; larger values flatten the gap between top ranks
+    pub k: f32,
+    pub bm25_weight: f32,
+    pub semantic_weight: f32,
+}
 
-# Load a lightweight model (384 dimensions)
-model = SentenceTransformer('all-MiniLM-L6-v2')
+/// How `init` lays out the vector side of the index
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VectorIndexOptions {
+    /// Encoding of the stored vectors, recorded in the store header
+    pub format: StoreFormat,
+    /// HNSW graph parameters; `None` leaves semantic search on the exact scan
+    pub hnsw: Option<HnswParams>,
+}
 
-def encode_text(text):
-    """Encode text to embedding vector"""
-    embedding = model.encode([text])
-    return embedding[0].tolist()
+/// File name of the embedding store inside the index directory
+const STORE_FILE: &str = "embeddings.bin";
 
-if __name__ == "__main__":
-    if len(sys.argv) != 2:
-        print("Usage: python embed.py <text>", file=sys.stderr)
-        sys.exit(1)
-    
-    text = sys.argv[1]
-    try:
-        embedding = encode_text(text)
-        print(json.dumps(embedding))
-    except Exception as e:
-        print(f"Error: {e}", file=sys.stderr)
-        sys.exit(1)
-"#;
-        
-        let script_path = "embed.py";
-        fs::write(script_path, script)
-            .context("Failed to write embedding script")?;
-        
-        Ok(script_path.to_string())
-    }
-    
-    fn test_python_embedding(script_path: &str) -> Result<()> {
-        info!("Testing Python embedding model...");
-        
-        let output = Command::new("python3")
-            .arg(script_path)
-            .arg("test text")
-            .output()
-            .context("Failed to execute Python embedding script. Make sure Python 3 and sentence-transformers are installed.")?;
-        
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow!(
-                "Python embedding script failed: {}. Try: pip install sentence-transformers", 
-                stderr
-            ));
-        }
-        
-        info!("✅ Python embedding model working correctly");
-        Ok(())
-    }
-    
-    pub fn encode(&self, text: &str) -> Result<Vec<f32>> {
-        debug!("Encoding text: {} chars", text.len());
-        
-        let output = Command::new("python3")
-            .arg(&self.python_script)
-            .arg(text)
-            .output()
-            .context("Failed to execute embedding script")?;
-        
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow!("Embedding failed: {}", stderr));
-        }
-        
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let embedding: Vec<f32> = serde_json::from_str(&stdout)
-            .context("Failed to parse embedding JSON")?;
-        
-        debug!("Generated embedding with {} dimensions", embedding.len());
-        Ok(embedding)
-    }
-    
-    pub fn _dimension(&self) -> usize {
-        384 // all-MiniLM-L6-v2 dimension
-    }
+/// JSON store written by earlier versions; converted on the next `init`
+const LEGACY_STORE_FILE: &str = "embeddings.json";
+
+/// File name of the HNSW graph built over the embedding store
+const ANN_INDEX_FILE: &str = "embeddings.hnsw";
+
+/// Location of the embedding store for an index directory
+pub fn store_path(index_dir: &Path) -> PathBuf {
+    index_dir.join(STORE_FILE)
 }
 
-/// In-memory embedding store
-pub struct EmbeddingStore {
-    embeddings: HashMap<String, Vec<f32>>,
-    chunks: HashMap<String, EnhancedChunk>,
+/// Location of the HNSW graph for an index directory
+pub fn ann_index_path(index_dir: &Path) -> PathBuf {
+    index_dir.join(ANN_INDEX_FILE)
 }
 
-impl EmbeddingStore {
-    pub fn new() -> Self {
-        Self {
-            embeddings: HashMap::new(),
-            chunks: HashMap::new(),
+/// Build (or keep, if still current) the HNSW graph for `store`.
+///
+/// With `params` of `None` any existing graph is removed so queries fall back
+/// to the exact scan.
+fn update_ann_index(index_dir: &Path, store: &EmbeddingStore, params: Option<HnswParams>) -> Result<()> {
+    let path = ann_index_path(index_dir);
+    let Some(params) = params else {
+        if path.exists() {
+            fs::remove_file(&path).context("Failed to remove HNSW index")?;
         }
-    }
+        return Ok(());
+    };
     
-    pub fn add_chunk(&mut self, chunk: EnhancedChunk) {
-        if let Some(embedding) = &chunk.embedding {
-            self.embeddings.insert(chunk.id.clone(), embedding.clone());
+    if let Ok(existing) = Hnsw::load(&path) {
+        if existing.matches(store) && existing.params() == params {
+            info!("HNSW index is up to date");
+            return Ok(());
         }
-        self.chunks.insert(chunk.id.clone(), chunk);
     }
     
-    pub fn similarity_search(&self, query_embedding: &[f32], top_k: usize) -> Vec<(EnhancedChunk, f32)> {
-        let mut scored_chunks = Vec::new();
-        
-        for (chunk_id, chunk_embedding) in &self.embeddings {
-            let score = cosine_similarity(query_embedding, chunk_embedding);
-            if let Some(chunk) = self.chunks.get(chunk_id) {
-                scored_chunks.push((chunk.clone(), score));
-            }
-        }
-        
-        scored_chunks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-        scored_chunks.truncate(top_k);
-        
-        scored_chunks
-    }
+    Hnsw::build(store, params).save(&path)
+}
+
+/// Embeddings from the previous build that can be reused for `model_id`.
+///
+/// Falls back to importing a legacy `embeddings.json`. Stores made by another
+/// model or with another dimension are ignored so everything is re-embedded,
+/// as are lossy (quantized without full precision) stores unless `format`
+/// uses the same quantization, since re-encoding those is lossless.
+fn load_previous_store(index_dir: &Path, model_id: &str, dimension: usize, format: StoreFormat) -> Option<EmbeddingStore> {
+    let path = store_path(index_dir);
+    let legacy_path = index_dir.join(LEGACY_STORE_FILE);
     
-    pub fn save_to_disk(&self, path: &Path) -> Result<()> {
-        let data = serde_json::to_string_pretty(&self.chunks)
-            .context("Failed to serialize chunks")?;
-        
-        fs::write(path, data)
-            .context("Failed to write embeddings to disk")?;
-        
-        info!("Saved {} chunks with embeddings to disk", self.chunks.len());
-        Ok(())
-    }
+    let store = if path.exists() {
+        EmbeddingStore::open(&path)
+    } else if legacy_path.exists() {
+        info!("Converting legacy {} to {}", LEGACY_STORE_FILE, STORE_FILE);
+        import_legacy_store(&legacy_path, model_id, dimension)
+    } else {
+        return None;
+    };
     
-    pub fn load_from_disk(path: &Path) -> Result<Self> {
-        if !path.exists() {
-            return Ok(Self::new());
+    match store {
+        Ok(store) if !store.has_exact_vectors() && store.format().quantization != format.quantization => {
+            info!(
+                "Stored embeddings are {:?}-quantized without full precision; re-embedding all chunks",
+                store.format().quantization
+            );
+            None
         }
-        
-        let data = fs::read_to_string(path)
-            .context("Failed to read embeddings file")?;
-        
-        let chunks: HashMap<String, EnhancedChunk> = serde_json::from_str(&data)
-            .context("Failed to parse embeddings JSON")?;
-        
-        let mut store = Self::new();
-        let mut embedding_count = 0;
-        
-        for chunk in chunks.into_values() {
-            if chunk.embedding.is_some() {
-                embedding_count += 1;
-            }
-            store.add_chunk(chunk);
+        Ok(store) if store.model_id() == model_id && store.dimension() == dimension => Some(store),
+        Ok(store) => {
+            info!(
+                "Stored embeddings come from {} ({} dimensions); re-embedding all chunks with {}",
+                store.model_id(), store.dimension(), model_id
+            );
+            None
+        }
+        Err(e) => {
+            warn!("Ignoring unreadable embedding store: {:#}", e);
+            None
         }
-        
-        info!("Loaded {} chunks ({} with embeddings) from disk", store.chunks.len(), embedding_count);
-        Ok(store)
     }
 }
 
+/// Read a JSON store from before the binary format, keyed by chunk id
+fn import_legacy_store(path: &Path, model_id: &str, dimension: usize) -> Result<EmbeddingStore> {
+    let data = fs::read_to_string(path)
+        .context("Failed to read embeddings file")?;
+    let chunks: HashMap<String, EnhancedChunk> = serde_json::from_str(&data)
+        .context("Failed to parse embeddings JSON")?;
+    
+    // The legacy format did not record the model; assume the current one
+    let mut store = EmbeddingStore::new(model_id, dimension);
+    for chunk in chunks.values() {
+        if let Some(embedding) = chunk.embedding.as_deref().filter(|e| e.len() == dimension) {
+            let text_hash = crate::ingest::compute_checksum(embedding_text(&Chunk::from(chunk)));
+            store.insert(&chunk.id, &text_hash, embedding)?;
+        }
+    }
+    Ok(store)
+}
+
 /// Utility functions
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() {
@@ -262,9 +238,18 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     }
 }
 
-pub fn normalize_bm25_score(score: f32) -> f32 {
-    // Sigmoid normalization to [0,1]
-    1.0 / (1.0 + (-score / 5.0).exp())
+/// Min-max normalize scores to [0,1] across one candidate set.
+///
+/// BM25 scores are unbounded and query-dependent, so they are only comparable
+/// within a single result list. If every score is equal, all map to 1.0.
+pub fn min_max_normalize(scores: &[f32]) -> Vec<f32> {
+    let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+    
+    scores.iter()
+        .map(|&score| if range > f32::EPSILON { (score - min) / range } else { 1.0 })
+        .collect()
 }
 
 pub fn analyze_query(query: &str) -> SearchStrategy {
@@ -282,6 +267,12 @@ pub fn analyze_query(query: &str) -> SearchStrategy {
         return SearchStrategy::BM25Heavy { alpha: 0.8 };
     }
     
+    // Requests for examples want code and prose alike
+    if query_lower.split_whitespace().any(|word| matches!(word, "example" | "examples" | "sample" | "samples")) {
+        info!("Query asks for examples, using balanced approach");
+        return SearchStrategy::Balanced { alpha: 0.5 };
+    }
+    
     // Check for conceptual patterns
     if query_lower.starts_with("how") ||
        query_lower.starts_with("what") ||
@@ -310,113 +301,585 @@ pub fn analyze_query(query: &str) -> SearchStrategy {
     SearchStrategy::Balanced { alpha: 0.6 }
 }
 
-/// Enhanced indexing with embeddings
-pub async fn build_enhanced_index(
-    cli: &crate::cli::Cli,
-    chunks: &[Chunk],
-) -> Result<()> {
-    info!("🚀 Building enhanced index with embeddings...");
+/// Tuning for embedding generation during `init`
+#[derive(Debug, Clone)]
+pub struct EmbedOptions {
+    /// Texts sent to the backend per call
+    pub batch_size: usize,
+    /// Batches in flight at once
+    pub concurrency: usize,
+    /// Extra attempts for a failing batch before giving up on it
+    pub max_retries: usize,
+}
+
+impl Default for EmbedOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 32,
+            concurrency: 2,
+            max_retries: 3,
+        }
+    }
+}
+
+/// Text embedded for a chunk: heading and text combined for better context
+fn embedding_text(chunk: &Chunk) -> String {
+    if let Some(heading) = &chunk.heading {
+        format!("{}\n{}", heading, chunk.text)
+    } else {
+        chunk.text.clone()
+    }
+}
+
+/// One embedded chunk as recorded in the checkpoint file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CheckpointEntry {
+    id: String,
+    model: String,
+    text_hash: String,
+    embedding: Vec<f32>,
+}
+
+/// Append-only JSON-lines log of embeddings generated by an unfinished `init`.
+///
+/// Each completed batch is appended, so an interrupted run resumes from the
+/// last finished batch. The file is removed once the store is saved.
+struct EmbeddingCheckpoint {
+    path: std::path::PathBuf,
+    entries: HashMap<String, CheckpointEntry>,
+}
+
+impl EmbeddingCheckpoint {
+    /// Load an existing checkpoint, skipping a torn final line from a crash
+    fn load(path: &Path) -> Result<Self> {
+        let mut entries = HashMap::new();
+        
+        if path.exists() {
+            let content = fs::read_to_string(path)
+                .context("Failed to read embedding checkpoint")?;
+            for line in content.lines() {
+                match serde_json::from_str::<CheckpointEntry>(line) {
+                    Ok(entry) => {
+                        entries.insert(entry.id.clone(), entry);
+                    }
+                    Err(e) => warn!("Skipping unreadable checkpoint line: {}", e),
+                }
+            }
+            info!("Resuming from checkpoint with {} embeddings", entries.len());
+        }
+        
+        Ok(Self { path: path.to_path_buf(), entries })
+    }
     
-    // First, build the regular BM25 index
-    crate::indexer::build_index(cli, chunks)?;
+    /// Embedding recorded for `id`, if it was made by `model` from the same text
+    fn lookup(&self, id: &str, model: &str, text_hash: &str) -> Option<&Vec<f32>> {
+        self.entries.get(id)
+            .filter(|entry| entry.model == model && entry.text_hash == text_hash)
+            .map(|entry| &entry.embedding)
+    }
     
-    // Initialize embedding model
-    let embedding_model = EmbeddingModel::new()
-        .context("Failed to initialize embedding model")?;
+    fn append(&mut self, new_entries: Vec<CheckpointEntry>) -> Result<()> {
+        use std::io::Write;
+        
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Failed to open embedding checkpoint")?;
+        
+        let mut buffer = String::new();
+        for entry in &new_entries {
+            buffer.push_str(&serde_json::to_string(entry)?);
+            buffer.push('\n');
+        }
+        file.write_all(buffer.as_bytes())
+            .and_then(|_| file.sync_data())
+            .context("Failed to write embedding checkpoint")?;
+        
+        for entry in new_entries {
+            self.entries.insert(entry.id.clone(), entry);
+        }
+        Ok(())
+    }
     
-    // Load existing embeddings if available
-    let embeddings_path = cli.index_dir.join("embeddings.json");
-    let mut embedding_store = EmbeddingStore::load_from_disk(&embeddings_path)
-        .unwrap_or_else(|_| EmbeddingStore::new());
-    
-    // Generate embeddings for new/updated chunks
-    let mut enhanced_chunks = Vec::new();
-    let mut new_embeddings = 0;
-    
-    for chunk in chunks {
-        let mut enhanced_chunk = EnhancedChunk::from(chunk.clone());
-        
-        // Check if we already have an embedding for this chunk
-        if let Some(existing_chunk) = embedding_store.chunks.get(&chunk.id) {
-            if existing_chunk.text == chunk.text {
-                // Text hasn't changed, reuse existing embedding
-                enhanced_chunk.embedding = existing_chunk.embedding.clone();
-                debug!("Reusing embedding for chunk: {}", chunk.id);
-            }
+    fn remove(self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path).context("Failed to remove embedding checkpoint")?;
         }
+        Ok(())
+    }
+}
+
+/// A batch that still failed after all retries
+#[derive(Debug)]
+struct BatchFailure {
+    chunk_ids: Vec<String>,
+    attempts: usize,
+    error: String,
+}
+
+/// Outcome of [`generate_embeddings`]
+#[derive(Debug, Default)]
+struct EmbeddingReport {
+    embeddings: HashMap<String, Vec<f32>>,
+    batches: usize,
+    retried_batches: usize,
+    failures: Vec<BatchFailure>,
+}
+
+/// Embed `pending` (chunk id, text) pairs in batches with bounded concurrency.
+///
+/// Each batch runs on the blocking pool, is retried with exponential backoff,
+/// and is appended to `checkpoint` as soon as it completes.
+async fn generate_embeddings<E: Embedder + ?Sized + 'static>(
+    pending: Vec<(String, String)>,
+    embedding_model: Arc<E>,
+    options: &EmbedOptions,
+    checkpoint: &mut EmbeddingCheckpoint,
+) -> Result<EmbeddingReport> {
+    let mut report = EmbeddingReport::default();
+    if pending.is_empty() {
+        return Ok(report);
+    }
+    
+    let progress = ProgressBar::new(pending.len() as u64);
+    progress.set_style(
+        ProgressStyle::with_template("{spinner} [{elapsed_precise}] [{bar:40}] {pos}/{len} chunks ({per_sec}, ETA {eta})")
+            .map_err(|e| anyhow::anyhow!("Invalid progress template: {}", e))?
+            .progress_chars("=> "),
+    );
+    
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    
+    for batch in pending.chunks(options.batch_size.max(1)) {
+        let (ids, texts): (Vec<String>, Vec<String>) = batch.iter().cloned().unzip();
+        let semaphore = Arc::clone(&semaphore);
+        let embedding_model = Arc::clone(&embedding_model);
+        let max_retries = options.max_retries;
+        report.batches += 1;
         
-        // Generate new embedding if needed
-        if enhanced_chunk.embedding.is_none() {
-            info!("Generating embedding for: {}", chunk.id);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let texts = Arc::new(texts);
+            let mut attempts = 0;
             
-            // Combine heading and text for better context
-            let text_for_embedding = if let Some(heading) = &chunk.heading {
-                format!("{}\n{}", heading, chunk.text)
-            } else {
-                chunk.text.clone()
+            let result = loop {
+                attempts += 1;
+                let model = Arc::clone(&embedding_model);
+                let batch_texts = Arc::clone(&texts);
+                let result = tokio::task::spawn_blocking(move || model.encode_batch(&batch_texts))
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|r| r);
+                
+                match result {
+                    Ok(embeddings) => break Ok(embeddings),
+                    Err(e) if attempts <= max_retries => {
+                        let backoff = Duration::from_millis(250 * (1 << (attempts - 1).min(6)));
+                        debug!("Embedding batch failed (attempt {}), retrying in {:?}: {}", attempts, backoff, e);
+                        tokio::time::sleep(backoff).await;
+                    }
+                    Err(e) => break Err(e),
+                }
             };
             
-            match embedding_model.encode(&text_for_embedding) {
-                Ok(embedding) => {
-                    enhanced_chunk.embedding = Some(embedding);
-                    new_embeddings += 1;
-                }
-                Err(e) => {
-                    warn!("Failed to generate embedding for {}: {}", chunk.id, e);
+            (ids, attempts, result)
+        });
+    }
+    
+    let model_id = embedding_model.model_id().to_string();
+    let text_hashes: HashMap<&str, String> = pending.iter()
+        .map(|(id, text)| (id.as_str(), crate::ingest::compute_checksum(text)))
+        .collect();
+    
+    while let Some(joined) = tasks.join_next().await {
+        let (ids, attempts, result) = joined.context("Embedding task panicked")?;
+        if attempts > 1 {
+            report.retried_batches += 1;
+        }
+        
+        match result {
+            Ok(embeddings) => {
+                let entries = ids.iter().zip(embeddings).map(|(id, embedding)| CheckpointEntry {
+                    id: id.clone(),
+                    model: model_id.clone(),
+                    text_hash: text_hashes[id.as_str()].clone(),
+                    embedding,
+                }).collect::<Vec<_>>();
+                checkpoint.append(entries.clone())?;
+                for entry in entries {
+                    report.embeddings.insert(entry.id, entry.embedding);
                 }
             }
+            Err(e) => {
+                report.failures.push(BatchFailure { chunk_ids: ids.clone(), attempts, error: e.to_string() });
+            }
         }
+        progress.inc(ids.len() as u64);
+    }
+    
+    progress.finish_and_clear();
+    Ok(report)
+}
+
+/// Enhanced indexing with embeddings
+pub async fn build_enhanced_index<E: Embedder + ?Sized + 'static>(
+    cli: &crate::cli::Cli,
+    changeset: &Changeset,
+    embedding_model: Arc<E>,
+    options: &EmbedOptions,
+    vector_options: VectorIndexOptions,
+) -> Result<()> {
+    info!("🚀 Building enhanced index with embeddings...");
+    
+    // First, apply the changeset to the regular BM25 index
+    crate::indexer::build_index(cli, changeset)?;
+    
+    // Embeddings cover every indexed chunk, including those of unchanged files
+    let chunks = if cli.index_dir.join("meta.json").exists() {
+        let index = crate::indexer::open_index(cli)?;
+        crate::retriever::all_chunks(&crate::retriever::Index::new(index.tantivy_index)?)?
+    } else {
+        Vec::new()
+    };
+    
+    // Load existing embeddings if available
+    let model_id = embedding_model.model_id().to_string();
+    let previous = load_previous_store(&cli.index_dir, &model_id, embedding_model.dimension(), vector_options.format);
+    let mut checkpoint = EmbeddingCheckpoint::load(&cli.index_dir.join("embeddings.checkpoint.jsonl"))?;
+    
+    // Reuse embeddings from the store or an interrupted run; queue the rest
+    let mut embedding_store = EmbeddingStore::new(&model_id, embedding_model.dimension());
+    let mut pending = Vec::new();
+    let mut pending_hashes = HashMap::new();
+    let mut resumed = 0;
+    
+    for chunk in &chunks {
+        let text = embedding_text(chunk);
+        let text_hash = crate::ingest::compute_checksum(&text);
+        
+        // Check if we already have an embedding for this exact text
+        let existing = previous.as_ref()
+            .filter(|store| store.text_hash(&chunk.id) == Some(text_hash.as_str()))
+            .and_then(|store| store.embedding(&chunk.id));
         
-        enhanced_chunks.push(enhanced_chunk);
+        if let Some(embedding) = existing {
+            debug!("Reusing embedding for chunk: {}", chunk.id);
+            embedding_store.insert(&chunk.id, &text_hash, &embedding)?;
+        } else if let Some(embedding) = checkpoint.lookup(&chunk.id, &model_id, &text_hash) {
+            embedding_store.insert(&chunk.id, &text_hash, embedding)?;
+            resumed += 1;
+        } else {
+            pending.push((chunk.id.clone(), text));
+            pending_hashes.insert(chunk.id.clone(), text_hash);
+        }
     }
+    drop(previous);
+    
+    info!(
+        "Embedding {} chunks in batches of {} ({} concurrent, {} resumed from checkpoint)",
+        pending.len(), options.batch_size, options.concurrency, resumed
+    );
+    let started = Instant::now();
+    let report = generate_embeddings(pending, embedding_model, options, &mut checkpoint).await?;
+    let generated = report.embeddings.len();
     
     // Update embedding store
-    for chunk in enhanced_chunks {
-        embedding_store.add_chunk(chunk);
+    for (id, embedding) in &report.embeddings {
+        embedding_store.insert(id, &pending_hashes[id], embedding)?;
     }
     
-    // Save embeddings to disk
-    embedding_store.save_to_disk(&embeddings_path)?;
+    // Save embeddings to disk; the checkpoint and any legacy store are only needed until then
+    embedding_store.save(&store_path(&cli.index_dir), vector_options.format)?;
+    checkpoint.remove()?;
+    let legacy_path = cli.index_dir.join(LEGACY_STORE_FILE);
+    if legacy_path.exists() {
+        fs::remove_file(&legacy_path).context("Failed to remove legacy embeddings.json")?;
+    }
+    update_ann_index(&cli.index_dir, &embedding_store, vector_options.hnsw)?;
+    
+    // Summary
+    let failed_chunks: usize = report.failures.iter().map(|f| f.chunk_ids.len()).sum();
+    info!(
+        "Embedding summary: {} batches in {:.1}s, {} new embeddings, {} resumed, {} batches needed retries, {} batches failed",
+        report.batches,
+        started.elapsed().as_secs_f64(),
+        generated,
+        resumed,
+        report.retried_batches,
+        report.failures.len()
+    );
+    for failure in &report.failures {
+        warn!(
+            "Failed to embed {} chunks after {} attempts ({}…): {}",
+            failure.chunk_ids.len(),
+            failure.attempts,
+            failure.chunk_ids.first().map(String::as_str).unwrap_or(""),
+            failure.error
+        );
+    }
     
-    info!("✅ Enhanced index built! Generated {} new embeddings", new_embeddings);
+    if !report.failures.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} chunks in {} batches could not be embedded; re-run init to retry them",
+            failed_chunks,
+            report.failures.len()
+        ));
+    }
+    
+    info!("✅ Enhanced index built!");
     Ok(())
 }
 
+/// Blend BM25 candidates with semantic similarity using `alpha` weighting.
+///
+/// BM25 candidates are joined with the embedding store by chunk id so lexical
+/// hits also get a semantic score; the store's nearest neighbours
+/// (`semantic_candidates`) are added for diversity. Returns the top-k results
+/// by combined score.
+fn linear_fusion(
+    embedding_store: &EmbeddingStore,
+    bm25_candidates: Vec<crate::retriever::ScoredChunk>,
+    semantic_candidates: Vec<(EnhancedChunk, f32)>,
+    query_embedding: &[f32],
+    top_k: usize,
+    alpha: f32,
+) -> Vec<SearchResult> {
+    // Step 3: Score all BM25 candidates with semantic similarity
+    let raw_bm25: Vec<f32> = bm25_candidates.iter().map(|c| c.score).collect();
+    let norm_bm25_scores = min_max_normalize(&raw_bm25);
+    let mut hybrid_results = Vec::new();
+    
+    for (candidate, norm_bm25) in bm25_candidates.into_iter().zip(norm_bm25_scores) {
+        let bm25_score = candidate.score;
+        
+        // Attach the stored embedding (if any) and get semantic score
+        let mut enhanced_chunk = EnhancedChunk::from(candidate.chunk);
+        enhanced_chunk.embedding = embedding_store.embedding(&enhanced_chunk.id).map(Cow::into_owned);
+        let semantic_score = match &enhanced_chunk.embedding {
+            Some(embedding) => cosine_similarity(query_embedding, embedding),
+            None => {
+                debug!("No stored embedding for BM25 candidate {}", enhanced_chunk.id);
+                0.0
+            }
+        };
+        
+        // Combine scores
+        let norm_semantic = (semantic_score + 1.0) / 2.0; // Normalize [-1,1] to [0,1]
+        let combined_score = alpha * norm_bm25 + (1.0 - alpha) * norm_semantic;
+        
+        let explanation = format!(
+            "BM25: {:.3} (norm: {:.3}), Semantic: {:.3} (norm: {:.3}), α={:.1}",
+            bm25_score, norm_bm25, semantic_score, norm_semantic, alpha
+        );
+        
+        hybrid_results.push(SearchResult {
+            chunk: enhanced_chunk,
+            bm25_score,
+            semantic_score,
+            combined_score,
+            explanation,
+        });
+    }
+    
+    // Step 4: Also add the pure semantic results for diversity
+    for (chunk, semantic_score) in semantic_candidates {
+        // Skip if already in BM25 results
+        if hybrid_results.iter().any(|r| r.chunk.id == chunk.id) {
+            continue;
+        }
+        
+        let norm_semantic = (semantic_score + 1.0) / 2.0;
+        let combined_score = (1.0 - alpha) * norm_semantic; // No BM25 component
+        
+        let explanation = format!(
+            "Semantic-only: {:.3} (norm: {:.3}), α={:.1}",
+            semantic_score, norm_semantic, alpha
+        );
+        
+        hybrid_results.push(SearchResult {
+            chunk,
+            bm25_score: 0.0,
+            semantic_score,
+            combined_score,
+            explanation,
+        });
+    }
+    
+    // Step 5: Sort by combined score and take top-k
+    hybrid_results.sort_by(|a, b| b.combined_score.partial_cmp(&a.combined_score).unwrap());
+    hybrid_results.truncate(top_k);
+    
+    // Log results for debugging
+    for (i, result) in hybrid_results.iter().enumerate() {
+        debug!("Result {}: {:.3} - {} ({})", 
+               i + 1, result.combined_score, result.chunk.id, result.explanation);
+    }
+    
+    hybrid_results
+}
+
+/// Merge BM25 and semantic candidate lists by Reciprocal Rank Fusion.
+///
+/// Each chunk scores `Σ weight / (k + rank)` over the lists it appears in
+/// (ranks are 1-based), so only positions matter and the BM25/cosine scale
+/// mismatch drops out. Raw scores are still reported for inspection.
+fn rrf_fusion(
+    embedding_store: &EmbeddingStore,
+    bm25_candidates: Vec<crate::retriever::ScoredChunk>,
+    semantic_candidates: Vec<(EnhancedChunk, f32)>,
+    query_embedding: &[f32],
+    top_k: usize,
+    params: RrfParams,
+) -> Vec<SearchResult> {
+    struct Entry {
+        chunk: EnhancedChunk,
+        bm25: Option<(usize, f32)>,
+        semantic: Option<(usize, f32)>,
+    }
+    
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+    
+    for (rank, candidate) in bm25_candidates.into_iter().enumerate() {
+        let mut chunk = EnhancedChunk::from(candidate.chunk);
+        chunk.embedding = embedding_store.embedding(&chunk.id).map(Cow::into_owned);
+        by_id.insert(chunk.id.clone(), entries.len());
+        entries.push(Entry { chunk, bm25: Some((rank + 1, candidate.score)), semantic: None });
+    }
+    
+    for (rank, (chunk, score)) in semantic_candidates.into_iter().enumerate() {
+        match by_id.get(&chunk.id) {
+            Some(&i) => entries[i].semantic = Some((rank + 1, score)),
+            None => {
+                by_id.insert(chunk.id.clone(), entries.len());
+                entries.push(Entry { chunk, bm25: None, semantic: Some((rank + 1, score)) });
+            }
+        }
+    }
+    
+    let format_rank = |list: Option<(usize, f32)>| match list {
+        Some((rank, _)) => rank.to_string(),
+        None => "-".to_string(),
+    };
+    
+    let mut results: Vec<SearchResult> = entries.into_iter().map(|entry| {
+        let rrf = |weight: f32, list: Option<(usize, f32)>| {
+            list.map_or(0.0, |(rank, _)| weight / (params.k + rank as f32))
+        };
+        let combined_score = rrf(params.bm25_weight, entry.bm25) + rrf(params.semantic_weight, entry.semantic);
+        
+        // Report a semantic score for BM25-only hits too, when an embedding is stored
+        let semantic_score = entry.semantic.map(|(_, score)| score).unwrap_or_else(|| {
+            entry.chunk.embedding.as_deref()
+                .map_or(0.0, |embedding| cosine_similarity(query_embedding, embedding))
+        });
+        
+        let explanation = format!(
+            "RRF: {:.4} (BM25 rank: {}, Semantic rank: {}, k={})",
+            combined_score, format_rank(entry.bm25), format_rank(entry.semantic), params.k
+        );
+        
+        SearchResult {
+            chunk: entry.chunk,
+            bm25_score: entry.bm25.map_or(0.0, |(_, score)| score),
+            semantic_score,
+            combined_score,
+            explanation,
+        }
+    }).collect();
+    
+    results.sort_by(|a, b| b.combined_score.partial_cmp(&a.combined_score).unwrap());
+    results.truncate(top_k);
+    
+    for (i, result) in results.iter().enumerate() {
+        debug!("Result {}: {:.4} - {} ({})", 
+               i + 1, result.combined_score, result.chunk.id, result.explanation);
+    }
+    
+    results
+}
+
 // Updated retriever.rs modifications
 
 /// Hybrid searcher that combines BM25 and semantic search
-pub struct HybridSearcher {
+pub struct HybridSearcher<E: Embedder> {
     pub bm25_index: crate::retriever::Index,
     pub embedding_store: EmbeddingStore,
-    pub embedding_model: EmbeddingModel,
+    pub embedding_model: E,
+    /// HNSW graph for approximate search; `None` scans every embedding
+    pub ann_index: Option<Hnsw>,
+    /// Candidate list size for HNSW queries
+    pub ef_search: usize,
 }
 
-impl HybridSearcher {
-    pub fn new(cli: &crate::cli::Cli) -> Result<Self> {
+impl<E: Embedder> HybridSearcher<E> {
+    pub fn new(cli: &crate::cli::Cli, embedding_model: E) -> Result<Self> {
         info!("Initializing hybrid searcher...");
         
         // Open BM25 index
         let bm25_index = crate::indexer::open_index(cli)?;
         let bm25_index = crate::retriever::Index::new(bm25_index.tantivy_index)?;
         
-        // Load embedding store
-        let embeddings_path = cli.index_dir.join("embeddings.json");
-        let embedding_store = EmbeddingStore::load_from_disk(&embeddings_path)
-            .context("Failed to load embeddings. Run 'build' command first.")?;
+        // Map embedding store
+        let store_path = store_path(&cli.index_dir);
+        if !store_path.exists() && cli.index_dir.join(LEGACY_STORE_FILE).exists() {
+            anyhow::bail!("Embeddings are in the old JSON format. Re-run 'init' to convert them.");
+        }
+        let embedding_store = EmbeddingStore::open(&store_path)
+            .context("Failed to load embeddings. Run 'init' command first.")?;
         
-        // Initialize embedding model
-        let embedding_model = EmbeddingModel::new()
-            .context("Failed to initialize embedding model")?;
+        if embedding_store.dimension() != embedding_model.dimension() {
+            anyhow::bail!(
+                "Index embeddings have {} dimensions but {} produces {}; query with the model used for 'init'",
+                embedding_store.dimension(), embedding_model.model_id(), embedding_model.dimension()
+            );
+        }
+        if embedding_store.model_id() != embedding_model.model_id() {
+            warn!(
+                "Index embeddings were built with {}, querying with {}",
+                embedding_store.model_id(), embedding_model.model_id()
+            );
+        }
+        if embedding_store.is_empty() {
+            warn!("Embedding store is empty; semantic search will return no results");
+        }
+        info!(
+            "Mapped {} embeddings from {:?} ({:?})",
+            embedding_store.len(), store_path, embedding_store.format()
+        );
+        
+        // Load the HNSW graph unless an exact scan was requested
+        let ann_path = ann_index_path(&cli.index_dir);
+        let ann_index = if cli.exact_search || !ann_path.exists() {
+            info!("Semantic search will scan all embeddings");
+            None
+        } else {
+            match Hnsw::load(&ann_path) {
+                Ok(graph) if graph.matches(&embedding_store) => Some(graph),
+                Ok(_) => {
+                    warn!("HNSW index is out of date with the embedding store; re-run 'init'. Using exact search");
+                    None
+                }
+                Err(e) => {
+                    warn!("Failed to load HNSW index, using exact search: {:#}", e);
+                    None
+                }
+            }
+        };
         
         info!("✅ Hybrid searcher initialized");
         Ok(Self {
             bm25_index,
             embedding_store,
             embedding_model,
+            ann_index,
+            ef_search: cli.ef_search,
         })
     }
     
-    pub fn hybrid_search(&self, query: &str, top_k: usize) -> Result<Vec<SearchResult>> {
+    pub fn hybrid_search(&self, query: &str, top_k: usize, fusion: FusionMode) -> Result<Vec<SearchResult>> {
         info!("🔍 Starting hybrid search for: '{}'", query);
         
         // Analyze query to determine strategy
@@ -428,112 +891,91 @@ impl HybridSearcher {
             SearchStrategy::BM25Heavy { alpha } |
             SearchStrategy::Balanced { alpha } |
             SearchStrategy::SemanticHeavy { alpha } => {
-                self.hybrid_search_with_alpha(query, top_k, alpha)
+                self.hybrid_search_with_alpha(query, top_k, alpha, fusion)
             }
         }
     }
     
-    pub fn hybrid_search_with_alpha(&self, query: &str, top_k: usize, alpha: f32) -> Result<Vec<SearchResult>> {
-        info!("Hybrid search with α={:.1} (BM25: {:.0}%, Semantic: {:.0}%)", 
-              alpha, alpha * 100.0, (1.0 - alpha) * 100.0);
+    /// Hybrid search; `alpha` only applies to [`FusionMode::Linear`]
+    pub fn hybrid_search_with_alpha(
+        &self,
+        query: &str,
+        top_k: usize,
+        alpha: f32,
+        fusion: FusionMode,
+    ) -> Result<Vec<SearchResult>> {
+        match fusion {
+            FusionMode::Linear => info!("Hybrid search with α={:.1} (BM25: {:.0}%, Semantic: {:.0}%)", 
+                                        alpha, alpha * 100.0, (1.0 - alpha) * 100.0),
+            FusionMode::Rrf(params) => info!(
+                "Hybrid search with RRF (k={}, BM25 weight: {:.2}, Semantic weight: {:.2})",
+                params.k, params.bm25_weight, params.semantic_weight
+            ),
+        }
         
         // Step 1: Get BM25 candidates (cast wider net)
-        let bm25_candidates = crate::retriever::bm25_search(&self.bm25_index, query, top_k * 3)?;
+        let bm25_candidates = crate::retriever::bm25_search_scored(&self.bm25_index, query, top_k * 3)?;
         
         // Step 2: Get query embedding
         let query_embedding = self.embedding_model.encode(query)?;
         
-        // Step 3: Score all BM25 candidates with semantic similarity
-        let mut hybrid_results = Vec::new();
-        
-        for chunk in bm25_candidates {
-            // Get BM25 score (we'll need to modify bm25_search to return scores)
-            let bm25_score = 1.0; // Placeholder - we'd need to modify the BM25 function
-            
-            // Convert to enhanced chunk and get semantic score
-            let enhanced_chunk = EnhancedChunk::from(chunk);
-            let semantic_score = if let Some(embedding) = &enhanced_chunk.embedding {
-                cosine_similarity(&query_embedding, embedding)
-            } else {
-                0.0
-            };
-            
-            // Combine scores
-            let norm_bm25 = normalize_bm25_score(bm25_score);
-            let norm_semantic = (semantic_score + 1.0) / 2.0; // Normalize [-1,1] to [0,1]
-            let combined_score = alpha * norm_bm25 + (1.0 - alpha) * norm_semantic;
-            
-            let explanation = format!(
-                "BM25: {:.3} (norm: {:.3}), Semantic: {:.3} (norm: {:.3}), α={:.1}",
-                bm25_score, norm_bm25, semantic_score, norm_semantic, alpha
-            );
-            
-            hybrid_results.push(SearchResult {
-                chunk: enhanced_chunk,
-                bm25_score,
-                semantic_score,
-                combined_score,
-                explanation,
-            });
-        }
-        
-        // Step 4: Also get some pure semantic results for diversity
-        let semantic_candidates = self.embedding_store.similarity_search(&query_embedding, top_k);
-        
-        for (chunk, semantic_score) in semantic_candidates {
-            // Skip if already in BM25 results
-            if hybrid_results.iter().any(|r| r.chunk.id == chunk.id) {
-                continue;
+        // Steps 3-5: Merge with semantic candidates and rank
+        let hybrid_results = match fusion {
+            FusionMode::Linear => {
+                let semantic_candidates = self.semantic_candidates(&query_embedding, top_k)?;
+                linear_fusion(&self.embedding_store, bm25_candidates, semantic_candidates, &query_embedding, top_k, alpha)
             }
-            
-            let norm_semantic = (semantic_score + 1.0) / 2.0;
-            let combined_score = (1.0 - alpha) * norm_semantic; // No BM25 component
-            
-            let explanation = format!(
-                "Semantic-only: {:.3} (norm: {:.3}), α={:.1}",
-                semantic_score, norm_semantic, alpha
-            );
-            
-            hybrid_results.push(SearchResult {
-                chunk,
-                bm25_score: 0.0,
-                semantic_score,
-                combined_score,
-                explanation,
-            });
-        }
-        
-        // Step 5: Sort by combined score and take top-k
-        hybrid_results.sort_by(|a, b| b.combined_score.partial_cmp(&a.combined_score).unwrap());
-        hybrid_results.truncate(top_k);
-        
-        // Log results for debugging
-        for (i, result) in hybrid_results.iter().enumerate() {
-            debug!("Result {}: {:.3} - {} ({})", 
-                   i + 1, result.combined_score, result.chunk.id, result.explanation);
-        }
+            FusionMode::Rrf(params) => {
+                let semantic_candidates = self.semantic_candidates(&query_embedding, top_k * 3)?;
+                rrf_fusion(&self.embedding_store, bm25_candidates, semantic_candidates, &query_embedding, top_k, params)
+            }
+        };
         
         info!("✅ Hybrid search returned {} results", hybrid_results.len());
         Ok(hybrid_results)
     }
     
+    /// Nearest neighbours from the embedding store, with chunk text loaded from the index
+    fn semantic_candidates(&self, query_embedding: &[f32], top_k: usize) -> Result<Vec<(EnhancedChunk, f32)>> {
+        let query = self.embedding_store.prepare(query_embedding);
+        let rows = match &self.ann_index {
+            Some(graph) => graph.search_and_rescore(&self.embedding_store, &query, top_k, self.ef_search),
+            None => self.embedding_store.search_rows(&query, top_k),
+        };
+        let hits: Vec<(&str, f32)> = rows.into_iter()
+            .map(|(row, score)| (self.embedding_store.id(row), score))
+            .collect();
+        let ids: Vec<&str> = hits.iter().map(|(id, _)| *id).collect();
+        let mut chunks = crate::retriever::fetch_chunks(&self.bm25_index, &ids)?;
+        
+        Ok(hits.into_iter().filter_map(|(id, score)| {
+            let Some(chunk) = chunks.remove(id) else {
+                warn!("Embedding for {} has no matching chunk in the index", id);
+                return None;
+            };
+            let mut enhanced_chunk = EnhancedChunk::from(chunk);
+            enhanced_chunk.embedding = self.embedding_store.embedding(id).map(Cow::into_owned);
+            Some((enhanced_chunk, score))
+        }).collect())
+    }
+    
     fn pure_bm25_search(&self, query: &str, top_k: usize) -> Result<Vec<SearchResult>> {
         info!("Using pure BM25 search");
-        let chunks = crate::retriever::bm25_search(&self.bm25_index, query, top_k)?;
+        let candidates = crate::retriever::bm25_search_scored(&self.bm25_index, query, top_k)?;
         
-        Ok(chunks.into_iter().map(|chunk| SearchResult {
-            chunk: EnhancedChunk::from(chunk),
-            bm25_score: 1.0,
+        Ok(candidates.into_iter().map(|candidate| SearchResult {
+            chunk: EnhancedChunk::from(candidate.chunk),
+            bm25_score: candidate.score,
             semantic_score: 0.0,
-            combined_score: 1.0,
-            explanation: "Pure BM25".to_string(),
+            combined_score: candidate.score,
+            explanation: format!("Pure BM25: {:.3}", candidate.score),
         }).collect())
     }
     
     pub fn pure_semantic_search(&self, query: &str, top_k: usize) -> Result<Vec<SearchResult>> {
         info!("Using pure semantic search");
         let query_embedding = self.embedding_model.encode(query)?;
-        let candidates = self.embedding_store.similarity_search(&query_embedding, top_k);
+        let candidates = self.semantic_candidates(&query_embedding, top_k)?;
         
         Ok(candidates.into_iter().map(|(chunk, score)| SearchResult {
             chunk,
@@ -548,6 +990,196 @@ impl HybridSearcher {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::retriever::ScoredChunk;
+    use std::io::Write;
+    
+    /// Deterministic stand-in for the sentence-transformers model: hashes each
+    /// word into one of a few buckets, so texts sharing words are similar.
+    struct FakeEmbeddingModel;
+    
+    impl FakeEmbeddingModel {
+        const DIMENSION: usize = 16;
+    }
+    
+    impl Embedder for FakeEmbeddingModel {
+        fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            use std::collections::hash_map::DefaultHasher;
+            use std::hash::{Hash, Hasher};
+            
+            Ok(texts.iter().map(|text| {
+                let mut embedding = vec![0.0; Self::DIMENSION];
+                for word in text.split_whitespace() {
+                    let mut hasher = DefaultHasher::new();
+                    word.to_lowercase().hash(&mut hasher);
+                    embedding[hasher.finish() as usize % Self::DIMENSION] += 1.0;
+                }
+                embedding
+            }).collect())
+        }
+        
+        fn dimension(&self) -> usize {
+            Self::DIMENSION
+        }
+        
+        fn model_id(&self) -> &str {
+            "fake"
+        }
+    }
+    
+    fn test_chunk(id: &str, text: &str) -> Chunk {
+        Chunk {
+            id: id.to_string(),
+            text: text.to_string(),
+            source: "test.md".to_string(),
+            heading: None,
+            position: 0,
+            page: None,
+            alternate_sources: Vec::new(),
+            metadata: DocMetadata::default(),
+        }
+    }
+    
+    /// Store every chunk with its fake embedding
+    fn build_store(model: &FakeEmbeddingModel, chunks: &[Chunk]) -> EmbeddingStore {
+        let mut store = EmbeddingStore::new(model.model_id(), model.dimension());
+        for chunk in chunks {
+            let embedding = model.encode(&chunk.text).unwrap();
+            store.insert(&chunk.id, &crate::ingest::compute_checksum(&chunk.text), &embedding).unwrap();
+        }
+        store
+    }
+    
+    /// The store's nearest neighbours, resolved against `chunks` instead of an index
+    fn nearest(store: &EmbeddingStore, chunks: &[Chunk], query_embedding: &[f32], top_k: usize) -> Vec<(EnhancedChunk, f32)> {
+        store.similarity_search(query_embedding, top_k).into_iter().map(|(id, score)| {
+            let chunk = chunks.iter().find(|c| c.id == id).unwrap();
+            (EnhancedChunk::from(chunk.clone()), score)
+        }).collect()
+    }
+    
+    #[test]
+    fn test_linear_fusion_joins_bm25_candidates_with_stored_embeddings() {
+        let model = FakeEmbeddingModel;
+        let both = test_chunk("both", "render sprites with the sprite batch renderer");
+        let lexical = test_chunk("lexical", "sprites appear in an unrelated changelog entry");
+        let semantic = test_chunk("semantic", "render with the batch renderer");
+        let chunks = [both.clone(), lexical.clone(), semantic.clone()];
+        let store = build_store(&model, &chunks);
+        
+        let bm25_candidates = vec![
+            ScoredChunk { chunk: both, score: 4.0 },
+            ScoredChunk { chunk: lexical, score: 2.0 },
+        ];
+        let query_embedding = model.encode("render sprites with the batch renderer").unwrap();
+        let semantic_candidates = nearest(&store, &chunks, &query_embedding, 3);
+        let alpha = 0.5;
+        
+        let results = linear_fusion(&store, bm25_candidates, semantic_candidates, &query_embedding, 3, alpha);
+        
+        let top = results.iter().find(|r| r.chunk.id == "both").unwrap();
+        assert!(top.bm25_score > 0.0);
+        assert!(top.semantic_score > 0.0);
+        assert!(top.chunk.embedding.is_some());
+        let expected = alpha * 1.0 + (1.0 - alpha) * (top.semantic_score + 1.0) / 2.0;
+        assert!((top.combined_score - expected).abs() < 1e-6);
+        assert_eq!(results[0].chunk.id, "both");
+        
+        // The semantic-only hit still shows up, with no BM25 component
+        let semantic_only = results.iter().find(|r| r.chunk.id == "semantic").unwrap();
+        assert_eq!(semantic_only.bm25_score, 0.0);
+        assert!(semantic_only.semantic_score > 0.0);
+        
+        // Every chunk appears once even though "both" is in both lists
+        assert_eq!(results.iter().filter(|r| r.chunk.id == "both").count(), 1);
+    }
+    
+    /// Fails the first `failures` calls, then delegates to [`FakeEmbeddingModel`]
+    struct FlakyEmbeddingModel {
+        failures: std::sync::atomic::AtomicUsize,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+    
+    impl FlakyEmbeddingModel {
+        fn new(failures: usize) -> Self {
+            Self { failures: failures.into(), calls: 0.into() }
+        }
+    }
+    
+    impl Embedder for FlakyEmbeddingModel {
+        fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            use std::sync::atomic::Ordering;
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                anyhow::bail!("backend unavailable");
+            }
+            FakeEmbeddingModel.encode_batch(texts)
+        }
+        
+        fn dimension(&self) -> usize {
+            FakeEmbeddingModel::DIMENSION
+        }
+        
+        fn model_id(&self) -> &str {
+            "fake"
+        }
+    }
+    
+    fn pending_texts(count: usize) -> Vec<(String, String)> {
+        (0..count).map(|i| (format!("chunk-{}", i), format!("text number {}", i))).collect()
+    }
+    
+    #[tokio::test]
+    async fn test_generate_embeddings_batches_and_retries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.checkpoint.jsonl");
+        let mut checkpoint = EmbeddingCheckpoint::load(&path).unwrap();
+        let model = Arc::new(FlakyEmbeddingModel::new(1));
+        let options = EmbedOptions { batch_size: 4, concurrency: 2, max_retries: 2 };
+        
+        let report = generate_embeddings(pending_texts(10), Arc::clone(&model), &options, &mut checkpoint)
+            .await
+            .unwrap();
+        
+        assert_eq!(report.batches, 3);
+        assert_eq!(report.retried_batches, 1);
+        assert!(report.failures.is_empty());
+        assert_eq!(report.embeddings.len(), 10);
+        assert_eq!(model.calls.load(std::sync::atomic::Ordering::SeqCst), 4);
+        assert_eq!(report.embeddings["chunk-3"], FakeEmbeddingModel.encode("text number 3").unwrap());
+    }
+    
+    #[tokio::test]
+    async fn test_failed_batches_are_reported_and_checkpoint_resumes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.checkpoint.jsonl");
+        let options = EmbedOptions { batch_size: 5, concurrency: 1, max_retries: 0 };
+        
+        // First run: one of the two batches fails and is not retried
+        let mut checkpoint = EmbeddingCheckpoint::load(&path).unwrap();
+        let report = generate_embeddings(pending_texts(10), Arc::new(FlakyEmbeddingModel::new(1)), &options, &mut checkpoint)
+            .await
+            .unwrap();
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].chunk_ids.len(), 5);
+        assert_eq!(report.embeddings.len(), 5);
+        
+        // A torn trailing line from a crash is ignored on reload
+        fs::OpenOptions::new().append(true).open(&path).unwrap()
+            .write_all(b"{\"id\":\"chunk-").unwrap();
+        let checkpoint = EmbeddingCheckpoint::load(&path).unwrap();
+        let done: Vec<_> = pending_texts(10).into_iter()
+            .filter(|(id, text)| checkpoint.lookup(id, "fake", &crate::ingest::compute_checksum(text)).is_some())
+            .collect();
+        assert_eq!(done.len(), 5);
+        
+        // Entries made from different text or by a different model are not reused
+        let (id, _) = &done[0];
+        assert!(checkpoint.lookup(id, "fake", &crate::ingest::compute_checksum("edited")).is_none());
+        assert!(checkpoint.lookup(id, "other-model", &crate::ingest::compute_checksum(&done[0].1)).is_none());
+        
+        checkpoint.remove().unwrap();
+        assert!(!path.exists());
+    }
     
     #[test]
     fn test_cosine_similarity() {
@@ -559,6 +1191,104 @@ mod tests {
         assert!((cosine_similarity(&a, &c) - 0.0).abs() < 1e-6);
     }
     
+    #[test]
+    fn test_hybrid_searcher_with_fake_embedder() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let model = FakeEmbeddingModel;
+        let chunks = vec![
+            test_chunk("sprites", "render sprites with the sprite batch renderer"),
+            test_chunk("audio", "play audio clips through the mixer"),
+        ];
+        
+        let index = crate::indexer::Index::create_in_dir(temp_dir.path()).unwrap();
+        let mut writer = index.writer(15_000_000).unwrap();
+        for chunk in &chunks {
+            let mut doc = tantivy::TantivyDocument::default();
+            doc.add_text(index.id_field, &chunk.id);
+            doc.add_text(index.text_field, &chunk.text);
+            doc.add_text(index.source_field, &chunk.source);
+            doc.add_text(index.heading_field, "");
+            doc.add_u64(index.position_field, chunk.position as u64);
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+        
+        let mut searcher = HybridSearcher {
+            bm25_index: crate::retriever::Index::new(index.tantivy_index).unwrap(),
+            embedding_store: build_store(&model, &chunks),
+            embedding_model: model,
+            ann_index: None,
+            ef_search: 64,
+        };
+        
+        let results = searcher.hybrid_search_with_alpha("sprite renderer", 2, 0.5, FusionMode::Linear).unwrap();
+        assert_eq!(results[0].chunk.id, "sprites");
+        assert!(results[0].bm25_score > 0.0);
+        assert!(results[0].semantic_score > 0.0);
+        
+        // The HNSW path resolves the same chunks as the exact scan
+        let exact = searcher.pure_semantic_search("sprite renderer", 2).unwrap();
+        searcher.ann_index = Some(Hnsw::build(&searcher.embedding_store, HnswParams::default()));
+        let approximate = searcher.pure_semantic_search("sprite renderer", 2).unwrap();
+        let ids = |results: &[SearchResult]| results.iter().map(|r| r.chunk.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&approximate), ids(&exact));
+        assert_eq!(approximate[0].chunk.text, chunks[0].text);
+    }
+    
+    #[test]
+    fn test_rrf_fusion_rewards_agreement_between_lists() {
+        let model = FakeEmbeddingModel;
+        let both = test_chunk("both", "render sprites");
+        let bm25_top = test_chunk("bm25_top", "sprites sprites sprites");
+        let semantic_top = test_chunk("semantic_top", "render render");
+        let store = build_store(&model, &[both.clone(), bm25_top.clone(), semantic_top.clone()]);
+        
+        // "both" is second in each list; the others top just one list
+        let bm25_candidates = vec![
+            ScoredChunk { chunk: bm25_top, score: 9.0 },
+            ScoredChunk { chunk: both.clone(), score: 3.0 },
+        ];
+        let semantic_candidates = vec![
+            (EnhancedChunk::from(semantic_top), 0.9),
+            (EnhancedChunk::from(both), 0.8),
+        ];
+        let query_embedding = model.encode("render sprites").unwrap();
+        
+        let results = rrf_fusion(&store, bm25_candidates, semantic_candidates, &query_embedding, 3, RrfParams { k: 60.0, bm25_weight: 1.0, semantic_weight: 1.0 });
+        
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].chunk.id, "both");
+        assert!((results[0].combined_score - 2.0 / 62.0).abs() < 1e-6);
+        assert!(results[0].explanation.contains("BM25 rank: 2"));
+        assert!(results[0].explanation.contains("Semantic rank: 2"));
+        
+        let bm25_only = results.iter().find(|r| r.chunk.id == "bm25_top").unwrap();
+        assert!((bm25_only.combined_score - 1.0 / 61.0).abs() < 1e-6);
+        assert!(bm25_only.explanation.contains("Semantic rank: -"));
+        
+        // Weights shift the balance between lists
+        let store = build_store(&model, &[]);
+        let results = rrf_fusion(
+            &store,
+            vec![ScoredChunk { chunk: test_chunk("lexical", "a"), score: 1.0 }],
+            vec![(EnhancedChunk::from(test_chunk("semantic", "b")), 0.5)],
+            &query_embedding,
+            2,
+            RrfParams { k: 60.0, bm25_weight: 1.0, semantic_weight: 2.0 },
+        );
+        assert_eq!(results[0].chunk.id, "semantic");
+    }
+    
+    #[test]
+    fn test_min_max_normalize() {
+        let normalized = min_max_normalize(&[2.0, 6.0, 4.0]);
+        assert_eq!(normalized, vec![0.0, 1.0, 0.5]);
+        
+        assert_eq!(min_max_normalize(&[3.5]), vec![1.0]);
+        assert_eq!(min_max_normalize(&[1.0, 1.0]), vec![1.0, 1.0]);
+        assert!(min_max_normalize(&[]).is_empty());
+    }
+    
     #[test]
     fn test_query_analysis() {
         assert!(matches!(analyze_query("sprite::new()"), SearchStrategy::BM25Heavy { .. }));
@@ -0,0 +1,529 @@
+// src/hnsw.rs
+
+//! Hierarchical Navigable Small World graph over the embedding store
+//!
+//! Approximate nearest-neighbour search (Malkov & Yashunin, 2016) for semantic
+//! retrieval. The graph only stores row numbers of the [`EmbeddingStore`];
+//! similarities come from the store in its own encoding (so quantized stores
+//! work too), and both files must come from the same `init` run. That is
+//! checked with [`EmbeddingStore::fingerprint`].
+//!
+//! `embeddings.hnsw` layout (little-endian): magic `BRAGHNSW`, version `u32`,
+//! `m` `u32`, `ef_construction` `u32`, store fingerprint `u64`, node count
+//! `u64`, entry point `u32` (`u32::MAX` when empty), then per node a layer
+//! count `u8` followed by `(u32 len, len × u32 neighbour)` for each layer.
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::embedding_store::{ByteReader, EmbeddingStore, QueryVector};
+
+const MAGIC: &[u8; 8] = b"BRAGHNSW";
+const FORMAT_VERSION: u32 = 1;
+const NO_ENTRY: u32 = u32::MAX;
+
+/// Build-time graph parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HnswParams {
+    /// Links per node on upper layers (layer 0 keeps `2 * m`)
+    pub m: usize,
+    /// Candidate list size while inserting; higher builds a better graph, slower
+    pub ef_construction: usize,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self { m: 16, ef_construction: 200 }
+    }
+}
+
+/// Similarity-ordered candidate (higher similarity compares greater)
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    similarity: f32,
+    node: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity.total_cmp(&other.similarity)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+/// HNSW graph whose nodes are embedding store rows
+pub struct Hnsw {
+    params: HnswParams,
+    fingerprint: u64,
+    entry_point: Option<u32>,
+    /// `links[node][layer]` are the node's neighbours on that layer
+    links: Vec<Vec<Vec<u32>>>,
+}
+
+impl Hnsw {
+    /// Build a graph over every row of `store`
+    pub fn build(store: &EmbeddingStore, params: HnswParams) -> Self {
+        let started = Instant::now();
+        let mut graph = Self {
+            params,
+            fingerprint: store.fingerprint(),
+            entry_point: None,
+            links: Vec::with_capacity(store.len()),
+        };
+
+        // Fixed seed keeps builds reproducible for the same store
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        let level_multiplier = 1.0 / (params.m.max(2) as f64).ln();
+        for node in 0..store.len() {
+            let level = (-rng.next_unit().ln() * level_multiplier) as usize;
+            graph.insert(store, node as u32, level);
+            if node > 0 && node % 10_000 == 0 {
+                debug!("HNSW: inserted {} of {} nodes", node, store.len());
+            }
+        }
+
+        info!(
+            "Built HNSW graph over {} embeddings in {:.1}s (M={}, ef_construction={})",
+            store.len(), started.elapsed().as_secs_f64(), params.m, params.ef_construction
+        );
+        graph
+    }
+
+    pub fn params(&self) -> HnswParams {
+        self.params
+    }
+
+    /// Whether the graph was built from exactly this store
+    pub fn matches(&self, store: &EmbeddingStore) -> bool {
+        self.fingerprint == store.fingerprint() && self.links.len() == store.len()
+    }
+
+    /// Approximate top-k rows by store similarity, best first.
+    ///
+    /// `ef` is the candidate list size; it is raised to `top_k` if smaller.
+    pub fn search(&self, store: &EmbeddingStore, query: &QueryVector, top_k: usize, ef: usize) -> Vec<(usize, f32)> {
+        let Some(mut entry) = self.entry_point else {
+            return Vec::new();
+        };
+
+        for layer in (1..self.links[entry as usize].len()).rev() {
+            entry = self.greedy_closest(store, query, entry, layer);
+        }
+
+        let mut results = self.search_layer(store, query, &[entry], ef.max(top_k), 0);
+        results.truncate(top_k);
+        results.into_iter()
+            .map(|candidate| (candidate.node as usize, candidate.similarity))
+            .collect()
+    }
+
+    /// [`Hnsw::search`] for the store's candidate count, then
+    /// [`EmbeddingStore::rescore`] down to `top_k`
+    pub fn search_and_rescore(&self, store: &EmbeddingStore, query: &QueryVector, top_k: usize, ef: usize) -> Vec<(usize, f32)> {
+        let candidates = self.search(store, query, store.candidate_count(top_k), ef);
+        store.rescore(query, candidates, top_k)
+    }
+
+    fn insert(&mut self, store: &EmbeddingStore, node: u32, level: usize) {
+        self.links.push(vec![Vec::new(); level + 1]);
+        let query = store.prepare(&store.vector(node as usize));
+
+        let Some(mut entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            return;
+        };
+        let top_layer = self.links[entry as usize].len() - 1;
+
+        // Descend greedily through the layers above the new node's level
+        for layer in (level + 1..=top_layer).rev() {
+            entry = self.greedy_closest(store, &query, entry, layer);
+        }
+
+        let mut entry_points = vec![entry];
+        for layer in (0..=level.min(top_layer)).rev() {
+            let candidates = self.search_layer(store, &query, &entry_points, self.params.ef_construction, layer);
+            let neighbours = self.select_neighbours(store, &candidates, self.max_links(layer));
+            self.links[node as usize][layer] = neighbours.clone();
+
+            for neighbour in neighbours {
+                self.links[neighbour as usize][layer].push(node);
+                if self.links[neighbour as usize][layer].len() > self.max_links(layer) {
+                    self.prune(store, neighbour, layer);
+                }
+            }
+            entry_points = candidates.iter().map(|candidate| candidate.node).collect();
+        }
+
+        if level > top_layer {
+            self.entry_point = Some(node);
+        }
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { self.params.m * 2 } else { self.params.m }
+    }
+
+    /// Follow the single best neighbour on `layer` until no neighbour is closer
+    fn greedy_closest(&self, store: &EmbeddingStore, query: &QueryVector, mut node: u32, layer: usize) -> u32 {
+        let mut best = store.similarity(query, node as usize);
+        loop {
+            let mut improved = false;
+            for &neighbour in &self.links[node as usize][layer] {
+                let similarity = store.similarity(query, neighbour as usize);
+                if similarity > best {
+                    best = similarity;
+                    node = neighbour;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return node;
+            }
+        }
+    }
+
+    /// Best-first search on one layer; returns up to `ef` candidates, best first
+    fn search_layer(
+        &self,
+        store: &EmbeddingStore,
+        query: &QueryVector,
+        entry_points: &[u32],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = entry_points.iter().copied().collect();
+        let mut to_visit = BinaryHeap::new();
+        let mut found = BinaryHeap::new();
+
+        for &node in entry_points {
+            let candidate = Candidate { similarity: store.similarity(query, node as usize), node };
+            to_visit.push(candidate);
+            found.push(Reverse(candidate));
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(current) = to_visit.pop() {
+            let worst = found.peek().map(|Reverse(c)| c.similarity).unwrap_or(f32::MIN);
+            if current.similarity < worst && found.len() >= ef {
+                break;
+            }
+
+            for &neighbour in &self.links[current.node as usize][layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let similarity = store.similarity(query, neighbour as usize);
+                let worst = found.peek().map(|Reverse(c)| c.similarity).unwrap_or(f32::MIN);
+                if found.len() < ef || similarity > worst {
+                    let candidate = Candidate { similarity, node: neighbour };
+                    to_visit.push(candidate);
+                    found.push(Reverse(candidate));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        let mut results: Vec<Candidate> = found.into_iter().map(|Reverse(c)| c).collect();
+        results.sort_by(|a, b| b.cmp(a));
+        results
+    }
+
+    /// Neighbour selection heuristic: keep a candidate only if it is closer to
+    /// the base node than to any neighbour already kept, which spreads links
+    /// across clusters. Remaining slots are filled with the closest rejects.
+    fn select_neighbours(&self, store: &EmbeddingStore, candidates: &[Candidate], max_links: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(max_links);
+        let mut rejected = Vec::new();
+
+        for candidate in candidates {
+            if selected.len() >= max_links {
+                break;
+            }
+            let diverse = selected.is_empty() || {
+                let candidate_query = store.prepare(&store.vector(candidate.node as usize));
+                selected.iter().all(|&kept| store.similarity(&candidate_query, kept as usize) < candidate.similarity)
+            };
+            if diverse {
+                selected.push(candidate.node);
+            } else {
+                rejected.push(candidate.node);
+            }
+        }
+
+        for node in rejected {
+            if selected.len() >= max_links {
+                break;
+            }
+            selected.push(node);
+        }
+        selected
+    }
+
+    /// Re-select the links of `node` on `layer` after it exceeded its limit
+    fn prune(&mut self, store: &EmbeddingStore, node: u32, layer: usize) {
+        let query = store.prepare(&store.vector(node as usize));
+        let mut candidates: Vec<Candidate> = self.links[node as usize][layer].iter()
+            .map(|&neighbour| Candidate { similarity: store.similarity(&query, neighbour as usize), node: neighbour })
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        self.links[node as usize][layer] = self.select_neighbours(store, &candidates, self.max_links(layer));
+    }
+
+    /// Write the graph to `path`, replacing any existing file atomically
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("hnsw.tmp");
+        let file = fs::File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {:?}", tmp_path))?;
+        let mut out = BufWriter::new(file);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&(self.params.m as u32).to_le_bytes())?;
+        out.write_all(&(self.params.ef_construction as u32).to_le_bytes())?;
+        out.write_all(&self.fingerprint.to_le_bytes())?;
+        out.write_all(&(self.links.len() as u64).to_le_bytes())?;
+        out.write_all(&self.entry_point.unwrap_or(NO_ENTRY).to_le_bytes())?;
+
+        for layers in &self.links {
+            out.write_all(&[layers.len() as u8])?;
+            for neighbours in layers {
+                out.write_all(&(neighbours.len() as u32).to_le_bytes())?;
+                for neighbour in neighbours {
+                    out.write_all(&neighbour.to_le_bytes())?;
+                }
+            }
+        }
+
+        out.into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|file| file.sync_all())
+            .context("Failed to write HNSW index")?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to move HNSW index into place at {:?}", path))?;
+
+        info!("Saved HNSW graph with {} nodes to {:?}", self.links.len(), path);
+        Ok(())
+    }
+
+    /// Load a graph written by [`Hnsw::save`]
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("Failed to read HNSW index {:?}", path))?;
+        let mut reader = ByteReader { bytes: &bytes, pos: 0 };
+
+        if reader.take(8)? != MAGIC {
+            bail!("{:?} is not an HNSW index", path);
+        }
+        let version = reader.u32()?;
+        if version != FORMAT_VERSION {
+            bail!("Unsupported HNSW index version {} (expected {})", version, FORMAT_VERSION);
+        }
+        let params = HnswParams {
+            m: reader.u32()? as usize,
+            ef_construction: reader.u32()? as usize,
+        };
+        let fingerprint = reader.u64()?;
+        let count = reader.u64()? as usize;
+        let entry_point = Some(reader.u32()?).filter(|&entry| entry != NO_ENTRY);
+
+        let mut links = Vec::with_capacity(count.min(bytes.len()));
+        for _ in 0..count {
+            let layer_count = reader.u8()? as usize;
+            let mut layers = Vec::with_capacity(layer_count);
+            for _ in 0..layer_count {
+                let len = reader.u32()? as usize;
+                let neighbours = (0..len)
+                    .map(|_| reader.u32().and_then(|n| check_node(n, count)))
+                    .collect::<Result<Vec<u32>>>()?;
+                layers.push(neighbours);
+            }
+            links.push(layers);
+        }
+        if let Some(entry) = entry_point {
+            check_node(entry, count)?;
+        }
+        if links.iter().any(Vec::is_empty) {
+            bail!("HNSW index {:?} is corrupt", path);
+        }
+
+        debug!("Loaded HNSW graph with {} nodes from {:?}", count, path);
+        Ok(Self { params, fingerprint, entry_point, links })
+    }
+}
+
+fn check_node(node: u32, count: usize) -> Result<u32> {
+    if (node as usize) < count {
+        Ok(node)
+    } else {
+        bail!("HNSW index references node {} of {}", node, count)
+    }
+}
+
+/// Small deterministic PRNG for layer assignment
+struct XorShift(u64);
+
+impl XorShift {
+    /// Uniform sample in (0, 1]
+    fn next_unit(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+    }
+}
+
+/// Recall of the graph against an exact scan at one `ef` setting
+#[derive(Debug)]
+pub struct RecallReport {
+    pub ef: usize,
+    /// Fraction of the exact top-k found by the graph, averaged over queries
+    pub recall: f64,
+    pub avg_query_micros: f64,
+    pub avg_exact_micros: f64,
+}
+
+/// Measure recall@`top_k` of `graph` for each `ef`, using `queries` stored
+/// rows (spread evenly over the store) as query vectors.
+pub fn recall_benchmark(
+    store: &EmbeddingStore,
+    graph: &Hnsw,
+    queries: usize,
+    top_k: usize,
+    efs: &[usize],
+) -> Vec<RecallReport> {
+    let step = (store.len() / queries.max(1)).max(1);
+    let query_rows: Vec<usize> = (0..store.len()).step_by(step).take(queries).collect();
+    let queries: Vec<QueryVector> = query_rows.iter()
+        .map(|&row| store.prepare(&store.vector(row)))
+        .collect();
+
+    let started = Instant::now();
+    let ground_truth: Vec<HashSet<usize>> = queries.iter()
+        .map(|query| store.search_rows(query, top_k).into_iter().map(|(row, _)| row).collect())
+        .collect();
+    let avg_exact_micros = started.elapsed().as_micros() as f64 / query_rows.len().max(1) as f64;
+
+    efs.iter().map(|&ef| {
+        let started = Instant::now();
+        let mut hits = 0;
+        let mut expected = 0;
+        for (query, truth) in queries.iter().zip(&ground_truth) {
+            let results = graph.search_and_rescore(store, query, top_k, ef);
+            hits += results.iter().filter(|(found, _)| truth.contains(found)).count();
+            expected += truth.len();
+        }
+        RecallReport {
+            ef,
+            recall: if expected == 0 { 1.0 } else { hits as f64 / expected as f64 },
+            avg_query_micros: started.elapsed().as_micros() as f64 / query_rows.len().max(1) as f64,
+            avg_exact_micros,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// `count` pseudo-random vectors drawn around a handful of cluster centres
+    fn clustered_store(count: usize, dimension: usize) -> EmbeddingStore {
+        let mut rng = XorShift(42);
+        let centres: Vec<Vec<f32>> = (0..8)
+            .map(|_| (0..dimension).map(|_| rng.next_unit() as f32 * 2.0 - 1.0).collect())
+            .collect();
+
+        let mut store = EmbeddingStore::new("test", dimension);
+        for i in 0..count {
+            let centre = &centres[i % centres.len()];
+            let vector: Vec<f32> = centre.iter()
+                .map(|c| c + (rng.next_unit() as f32 - 0.5) * 0.8)
+                .collect();
+            store.insert(&format!("chunk-{}", i), "hash", &vector).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn test_search_recall_against_exact_scan() {
+        let store = clustered_store(1_000, 16);
+        let graph = Hnsw::build(&store, HnswParams { m: 12, ef_construction: 64 });
+
+        let reports = recall_benchmark(&store, &graph, 100, 10, &[10, 100]);
+        assert!(reports[1].recall >= 0.95, "recall@10 with ef=100 was {}", reports[1].recall);
+        assert!(reports[1].recall >= reports[0].recall);
+
+        // A stored vector is its own nearest neighbour
+        let results = graph.search(&store, &store.prepare(&store.vector(7)), 5, 50);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].0, 7);
+        assert!(results.windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+
+    #[test]
+    fn test_save_load_round_trip_and_fingerprint() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.hnsw");
+        let store = clustered_store(300, 8);
+        let graph = Hnsw::build(&store, HnswParams::default());
+        graph.save(&path).unwrap();
+
+        let loaded = Hnsw::load(&path).unwrap();
+        assert!(loaded.matches(&store));
+        assert_eq!(loaded.params(), HnswParams::default());
+        let query = store.prepare(&store.vector(3));
+        assert_eq!(loaded.search(&store, &query, 10, 40), graph.search(&store, &query, 10, 40));
+
+        let mut changed = clustered_store(300, 8);
+        changed.insert("chunk-0", "new-hash", &store.vector(1)).unwrap();
+        assert!(!loaded.matches(&changed));
+    }
+
+    #[test]
+    fn test_empty_store() {
+        let store = EmbeddingStore::new("test", 4);
+        let graph = Hnsw::build(&store, HnswParams::default());
+        assert!(graph.search(&store, &store.prepare(&[1.0, 0.0, 0.0, 0.0]), 3, 10).is_empty());
+    }
+
+    #[test]
+    fn test_graph_over_quantized_store() {
+        use crate::embedding_store::{Quantization, StoreFormat};
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("embeddings.bin");
+        let original = clustered_store(500, 16);
+        let graph = Hnsw::build(&original, HnswParams { m: 12, ef_construction: 64 });
+        original.save(&path, StoreFormat { quantization: Quantization::Int8, full_precision: true }).unwrap();
+
+        // The graph built from float vectors still matches the quantized store
+        let store = EmbeddingStore::open(&path).unwrap();
+        assert!(graph.matches(&store));
+        let reports = recall_benchmark(&store, &graph, 50, 10, &[64]);
+        assert!(reports[0].recall >= 0.9, "recall@10 was {}", reports[0].recall);
+    }
+}
@@ -9,15 +9,22 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tantivy::{
     doc,
-    schema::{Field, Schema, TextFieldIndexing, TextOptions, FAST, INDEXED, STORED, STRING},
-    Index as TantivyIndex, IndexReader, IndexWriter, ReloadPolicy, Searcher, Term,
+    schema::{document::Value, Field, Schema, TextFieldIndexing, TextOptions, FAST, INDEXED, STORED, STRING},
+    Index as TantivyIndex, IndexReader, IndexWriter, ReloadPolicy, Searcher, TantivyDocument, Term,
 };
 
 use crate::cli::Cli;
-use crate::ingest::Chunk;
+use crate::ingest::{chunk_file, Changeset, Chunk, DocMetadata};
+
+/// Version of the schema built by `build_schema`; indexes recorded with an
+/// older version are rebuilt by the next `init`.
+///
+/// 2: document metadata fields; older indexes may also lack `page` and
+///    `alternate_source`
+pub const SCHEMA_VERSION: u32 = 2;
 
 /// Index state tracking for incremental updates
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -39,6 +46,12 @@ pub struct Index {
     pub source_field: Field,
     pub heading_field: Field,
     pub position_field: Field,
+    /// Absent in indexes created before page numbers were recorded
+    pub page_field: Option<Field>,
+    /// Absent in indexes created before near-duplicates were folded
+    pub alternate_source_field: Option<Field>,
+    /// Absent in indexes created before schema version 2
+    pub metadata_fields: Option<MetadataFields>,
     pub _reader: IndexReader,
 }
 
@@ -54,6 +67,9 @@ impl Index {
         let source_field = schema.get_field("source").unwrap();
         let heading_field = schema.get_field("heading").unwrap();
         let position_field = schema.get_field("position").unwrap();
+        let page_field = schema.get_field("page").ok();
+        let alternate_source_field = schema.get_field("alternate_source").ok();
+        let metadata_fields = MetadataFields::from_schema(&schema);
         
         let reader = tantivy_index
             .reader_builder()
@@ -69,6 +85,9 @@ impl Index {
             source_field,
             heading_field,
             position_field,
+            page_field,
+            alternate_source_field,
+            metadata_fields,
             _reader: reader,
         })
     }
@@ -84,6 +103,9 @@ impl Index {
         let source_field = schema.get_field("source").unwrap();
         let heading_field = schema.get_field("heading").unwrap();
         let position_field = schema.get_field("position").unwrap();
+        let page_field = schema.get_field("page").ok();
+        let alternate_source_field = schema.get_field("alternate_source").ok();
+        let metadata_fields = MetadataFields::from_schema(&schema);
         
         let reader = tantivy_index
             .reader_builder()
@@ -99,10 +121,37 @@ impl Index {
             source_field,
             heading_field,
             position_field,
+            page_field,
+            alternate_source_field,
+            metadata_fields,
             _reader: reader,
         })
     }
 
+    /// Replace the index in `dir` with an empty one using the current schema.
+    ///
+    /// Tantivy cannot change the schema of an existing index, so the old
+    /// index's files are removed; other files in `dir` are left alone.
+    pub fn recreate_in_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let old_index = TantivyIndex::open_in_dir(dir)
+            .context("Failed to open Tantivy index")?;
+        let mut files: Vec<PathBuf> = old_index.searchable_segment_metas()?
+            .iter()
+            .flat_map(|meta| meta.list_files())
+            .collect();
+        files.extend(["meta.json", ".managed.json"].map(PathBuf::from));
+        drop(old_index);
+        
+        for file in files {
+            let path = dir.join(file);
+            if path.exists() {
+                fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+            }
+        }
+        Self::create_in_dir(dir)
+    }
+
     /// Get a searcher for querying the index
     pub fn _searcher(&self) -> Searcher {
         self._reader.searcher()
@@ -116,6 +165,74 @@ impl Index {
     }
 }
 
+/// Field handles for `DocMetadata`, added together in schema version 2
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataFields {
+    pub title: Field,
+    pub file_type: Field,
+    pub language: Field,
+    pub modified: Field,
+    pub git_commit: Field,
+    /// One value per tag
+    pub tag: Field,
+    pub doc_version: Field,
+}
+
+impl MetadataFields {
+    /// Look up the metadata fields, or `None` for indexes that predate them
+    pub fn from_schema(schema: &Schema) -> Option<Self> {
+        Some(Self {
+            title: schema.get_field("title").ok()?,
+            file_type: schema.get_field("file_type").ok()?,
+            language: schema.get_field("language").ok()?,
+            modified: schema.get_field("modified").ok()?,
+            git_commit: schema.get_field("git_commit").ok()?,
+            tag: schema.get_field("tag").ok()?,
+            doc_version: schema.get_field("doc_version").ok()?,
+        })
+    }
+    
+    /// Add `metadata` to `doc`; unset values are left out
+    fn add_to(&self, doc: &mut TantivyDocument, metadata: &DocMetadata) {
+        doc.add_text(self.file_type, &metadata.file_type);
+        let optional = [
+            (self.title, &metadata.title),
+            (self.language, &metadata.language),
+            (self.git_commit, &metadata.git_commit),
+            (self.doc_version, &metadata.version),
+        ];
+        for (field, value) in optional {
+            if let Some(value) = value {
+                doc.add_text(field, value);
+            }
+        }
+        if let Some(modified) = metadata.modified {
+            doc.add_u64(self.modified, modified);
+        }
+        for tag in &metadata.tags {
+            doc.add_text(self.tag, tag);
+        }
+    }
+    
+    /// Read the metadata stored in `doc`
+    pub fn read(&self, doc: &TantivyDocument) -> DocMetadata {
+        let text = |field| doc.get_first(field)
+            .and_then(|value| value.as_str())
+            .map(str::to_string);
+        DocMetadata {
+            title: text(self.title),
+            file_type: text(self.file_type).unwrap_or_default(),
+            language: text(self.language),
+            modified: doc.get_first(self.modified).and_then(|value| value.as_u64()),
+            git_commit: text(self.git_commit),
+            tags: doc.get_all(self.tag)
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect(),
+            version: text(self.doc_version),
+        }
+    }
+}
+
 /// Build the Tantivy schema for document chunks
 fn build_schema() -> Schema {
     let mut schema_builder = Schema::builder();
@@ -141,11 +258,30 @@ fn build_schema() -> Schema {
     // Position field: chunk position within the source file
     schema_builder.add_u64_field("position", INDEXED | STORED | FAST);
     
+    // Page field: page number within paginated sources such as PDFs
+    schema_builder.add_u64_field("page", STORED);
+    
+    // Alternate source field: citations of near-duplicate chunks folded into this one
+    schema_builder.add_text_field("alternate_source", STRING | STORED);
+    
+    // Document metadata: fast and indexed so searches can filter and facet on them
+    schema_builder.add_text_field("title", STRING | STORED | FAST);
+    schema_builder.add_text_field("file_type", STRING | STORED | FAST);
+    schema_builder.add_text_field("language", STRING | STORED | FAST);
+    schema_builder.add_u64_field("modified", INDEXED | STORED | FAST);
+    schema_builder.add_text_field("git_commit", STRING | STORED | FAST);
+    schema_builder.add_text_field("tag", STRING | STORED | FAST);
+    schema_builder.add_text_field("doc_version", STRING | STORED | FAST);
+    
     schema_builder.build()
 }
 
-/// Build or update the Tantivy index from chunks
-pub fn build_index(cli: &Cli, chunks: &[Chunk]) -> Result<()> {
+/// Apply an ingest changeset to the Tantivy index.
+///
+/// Chunks of files outside the changeset are left as they are; chunks of
+/// changed files are added, replaced or removed by comparing content hashes.
+pub fn build_index(cli: &Cli, changeset: &Changeset) -> Result<()> {
+    let chunks = &changeset.chunks;
     log::info!("Building index at {:?} with {} chunks", cli.index_dir, chunks.len());
     
     // Create index directory if it doesn't exist
@@ -160,6 +296,11 @@ pub fn build_index(cli: &Cli, chunks: &[Chunk]) -> Result<()> {
     let current_chunks: HashMap<String, &Chunk> = chunks.iter()
         .map(|chunk| (chunk.id.clone(), chunk))
         .collect();
+    let changed_files = changeset.changed_files();
+    if changeset.rebuild {
+        log::info!("Rebuilding index from scratch");
+        state.chunk_hashes.clear();
+    }
     
     // Determine which chunks are new, modified, or removed
     let mut new_chunks = Vec::new();
@@ -187,9 +328,9 @@ pub fn build_index(cli: &Cli, chunks: &[Chunk]) -> Result<()> {
         }
     }
     
-    // Check for removed chunks
+    // Check for removed chunks; only changed files can lose chunks
     for existing_id in state.chunk_hashes.keys() {
-        if !current_chunks.contains_key(existing_id) {
+        if changed_files.contains(chunk_file(existing_id)) && !current_chunks.contains_key(existing_id) {
             removed_chunk_ids.push(existing_id.clone());
         }
     }
@@ -207,14 +348,21 @@ pub fn build_index(cli: &Cli, chunks: &[Chunk]) -> Result<()> {
     );
     
     // If no changes, skip index update
-    if new_chunks.is_empty() && modified_chunks.is_empty() && removed_chunk_ids.is_empty() {
+    if !changeset.rebuild && new_chunks.is_empty() && modified_chunks.is_empty() && removed_chunk_ids.is_empty() {
         log::info!("No changes detected, skipping index update");
-        return Ok(());
+        return changeset.save_state(&cli.index_dir);
     }
     
-    // Create or open the index
+    // Create or open the index; a rebuild also migrates an outdated schema
     let index = if cli.index_dir.join("meta.json").exists() {
-        Index::open_in_dir(&cli.index_dir)?
+        let index = Index::open_in_dir(&cli.index_dir)?;
+        if changeset.rebuild && index.tantivy_index.schema() != build_schema() {
+            log::info!("Migrating index at {:?} to schema version {}", cli.index_dir, SCHEMA_VERSION);
+            drop(index);
+            Index::recreate_in_dir(&cli.index_dir)?
+        } else {
+            index
+        }
     } else {
         Index::create_in_dir(&cli.index_dir)?
     };
@@ -222,6 +370,10 @@ pub fn build_index(cli: &Cli, chunks: &[Chunk]) -> Result<()> {
     // Get index writer with 50MB heap
     let mut writer = index.writer(50_000_000)?;
     
+    if changeset.rebuild {
+        writer.delete_all_documents().context("Failed to clear index")?;
+    }
+    
     // Remove deleted chunks
     for chunk_id in &removed_chunk_ids {
         let term = Term::from_field_text(index.id_field, chunk_id);
@@ -247,7 +399,7 @@ pub fn build_index(cli: &Cli, chunks: &[Chunk]) -> Result<()> {
     writer.commit().context("Failed to commit index changes")?;
     
     // Update state metadata
-    state.schema_version = 1;
+    state.schema_version = SCHEMA_VERSION;
     state.last_updated = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
@@ -255,6 +407,7 @@ pub fn build_index(cli: &Cli, chunks: &[Chunk]) -> Result<()> {
     
     // Save updated state
     save_index_state(&state_path, &state)?;
+    changeset.save_state(&cli.index_dir)?;
     
     log::info!("Index successfully built/updated");
     Ok(())
@@ -268,10 +421,27 @@ pub fn open_index(cli: &Cli) -> Result<Index> {
         anyhow::bail!("Index directory does not exist: {:?}", cli.index_dir);
     }
     
+    if needs_migration(&cli.index_dir) {
+        log::warn!(
+            "Index at {:?} predates schema version {}; re-run init to rebuild it with document metadata",
+            cli.index_dir,
+            SCHEMA_VERSION
+        );
+    }
+    
     Index::open_in_dir(&cli.index_dir)
         .context("Failed to open index")
 }
 
+/// Whether the index in `index_dir` was built with an older schema version
+pub fn needs_migration(index_dir: &Path) -> bool {
+    if !index_dir.join("meta.json").exists() {
+        return false;
+    }
+    load_index_state(&index_dir.join("state.json"))
+        .map_or(true, |state| state.schema_version < SCHEMA_VERSION)
+}
+
 /// Add a chunk to the index writer
 fn add_chunk_to_writer(writer: &mut IndexWriter, index: &Index, chunk: &Chunk) -> Result<()> {
     let mut doc = tantivy::TantivyDocument::default();
@@ -288,6 +458,20 @@ fn add_chunk_to_writer(writer: &mut IndexWriter, index: &Index, chunk: &Chunk) -
     
     doc.add_u64(index.position_field, chunk.position as u64);
     
+    if let (Some(page_field), Some(page)) = (index.page_field, chunk.page) {
+        doc.add_u64(page_field, page as u64);
+    }
+    
+    if let Some(alternate_source_field) = index.alternate_source_field {
+        for source in &chunk.alternate_sources {
+            doc.add_text(alternate_source_field, source);
+        }
+    }
+    
+    if let Some(metadata_fields) = index.metadata_fields {
+        metadata_fields.add_to(&mut doc, &chunk.metadata);
+    }
+    
     writer.add_document(doc)?;
     Ok(())
 }
@@ -302,6 +486,9 @@ fn calculate_chunk_hash(chunk: &Chunk) -> String {
     chunk.source.hash(&mut hasher);
     chunk.heading.hash(&mut hasher);
     chunk.position.hash(&mut hasher);
+    chunk.page.hash(&mut hasher);
+    chunk.alternate_sources.hash(&mut hasher);
+    chunk.metadata.hash(&mut hasher);
     
     format!("{:x}", hasher.finish())
 }
@@ -340,6 +527,9 @@ mod tests {
             source: source.to_string(),
             heading: None,
             position,
+            page: None,
+            alternate_sources: Vec::new(),
+            metadata: DocMetadata::default(),
         }
     }
     
@@ -359,7 +549,7 @@ mod tests {
         let index = Index::create_in_dir(temp_dir.path())?;
         
         // Test that we can create a writer
-        let _writer = index.writer(1_000_000)?;
+        let _writer = index.writer(15_000_000)?;
         
         Ok(())
     }
@@ -393,4 +583,64 @@ mod tests {
         
         Ok(())
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_metadata_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let index = Index::create_in_dir(temp_dir.path())?;
+        let mut chunk = create_test_chunk("guide.md:chunk0", "Install it.", "guide.md", 0);
+        chunk.metadata = DocMetadata {
+            title: Some("Guide".to_string()),
+            file_type: "markdown".to_string(),
+            language: Some("en".to_string()),
+            modified: Some(1_700_000_000),
+            git_commit: Some("abc123".to_string()),
+            tags: vec!["setup".to_string(), "cli".to_string()],
+            version: Some("2.1".to_string()),
+        };
+        
+        let mut writer = index.writer(15_000_000)?;
+        add_chunk_to_writer(&mut writer, &index, &chunk)?;
+        writer.commit()?;
+        index._reader.reload()?;
+        
+        let searcher = index._searcher();
+        let fields = index.metadata_fields.unwrap();
+        let tag = Term::from_field_text(fields.tag, "cli");
+        let query = tantivy::query::TermQuery::new(tag, tantivy::schema::IndexRecordOption::Basic);
+        let hits = searcher.search(&query, &tantivy::collector::TopDocs::with_limit(1))?;
+        assert_eq!(hits.len(), 1);
+        let doc: TantivyDocument = searcher.doc(hits[0].1)?;
+        assert_eq!(fields.read(&doc), chunk.metadata);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_outdated_schema_is_migrated() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut old_schema = Schema::builder();
+        let id_field = old_schema.add_text_field("id", STRING | STORED);
+        let old_index = TantivyIndex::create_in_dir(temp_dir.path(), old_schema.build())?;
+        let mut writer: IndexWriter = old_index.writer(15_000_000)?;
+        writer.add_document(doc!(id_field => "old.md:chunk0"))?;
+        writer.commit()?;
+        drop(writer);
+        drop(old_index);
+        
+        let state_path = temp_dir.path().join("state.json");
+        save_index_state(&state_path, &IndexState { schema_version: 1, ..IndexState::default() })?;
+        fs::write(temp_dir.path().join("ingest_state.json"), "{}")?;
+        assert!(needs_migration(temp_dir.path()));
+        
+        let index = Index::recreate_in_dir(temp_dir.path())?;
+        assert!(index.metadata_fields.is_some());
+        assert_eq!(index._searcher().num_docs(), 0);
+        assert!(temp_dir.path().join("ingest_state.json").exists());
+        
+        save_index_state(&state_path, &IndexState { schema_version: SCHEMA_VERSION, ..IndexState::default() })?;
+        assert!(!needs_migration(temp_dir.path()));
+        
+        Ok(())
+    }
+}
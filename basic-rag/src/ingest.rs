@@ -4,18 +4,32 @@
 //!
 //! Responsibilities:
 //! 1. Clone or pull the target Git repository, extracting only the documentation folder.
-//! 2. Walk the local docs directory and split files into overlapping token-based chunks.
-//! 3. Compute checksums per file and compare to previous state for incremental updates.
+//! 2. Walk the local docs directory and split files into heading-aware, token-bounded chunks.
+//! 3. Compute checksums per file and compare to the state kept in the index directory
+//!    for incremental updates.
 
-use anyhow::{Context, Result, anyhow};
-use std::collections::HashMap;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use git2::build::CheckoutBuilder;
+use git2::{FetchOptions, IndexEntry, IndexEntryExtendedFlag, Oid, Repository};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use walkdir::WalkDir;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 use sha2::{Sha256, Digest};
 use serde::{Deserialize, Serialize};
 use log::{info, debug, warn};
-use crate::cli::Cli;
+use regex::Regex;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use scraper::{ElementRef, Html, Node, Selector};
+use syn::{Attribute, Expr, ImplItem, Item, Lit, Meta, TraitItem, Visibility};
+use crate::chunking::{self, Chunker};
+use crate::dedup;
+use crate::indexer;
+use crate::retriever;
+use crate::cli::{ChunkStrategy, Cli};
+use crate::tokenizer::{self, Tokenizer};
+use crate::sources::{discover_files, IngestConfig, Processor};
 
 /// Represents a chunk of text with source metadata.
 #[derive(Debug, Clone)]
@@ -25,53 +39,272 @@ pub struct Chunk {
     pub source: String,       // Source file path or URL fragment
     pub heading: Option<String>, // Optional heading extracted from the file
     pub position: usize,      // Chunk index within the file
+    pub page: Option<usize>,  // 1-based page number for paginated sources (PDF)
+    pub alternate_sources: Vec<String>, // Citations of near-duplicate chunks folded into this one
+    pub metadata: DocMetadata, // Metadata of the document the chunk came from
 }
 
-/// State tracking for incremental updates
+/// Document-level metadata shared by every chunk of a file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DocMetadata {
+    /// Front matter or HTML title, else the first heading, else the file name
+    pub title: Option<String>,
+    /// Processor the file was extracted with, e.g. "markdown"
+    pub file_type: String,
+    /// Natural language from front matter or the HTML `lang` attribute, e.g. "en"
+    pub language: Option<String>,
+    /// Modification time in seconds since the Unix epoch when the file was ingested
+    pub modified: Option<u64>,
+    /// HEAD commit of the docs repository when the file was ingested
+    pub git_commit: Option<String>,
+    /// Front matter tags
+    pub tags: Vec<String>,
+    /// Front matter version of the documented software
+    pub version: Option<String>,
+}
+
+impl Chunk {
+    /// Source reference for citations, e.g. "spec.pdf p.12"
+    pub fn citation(&self) -> String {
+        match self.page {
+            Some(page) => format!("{} p.{}", self.source, page),
+            None => self.source.clone(),
+        }
+    }
+}
+
+/// File a chunk id belongs to, e.g. "path/to/file.md" for "path/to/file.md:chunk3"
+pub fn chunk_file(chunk_id: &str) -> &str {
+    chunk_id.rsplit_once(":chunk").map_or(chunk_id, |(file, _)| file)
+}
+
+/// Files that changed since the previous ingest, with the chunks of those that still exist.
+///
+/// Paths are relative to the docs directory. Unchanged files are not re-read,
+/// so their chunks are not included, except for indexed chunks that gained an
+/// alternate source; the index keeps the ones it already has.
+#[derive(Debug, Default)]
+pub struct Changeset {
+    /// Chunks of added and modified files
+    pub chunks: Vec<Chunk>,
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    /// Files that were removed, or that can no longer be read or extracted
+    pub deleted: Vec<String>,
+    /// Number of files skipped because their checksum is unchanged
+    pub unchanged: usize,
+    /// Every file was re-ingested; the index must drop all existing chunks
+    pub rebuild: bool,
+    /// Number of near-duplicate chunks folded into an earlier chunk
+    pub duplicates: usize,
+    /// Ingestion state to record once the changeset is applied
+    state: IngestState,
+}
+
+impl Changeset {
+    /// Files whose previously indexed chunks are replaced or removed
+    pub fn changed_files(&self) -> HashSet<&str> {
+        self.added.iter()
+            .chain(&self.modified)
+            .chain(&self.deleted)
+            .map(String::as_str)
+            .collect()
+    }
+    
+    /// Record the ingested checksums in `index_dir`, so the next run only
+    /// picks up later changes. Call this after the changeset is indexed.
+    pub fn save_state(&self, index_dir: &Path) -> Result<()> {
+        self.state.save(index_dir)
+            .context("Failed to save ingestion state")
+    }
+}
+
+/// Content under a single heading breadcrumb, before it is split into chunks
+#[derive(Debug, Clone, PartialEq)]
+struct Section {
+    /// Heading breadcrumb, e.g. "User Guide > Troubleshooting"
+    heading: Option<String>,
+    blocks: Vec<Block>,
+    /// 1-based page the section was extracted from, for paginated formats
+    page: Option<usize>,
+    /// Location within the file appended to chunk sources, e.g. `cell-3`
+    anchor: Option<String>,
+}
+
+/// Per-format extraction settings taken from the command line
+#[derive(Debug, Clone, Copy)]
+struct ExtractOptions<'a> {
+    /// Token budget used to group CSV rows
+    chunk_size: usize,
+    /// Tokenizer the budget is measured with
+    tokenizer: &'a dyn Tokenizer,
+    /// Include Jupyter cell outputs
+    notebook_outputs: bool,
+}
+
+/// A run of prose or a verbatim code block within a section
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// Text with whitespace normalized
+    Prose(String),
+    /// Code kept byte-for-byte, with the fence's language tag if any
+    Code { language: Option<String>, code: String },
+}
+
+/// Elements that never hold page content
+const HTML_SKIPPED_ELEMENTS: &[&str] = &[
+    "nav", "header", "footer", "aside", "script", "style", "noscript", "template",
+    "button", "form", "svg", "rustdoc-toolbar",
+];
+
+/// Classes of page chrome found inside content areas (rustdoc sidebars, source links, anchors)
+const HTML_SKIPPED_CLASSES: &[&str] = &["sidebar", "src", "anchor"];
+
+/// Elements that flow within a line and so do not separate words
+const HTML_INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "cite", "code", "del", "em", "i", "ins", "kbd", "mark", "q", "s",
+    "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+/// Main content containers, most specific first
+const HTML_CONTENT_SELECTORS: &[&str] = &["#main-content", "main", "article", "[role=main]", "body"];
+
+/// RST directives whose arguments and content are not prose
+const RST_DROPPED_DIRECTIVES: &[&str] = &[
+    "image", "include", "literalinclude", "toctree", "raw", "index", "highlight", "contents", "meta",
+];
+
+/// RST directives whose content is a code block, with the language as argument
+const RST_CODE_DIRECTIVES: &[&str] = &["code-block", "code", "sourcecode"];
+
+/// Inline RST markup, rewritten in order to the text it wraps
+static RST_INLINE_MARKUP: LazyLock<Vec<(Regex, &str)>> = LazyLock::new(|| {
+    [
+        // :role:`Title <target>` and :role:`~target`
+        (r":[\w.:+-]+:`(?:([^`<]*?)\s*<[^>`]*>|[~!]?([^`]*))`", "$1$2"),
+        // ``literal``
+        (r"``([^`]+)``", "$1"),
+        // `Link text <url>`_ and `reference`_
+        (r"`([^`<]*?)\s*<[^>`]*>`__?", "$1"),
+        (r"`([^`]+)`(?:__?)?", "$1"),
+        // Footnote and citation references
+        (r"\s*\[(?:#[\w-]*|\*|\d+|[A-Za-z][\w-]*)\]_", ""),
+        // **strong** and *emphasis*
+        (r"\*\*([^*]+)\*\*", "$1"),
+        (r"\*([^*\s][^*]*)\*", "$1"),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).expect("valid RST markup pattern"), replacement))
+    .collect()
+});
+
+/// Name of the ingestion state file inside the index directory
+const INGEST_STATE_FILE: &str = "ingest_state.json";
+
+/// Docs directory and settings that determine the chunks produced for a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IngestKey {
+    /// Canonical path of the docs directory
+    docs_root: PathBuf,
+    chunk_size: usize,
+    chunk_overlap: usize,
+    notebook_outputs: bool,
+    #[serde(default)]
+    chunker: ChunkStrategy,
+    /// Encoding that chunk sizes are measured in
+    #[serde(default)]
+    tokenizer: String,
+    /// Only set for semantic chunking, the one strategy it affects
+    #[serde(default)]
+    semantic_breakpoint: Option<f32>,
+    /// Similarity threshold for folding near-duplicates, unset when disabled
+    #[serde(default)]
+    dedup_threshold: Option<f32>,
+}
+
+impl IngestKey {
+    fn from_cli(cli: &Cli, tokenizer: &dyn Tokenizer) -> Self {
+        Self {
+            docs_root: fs::canonicalize(&cli.docs_dir).unwrap_or_else(|_| cli.docs_dir.clone()),
+            chunk_size: cli.chunk_size,
+            chunk_overlap: cli.chunk_overlap,
+            notebook_outputs: cli.notebook_outputs,
+            chunker: cli.chunker,
+            tokenizer: tokenizer.name().to_string(),
+            semantic_breakpoint: (cli.chunker == ChunkStrategy::Semantic).then_some(cli.semantic_breakpoint),
+            dedup_threshold: (!cli.no_dedup).then_some(cli.dedup_threshold),
+        }
+    }
+}
+
+/// State tracking for incremental updates, stored in the index directory
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct IngestState {
+    /// Docs directory and settings the checksums were recorded with
+    #[serde(default)]
+    key: Option<IngestKey>,
     /// Maps file path to its SHA-256 checksum
     file_checksums: HashMap<String, String>,
+    /// Maps file path to the files it shared near-duplicate chunks with
+    #[serde(default)]
+    duplicate_links: HashMap<String, Vec<String>>,
 }
 
 impl IngestState {
-    /// Load state from state.json file
-    fn load() -> Result<Self> {
-        let state_path = Path::new("state.json");
+    /// Load the state kept in `index_dir`
+    fn load(index_dir: &Path) -> Result<Self> {
+        let state_path = index_dir.join(INGEST_STATE_FILE);
         if !state_path.exists() {
             return Ok(Self::default());
         }
         
-        let contents = fs::read_to_string(state_path)
-            .context("Failed to read state.json")?;
+        let contents = fs::read_to_string(&state_path)
+            .with_context(|| format!("Failed to read {:?}", state_path))?;
         
         let state: IngestState = serde_json::from_str(&contents)
-            .context("Failed to parse state.json")?;
+            .with_context(|| format!("Failed to parse {:?}", state_path))?;
         
         Ok(state)
     }
     
-    /// Save state to state.json file
-    fn save(&self) -> Result<()> {
+    /// Save the state to `index_dir`
+    fn save(&self, index_dir: &Path) -> Result<()> {
         let contents = serde_json::to_string_pretty(self)
             .context("Failed to serialize state")?;
         
-        fs::write("state.json", contents)
-            .context("Failed to write state.json")?;
+        let state_path = index_dir.join(INGEST_STATE_FILE);
+        fs::write(&state_path, contents)
+            .with_context(|| format!("Failed to write {:?}", state_path))?;
         
         Ok(())
     }
 }
 
+/// Git repository the docs directory is synced from
+#[derive(Debug, Clone)]
+pub struct DocsSource {
+    /// Remote URL (anything libgit2 can fetch: https, ssh, file path)
+    pub url: String,
+    /// Branch to track
+    pub branch: String,
+    /// Optional subfolder to restrict the working tree to
+    pub sparse_path: Option<String>,
+    /// Optional shallow fetch depth
+    pub depth: Option<i32>,
+}
+
 /// Ensure the docs directory is populated by cloning or pulling the Git repo.
-pub fn sync_docs(docs_dir: &Path) -> Result<()> {
+///
+/// Without a `source` the directory is used as-is (sample docs are written if it
+/// is empty). With a `source` the repository is cloned on the first run and
+/// fetched + fast-forwarded on subsequent runs.
+pub fn sync_docs(docs_dir: &Path, source: Option<&DocsSource>) -> Result<()> {
     info!("🔄 Syncing docs to {:?}", docs_dir);
     
-    // For now, just create the directory if it doesn't exist
-    // TODO: This is a placeholder - in a real implementation, you'd add:
-    // - Git repository cloning/pulling logic
-    // - Sparse checkout functionality
-    // - Error handling for Git operations
+    if let Some(source) = source {
+        return sync_git_docs(docs_dir, source);
+    }
+    
     std::fs::create_dir_all(docs_dir)
         .context("Failed to create docs directory")?;
     
@@ -84,6 +317,134 @@ pub fn sync_docs(docs_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Clone or fast-forward `docs_dir` from the configured Git repository
+fn sync_git_docs(docs_dir: &Path, source: &DocsSource) -> Result<()> {
+    let repo = if docs_dir.join(".git").exists() {
+        debug!("Opening existing docs checkout at {:?}", docs_dir);
+        let repo = Repository::open(docs_dir)
+            .context("Failed to open docs repository")?;
+        repo.remote_set_url("origin", &source.url)
+            .context("Failed to update docs remote URL")?;
+        repo
+    } else {
+        fs::create_dir_all(docs_dir)
+            .context("Failed to create docs directory")?;
+        if docs_dir.read_dir()?.next().is_some() {
+            return Err(anyhow!(
+                "Docs directory {:?} is not empty and is not a Git checkout; \
+                 remove it or point --docs-dir elsewhere",
+                docs_dir
+            ));
+        }
+        info!("Cloning {} ({}) into {:?}", source.url, source.branch, docs_dir);
+        let repo = Repository::init(docs_dir)
+            .context("Failed to initialize docs repository")?;
+        repo.remote("origin", &source.url)
+            .context("Failed to add docs remote")?;
+        repo
+    };
+    
+    write_sparse_checkout(&repo, source.sparse_path.as_deref())?;
+    
+    // Fetch the tracked branch into its remote-tracking ref
+    let remote_ref = format!("refs/remotes/origin/{}", source.branch);
+    let refspec = format!("+refs/heads/{}:{}", source.branch, remote_ref);
+    let mut fetch_options = FetchOptions::new();
+    if let Some(depth) = source.depth {
+        fetch_options.depth(depth);
+    }
+    repo.find_remote("origin")?
+        .fetch(&[&refspec], Some(&mut fetch_options), None)
+        .with_context(|| format!("Failed to fetch branch '{}' from {}", source.branch, source.url))?;
+    let target = repo.refname_to_id(&remote_ref)
+        .with_context(|| format!("Branch '{}' not found on remote", source.branch))?;
+    
+    // Fast-forward the local branch, refusing to discard local history
+    let local_ref = format!("refs/heads/{}", source.branch);
+    let current = repo.refname_to_id(&local_ref).ok();
+    match current {
+        Some(current) if current == target => {
+            info!("Docs already up to date at {}", target);
+        }
+        Some(current) if !repo.graph_descendant_of(target, current)? => {
+            return Err(anyhow!(
+                "Cannot fast-forward docs branch '{}' from {} to {}; the remote history diverged",
+                source.branch, current, target
+            ));
+        }
+        _ => {
+            info!("Updating docs to {}", target);
+        }
+    }
+    
+    checkout_sparse(&repo, target, source.sparse_path.as_deref())?;
+    repo.reference(&local_ref, target, true, "basic-rag: fast-forward docs")
+        .context("Failed to update local docs branch")?;
+    repo.set_head(&local_ref)
+        .context("Failed to update HEAD of docs repository")?;
+    
+    info!("✅ Docs synced to {} ({})", target, source.branch);
+    Ok(())
+}
+
+/// Record the sparse checkout pattern so the `git` CLI agrees with our working tree
+fn write_sparse_checkout(repo: &Repository, sparse_path: Option<&str>) -> Result<()> {
+    let mut config = repo.config().context("Failed to open docs repository config")?;
+    let sparse_file = repo.path().join("info").join("sparse-checkout");
+    
+    match sparse_path {
+        Some(path) => {
+            config.set_bool("core.sparseCheckout", true)?;
+            fs::create_dir_all(repo.path().join("info"))?;
+            fs::write(&sparse_file, format!("/{}/\n", path.trim_matches('/')))
+                .context("Failed to write sparse-checkout file")?;
+        }
+        None => {
+            config.set_bool("core.sparseCheckout", false)?;
+            if sparse_file.exists() {
+                fs::remove_file(&sparse_file)?;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Check out `target`, limiting the working tree to `sparse_path` when given.
+///
+/// libgit2 has no native sparse checkout, so the checkout is restricted by
+/// pathspec and every index entry outside the path is flagged skip-worktree.
+fn checkout_sparse(repo: &Repository, target: Oid, sparse_path: Option<&str>) -> Result<()> {
+    let commit = repo.find_commit(target)?;
+    let tree = commit.tree()?;
+    
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force().remove_untracked(false);
+    if let Some(path) = sparse_path {
+        checkout.path(path.trim_matches('/'));
+    }
+    repo.checkout_tree(tree.as_object(), Some(&mut checkout))
+        .context("Failed to check out docs tree")?;
+    
+    // Rebuild the index from the full tree so files outside the sparse path are
+    // tracked (and not reported as deleted), but skipped in the working tree
+    let mut index = repo.index()?;
+    index.read_tree(&tree)?;
+    if let Some(path) = sparse_path {
+        let prefix = format!("{}/", path.trim_matches('/'));
+        let outside: Vec<IndexEntry> = index.iter()
+            .filter(|entry| !entry.path.starts_with(prefix.as_bytes()))
+            .collect();
+        for mut entry in outside {
+            entry.flags_extended |= IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
+            index.add(&entry)?;
+        }
+    }
+    index.write().context("Failed to write docs repository index")?;
+    
+    Ok(())
+}
+
 /// Create sample documentation files for testing
 fn create_sample_docs(docs_dir: &Path) -> Result<()> {
     let sample_content = r#"# Getting Started
@@ -169,204 +530,1273 @@ Welcome to the comprehensive user guide.
     Ok(())
 }
 
-/// Walk the docs directory and chunk each file into token-based chunks.
-pub fn ingest_docs(cli: &Cli) -> Result<Vec<Chunk>> {
+/// Walk the docs directory and chunk each new or changed file.
+///
+/// Returns the changeset against the previous run's checksums.
+pub fn ingest_docs(cli: &Cli) -> Result<Changeset> {
     info!("📖 Ingesting and chunking docs in {:?}", cli.docs_dir);
+    if !cli.no_dedup && (cli.dedup_threshold <= 0.0 || cli.dedup_threshold > 1.0) {
+        bail!("dedup_threshold must be in (0, 1]");
+    }
     
-    // Load previous state
-    let mut state = IngestState::load()
+    // Load previous state; a different docs directory or chunking setup invalidates it
+    let state = IngestState::load(&cli.index_dir)
         .context("Failed to load ingestion state")?;
+    let tokenizer = tokenizer::from_cli(cli);
+    let key = IngestKey::from_cli(cli, tokenizer.as_ref());
+    let outdated_schema = indexer::needs_migration(&cli.index_dir);
+    let mut changeset = Changeset {
+        rebuild: state.key.as_ref() != Some(&key) || outdated_schema || !cli.index_dir.join("meta.json").exists(),
+        ..Changeset::default()
+    };
+    let previous_checksums = if changeset.rebuild {
+        if outdated_schema {
+            info!("🔁 Index schema is outdated; rebuilding the index");
+        } else if state.key.is_some() {
+            info!("🔁 Docs directory or chunking settings changed; rebuilding the index");
+        }
+        HashMap::new()
+    } else {
+        state.file_checksums
+    };
     
-    let mut all_chunks = Vec::new();
     let mut new_checksums = HashMap::new();
+    let options = ExtractOptions {
+        chunk_size: cli.chunk_size,
+        tokenizer: tokenizer.as_ref(),
+        notebook_outputs: cli.notebook_outputs,
+    };
+    let chunker = chunking::from_cli(cli, tokenizer.clone())?;
+    let git_commit = docs_commit(&cli.docs_dir);
     
-    // Walk the docs directory
-    for entry in WalkDir::new(&cli.docs_dir)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter(|e| is_supported_file(e.path()))
-    {
-        let file_path = entry.path();
-        let relative_path = file_path.strip_prefix(&cli.docs_dir)
-            .unwrap_or(file_path)
-            .to_string_lossy()
-            .to_string();
-        
-        debug!("Processing file: {:?}", file_path);
-        
-        // Read file content
-        let content = match fs::read_to_string(file_path) {
+    // Walk the docs directory, keeping the files selected by the ingest config
+    let config = IngestConfig::from_cli(cli)?;
+    let mut pending = Vec::new();
+    let mut unchanged = Vec::new();
+    for file in discover_files(&cli.docs_dir, &config)? {
+        // Read file content (PDFs are binary)
+        let content = match fs::read(&file.path) {
             Ok(content) => content,
             Err(e) => {
-                warn!("Failed to read file {:?}: {}", file_path, e);
+                warn!("Failed to read file {:?}: {}", file.path, e);
                 continue;
             }
         };
         
-        // Compute checksum
+        // Check if file has changed
         let checksum = compute_checksum(&content);
-        new_checksums.insert(relative_path.clone(), checksum.clone());
+        if previous_checksums.get(&file.relative_path) == Some(&checksum) {
+            unchanged.push((file, checksum));
+        } else {
+            pending.push((file, Some(content), checksum));
+        }
+    }
+    
+    // Files that shared duplicates with a changed or removed file are
+    // re-ingested too, so a chunk folded into a stale one gets indexed again
+    let mut duplicate_links = if changeset.rebuild { HashMap::new() } else { state.duplicate_links };
+    let discovered: HashSet<&String> = pending.iter()
+        .map(|(file, _, _)| &file.relative_path)
+        .chain(unchanged.iter().map(|(file, _)| &file.relative_path))
+        .collect();
+    let stale = pending.iter()
+        .map(|(file, _, _)| &file.relative_path)
+        .chain(previous_checksums.keys().filter(|path| !discovered.contains(path)));
+    let linked = linked_files(&duplicate_links, stale);
+    let mut skipped = HashSet::new();
+    for (file, checksum) in unchanged {
+        if linked.contains(&file.relative_path) {
+            debug!("Re-ingesting {:?}, which shares duplicates with a changed file", file.path);
+            pending.push((file, None, checksum));
+        } else {
+            debug!("File unchanged, skipping: {:?}", file.path);
+            skipped.insert(file.relative_path.clone());
+            new_checksums.insert(file.relative_path, checksum);
+            changeset.unchanged += 1;
+        }
+    }
+    
+    for (file, content, checksum) in pending {
+        let file_path = file.path.as_path();
+        let relative_path = file.relative_path;
         
-        // Check if file has changed
-        if let Some(old_checksum) = state.file_checksums.get(&relative_path) {
-            if old_checksum == &checksum {
-                debug!("File unchanged, skipping: {:?}", file_path);
+        debug!("Processing file: {:?} as {:?}", file_path, file.processor);
+        
+        let content = match content.map_or_else(|| fs::read(file_path), Ok) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Failed to read file {:?}: {}", file_path, e);
                 continue;
             }
-        }
+        };
         
         info!("Processing new/changed file: {:?}", file_path);
         
-        // Process the file content
-        let processed_content = process_file_content(&content, file_path)?;
+        // Split the file into sections; unreadable files are retried next run
+        let sections = match process_file_content(&content, file_path, file.processor, options) {
+            Ok(sections) => sections,
+            Err(e) => {
+                warn!("Failed to extract text from {:?}: {:#}", file_path, e);
+                continue;
+            }
+        };
         
-        // Create chunks
-        let chunks = create_chunks(&processed_content, &relative_path, cli.chunk_size, cli.chunk_overlap)?;
+        // Create chunks, each carrying the document's metadata
+        let metadata = doc_metadata(&content, file_path, file.processor, &sections, git_commit.as_deref());
+        let mut chunks = create_chunks(&sections, &relative_path, chunker.as_ref())?;
+        for chunk in &mut chunks {
+            chunk.metadata = metadata.clone();
+        }
         
         debug!("Created {} chunks for file: {:?}", chunks.len(), file_path);
-        all_chunks.extend(chunks);
+        changeset.chunks.extend(chunks);
+        if previous_checksums.contains_key(&relative_path) {
+            changeset.modified.push(relative_path.clone());
+        } else {
+            changeset.added.push(relative_path.clone());
+        }
+        new_checksums.insert(relative_path, checksum);
     }
     
-    // Update state with new checksums
-    state.file_checksums = new_checksums;
-    state.save()
-        .context("Failed to save ingestion state")?;
+    // Files from the previous run that were not ingested this time lose their chunks
+    changeset.deleted = previous_checksums.keys()
+        .filter(|path| !new_checksums.contains_key(*path))
+        .cloned()
+        .collect();
+    changeset.deleted.sort();
     
-    info!("✅ Ingested {} chunks from {} files", all_chunks.len(), state.file_checksums.len());
+    // Fold near-duplicate chunks into their first occurrence; chunks already
+    // indexed for skipped files come first and record new duplicates' sources
+    duplicate_links.retain(|file, _| !linked.contains(file));
+    if !cli.no_dedup {
+        let indexed = if changeset.chunks.is_empty() {
+            Vec::new()
+        } else {
+            indexed_chunks(&cli.index_dir, &skipped)?
+        };
+        let deduplicated = dedup::deduplicate(indexed, std::mem::take(&mut changeset.chunks), cli.dedup_threshold);
+        changeset.chunks = deduplicated.chunks;
+        changeset.duplicates = deduplicated.removed;
+        for (canonical, duplicate) in deduplicated.links {
+            duplicate_links.entry(canonical.clone()).or_default().push(duplicate.clone());
+            duplicate_links.entry(duplicate).or_default().push(canonical);
+        }
+    }
+    
+    // Saved by the indexer once the changes are applied
+    changeset.state = IngestState {
+        key: Some(key),
+        file_checksums: new_checksums,
+        duplicate_links,
+    };
+    
+    info!(
+        "✅ Ingested {} chunks: {} added, {} modified, {} deleted, {} unchanged files, {} near-duplicate chunks removed",
+        changeset.chunks.len(),
+        changeset.added.len(),
+        changeset.modified.len(),
+        changeset.deleted.len(),
+        changeset.unchanged,
+        changeset.duplicates
+    );
     
-    Ok(all_chunks)
+    Ok(changeset)
 }
 
-/// Check if a file is supported for ingestion
-fn is_supported_file(path: &Path) -> bool {
-    if let Some(extension) = path.extension() {
-        let ext = extension.to_string_lossy().to_lowercase();
-        matches!(ext.as_str(), "md" | "markdown" | "html" | "htm" | "txt" | "rst")
-    } else {
-        false
+/// Chunks the index in `index_dir` holds for `files`
+fn indexed_chunks(index_dir: &Path, files: &HashSet<String>) -> Result<Vec<Chunk>> {
+    if files.is_empty() {
+        return Ok(Vec::new());
     }
+    let index = indexer::Index::open_in_dir(index_dir)?;
+    let chunks = retriever::all_chunks(&retriever::Index::new(index.tantivy_index)?)?;
+    Ok(chunks.into_iter().filter(|chunk| files.contains(chunk_file(&chunk.id))).collect())
+}
+
+/// `files` and every file connected to them through shared duplicates
+fn linked_files<'a>(
+    links: &HashMap<String, Vec<String>>,
+    files: impl IntoIterator<Item = &'a String>,
+) -> HashSet<String> {
+    let mut linked: HashSet<String> = HashSet::new();
+    let mut queue: Vec<&String> = files.into_iter().collect();
+    while let Some(file) = queue.pop() {
+        if linked.insert(file.clone()) {
+            queue.extend(links.get(file).into_iter().flatten());
+        }
+    }
+    linked
 }
 
 /// Compute SHA-256 checksum of content
-fn compute_checksum(content: &str) -> String {
+pub fn compute_checksum(content: impl AsRef<[u8]>) -> String {
     let mut hasher = Sha256::new();
-    hasher.update(content.as_bytes());
+    hasher.update(content.as_ref());
     format!("{:x}", hasher.finalize())
 }
 
-/// Process file content based on file type
-fn process_file_content(content: &str, file_path: &Path) -> Result<String> {
-    let extension = file_path.extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("")
-        .to_lowercase();
+/// Split file content into sections with the processor chosen for its file type
+fn process_file_content(
+    content: &[u8],
+    file_path: &Path,
+    processor: Processor,
+    options: ExtractOptions<'_>,
+) -> Result<Vec<Section>> {
+    // Everything but PDF is text
+    let text = || std::str::from_utf8(content).context("File is not valid UTF-8");
+    Ok(match processor {
+        Processor::Pdf => pdf_sections(content)?,
+        Processor::Markdown => markdown_sections(text()?),
+        Processor::Html => html_sections(text()?),
+        Processor::Rst => rst_sections(text()?),
+        Processor::Rust => rust_sections(text()?, file_path)?,
+        Processor::Notebook => notebook_sections(text()?, options.notebook_outputs)?,
+        // YAML is a superset of JSON, but serde_json gives clearer errors for .json files
+        Processor::Openapi if file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) => {
+            openapi_sections(serde_json::from_str(text()?).context("Invalid JSON")?)
+        }
+        Processor::Openapi => openapi_sections(serde_yaml::from_str(text()?).context("Invalid YAML")?),
+        Processor::Csv => csv_sections(text()?, options.chunk_size, options.tokenizer)?,
+        Processor::Text => unsectioned(text()?),
+    })
+}
+
+/// Extract one section per PDF page; pages without text are skipped
+fn pdf_sections(content: &[u8]) -> Result<Vec<Section>> {
+    // pdf-extract panics on some malformed documents instead of returning an error
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(content))
+        .map_err(|_| anyhow!("PDF parser failed on a malformed document"))?
+        .context("Failed to extract text from PDF")?;
     
-    match extension.as_str() {
-        "md" | "markdown" => process_markdown(content),
-        "html" | "htm" => process_html(content),
-        "txt" | "rst" => Ok(process_plain_text(content)),
-        _ => Ok(process_plain_text(content)),
+    Ok(pages.iter()
+        .enumerate()
+        .filter_map(|(index, text)| {
+            let blocks = paragraph_blocks(text);
+            (!blocks.is_empty()).then(|| Section {
+                heading: None,
+                blocks,
+                page: Some(index + 1),
+                anchor: None,
+            })
+        })
+        .collect())
+}
+
+/// Wrap text without heading structure in a single section
+fn unsectioned(text: &str) -> Vec<Section> {
+    let blocks = paragraph_blocks(text);
+    if blocks.is_empty() {
+        Vec::new()
+    } else {
+        vec![Section { heading: None, blocks, page: None, anchor: None }]
+    }
+}
+
+/// One prose block per paragraph of plain text, split at blank lines
+fn paragraph_blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph = String::new();
+    for line in text.lines().chain([""]) {
+        if line.trim().is_empty() {
+            let prose = normalize_whitespace(&paragraph);
+            paragraph.clear();
+            if !prose.is_empty() {
+                blocks.push(Block::Prose(prose));
+            }
+        } else {
+            paragraph.push_str(line);
+            paragraph.push(' ');
+        }
+    }
+    blocks
+}
+
+/// Accumulates sections while a document is walked in order
+#[derive(Default)]
+struct SectionBuilder {
+    sections: Vec<Section>,
+    /// Open headings as (level, title), outermost first
+    breadcrumb: Vec<(usize, String)>,
+    blocks: Vec<Block>,
+    text: String,
+    /// Collects the title while inside a heading
+    title: Option<String>,
+    /// Anchor given to the sections that follow
+    anchor: Option<String>,
+}
+
+impl SectionBuilder {
+    /// Close the current section; later sections carry `anchor`
+    fn start_anchor(&mut self, anchor: String) {
+        self.flush_section();
+        self.anchor = Some(anchor);
+    }
+    
+    /// Close the current section as a heading begins
+    fn start_heading(&mut self) {
+        self.flush_section();
+        self.title = Some(String::new());
+    }
+    
+    /// Finish the heading begun by `start_heading` and open its section
+    fn end_heading(&mut self, level: usize) {
+        let title = normalize_whitespace(&self.title.take().unwrap_or_default());
+        // A heading closes every open section at its level or deeper
+        self.breadcrumb.retain(|(parent, _)| *parent < level);
+        if !title.is_empty() {
+            // Sections start with their own title, so BM25 still matches heading words
+            self.text.push_str(&title);
+            self.flush_prose();
+            self.breadcrumb.push((level, title));
+        }
+    }
+    
+    /// Append prose, or heading text while inside a heading
+    fn push_text(&mut self, fragment: &str) {
+        self.title.as_mut().unwrap_or(&mut self.text).push_str(fragment);
+    }
+    
+    /// Separate words across a line or block boundary
+    fn push_break(&mut self) {
+        self.push_text(" ");
+    }
+    
+    /// Close the current paragraph; inside a heading this only separates words
+    fn end_paragraph(&mut self) {
+        if self.title.is_some() {
+            self.push_break();
+        } else {
+            self.flush_prose();
+        }
+    }
+    
+    /// Add a verbatim code block; surrounding empty lines are dropped
+    fn push_code(&mut self, language: Option<String>, code: &str) {
+        self.flush_prose();
+        let code = code.trim_matches('\n');
+        if !code.trim().is_empty() {
+            self.blocks.push(Block::Code { language, code: code.to_string() });
+        }
+    }
+    
+    fn finish(mut self) -> Vec<Section> {
+        self.flush_section();
+        self.sections
+    }
+    
+    /// Move the accumulated prose into `blocks`
+    fn flush_prose(&mut self) {
+        let prose = normalize_whitespace(&self.text);
+        self.text.clear();
+        if !prose.is_empty() {
+            self.blocks.push(Block::Prose(prose));
+        }
+    }
+    
+    /// Emit the accumulated blocks as a section under the current breadcrumb.
+    ///
+    /// Headings without a body (e.g. directly followed by a subheading) are
+    /// only kept in the breadcrumb.
+    fn flush_section(&mut self) {
+        self.flush_prose();
+        let blocks = std::mem::take(&mut self.blocks);
+        let title_only = match blocks.as_slice() {
+            [] => true,
+            [Block::Prose(prose)] => self.breadcrumb.last().is_some_and(|(_, title)| prose == title),
+            _ => false,
+        };
+        if title_only {
+            return;
+        }
+        
+        let heading = (!self.breadcrumb.is_empty()).then(|| {
+            self.breadcrumb.iter()
+                .map(|(_, title)| title.as_str())
+                .collect::<Vec<_>>()
+                .join(" > ")
+        });
+        self.sections.push(Section { heading, blocks, page: None, anchor: self.anchor.clone() });
     }
 }
 
-/// Process Markdown content
-fn process_markdown(content: &str) -> Result<String> {
-    // Strip frontmatter if present
-    let content = strip_frontmatter(content);
+/// Split Markdown into one section per heading, tracking the heading breadcrumb.
+///
+/// Prose has its markup dropped and whitespace normalized, while fenced and
+/// indented code blocks are kept verbatim. Text before the first heading forms
+/// a section without a heading.
+fn markdown_sections(content: &str) -> Vec<Section> {
+    let (_, content) = split_frontmatter(content);
+    let mut builder = SectionBuilder::default();
+    feed_markdown(&mut builder, content);
+    builder.finish()
+}
+
+/// Feed Markdown to the builder, continuing its heading breadcrumb
+fn feed_markdown(builder: &mut SectionBuilder, content: &str) {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+    // Collects the language and body while inside a code block
+    let mut code: Option<(Option<String>, String)> = None;
     
-    // Simple markdown processing - remove common markdown syntax
-    let processed = content
-        .lines()
-        .map(|line| {
-            let line = line.trim();
+    for event in Parser::new_ext(content, options) {
+        match event {
+            Event::Start(Tag::Heading(..)) => builder.start_heading(),
+            Event::End(Tag::Heading(level, _, _)) => builder.end_heading(level as usize),
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().map(str::to_string),
+                    CodeBlockKind::Indented => None,
+                };
+                code = Some((language, String::new()));
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((language, body)) = code.take() {
+                    builder.push_code(language, &body);
+                }
+            }
+            Event::Text(fragment) | Event::Code(fragment) => match code.as_mut() {
+                Some((_, body)) => body.push_str(&fragment),
+                None => builder.push_text(&fragment),
+            },
+            Event::SoftBreak | Event::HardBreak => builder.push_break(),
+            Event::Rule | Event::End(
+                Tag::Paragraph | Tag::Item | Tag::BlockQuote | Tag::TableHead | Tag::TableRow
+                    | Tag::FootnoteDefinition(_)
+            ) => builder.end_paragraph(),
+            // Block ends separate words; inline ends must not split them
+            Event::End(tag) if !matches!(
+                tag,
+                Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+            ) => builder.push_break(),
+            _ => {}
+        }
+    }
+}
+
+/// Split HTML into sections at `h1`–`h6`, keeping only the main content.
+///
+/// The first container matching `HTML_CONTENT_SELECTORS` is used, page chrome
+/// inside it is dropped, and `pre` blocks are kept verbatim as code.
+fn html_sections(content: &str) -> Vec<Section> {
+    let document = Html::parse_document(content);
+    let root = HTML_CONTENT_SELECTORS.iter()
+        .filter_map(|selector| Selector::parse(selector).ok())
+        .find_map(|selector| document.select(&selector).next())
+        .unwrap_or_else(|| document.root_element());
+    
+    let mut builder = SectionBuilder::default();
+    walk_html(root, &mut builder);
+    builder.finish()
+}
+
+/// Feed the children of `element` to the builder in document order
+fn walk_html(element: ElementRef, builder: &mut SectionBuilder) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => builder.push_text(text),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    visit_html_element(child, builder);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn visit_html_element(element: ElementRef, builder: &mut SectionBuilder) {
+    let tag = element.value().name();
+    if HTML_SKIPPED_ELEMENTS.contains(&tag)
+        || element.value().classes().any(|class| HTML_SKIPPED_CLASSES.contains(&class))
+    {
+        return;
+    }
+    
+    if let Some(level) = html_heading_level(tag) {
+        builder.start_heading();
+        walk_html(element, builder);
+        builder.end_heading(level);
+    } else if tag == "pre" {
+        builder.push_code(html_code_language(element), &element.text().collect::<String>());
+    } else if HTML_INLINE_ELEMENTS.contains(&tag) {
+        walk_html(element, builder);
+    } else if tag == "br" {
+        builder.push_break();
+    } else {
+        builder.end_paragraph();
+        walk_html(element, builder);
+        builder.end_paragraph();
+    }
+}
+
+/// Level of an `h1`–`h6` tag
+fn html_heading_level(tag: &str) -> Option<usize> {
+    tag.strip_prefix('h')
+        .and_then(|level| level.parse().ok())
+        .filter(|level| (1..=6).contains(level))
+}
+
+/// Language of a `pre` block from `language-*`/`lang-*` classes on it or its
+/// `code` child, or rustdoc's `rust` class
+fn html_code_language(pre: ElementRef) -> Option<String> {
+    let code = pre.children().filter_map(ElementRef::wrap).find(|child| child.value().name() == "code");
+    pre.value().classes()
+        .chain(code.iter().flat_map(|code| code.value().classes()))
+        .find_map(|class| {
+            class.strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+                .or((class == "rust").then_some(class))
+        })
+        .map(str::to_string)
+}
+
+/// Split reStructuredText into sections at its titles.
+///
+/// Title levels follow the order in which adornment styles first appear, as in
+/// docutils. Directive and role markup is stripped but its content kept, while
+/// `code-block` directives and `::` literal blocks are kept verbatim as code.
+fn rst_sections(content: &str) -> Vec<Section> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut reader = RstReader::default();
+    reader.read(&lines);
+    reader.builder.finish()
+}
+
+/// Line-oriented reStructuredText reader feeding a `SectionBuilder`
+#[derive(Default)]
+struct RstReader {
+    builder: SectionBuilder,
+    /// Title adornments as (character, has overline) in order of first use
+    title_styles: Vec<(char, bool)>,
+}
+
+impl RstReader {
+    fn read(&mut self, lines: &[&str]) {
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            let indent = indentation(line);
             
-            // Remove heading markers
-            if line.starts_with('#') {
-                return line.trim_start_matches('#').trim().to_string();
+            if line.trim().is_empty() || is_table_border(line) {
+                self.builder.end_paragraph();
+                i += 1;
+                continue;
             }
             
-            // Remove code block markers
-            if line.starts_with("```") {
-                return String::new();
+            // Title between an overline and an underline
+            if let Some(style) = adornment(line) {
+                let underline = lines.get(i + 2).and_then(|line| adornment(line));
+                match lines.get(i + 1) {
+                    Some(title) if underline == Some(style) && !title.trim().is_empty() => {
+                        self.title(title.trim(), (style, true));
+                        i += 3;
+                    }
+                    // A transition only separates text
+                    _ => {
+                        self.builder.end_paragraph();
+                        i += 1;
+                    }
+                }
+                continue;
             }
             
-            // Remove bold/italic markers (basic)
-            let line = line.replace("**", "").replace("*", "");
+            // Title with an underline at least as long as the text
+            if let Some(style) = lines.get(i + 1).filter(|_| indent == 0).and_then(|line| adornment(line)) {
+                if lines[i + 1].trim_end().chars().count() >= line.trim_end().chars().count() {
+                    self.title(line.trim(), (style, false));
+                    i += 2;
+                    continue;
+                }
+            }
             
-            // Remove inline code markers
-            let line = line.replace("`", "");
+            if let Some(head) = line.trim_start().strip_prefix("..").filter(|rest| rest.is_empty() || rest.starts_with(' ')) {
+                let end = block_end(lines, i + 1, indent);
+                self.directive(head.trim(), &dedent(&lines[i + 1..end]));
+                i = end;
+                continue;
+            }
             
-            line
-        })
-        .collect::<Vec<_>>()
-        .join(" ");
+            let text = line.trim();
+            let text = ["- ", "* ", "+ "].iter().find_map(|bullet| text.strip_prefix(bullet)).unwrap_or(text);
+            match literal_block_intro(text) {
+                // A paragraph ending in "::" introduces an indented literal block
+                Some(intro) => {
+                    self.builder.push_text(&rst_inline(intro));
+                    self.builder.push_break();
+                    let end = block_end(lines, i + 1, indent);
+                    self.builder.push_code(None, &dedent(&lines[i + 1..end]).join("\n"));
+                    i = end;
+                }
+                None => {
+                    self.builder.push_text(&rst_inline(text));
+                    self.builder.push_break();
+                    i += 1;
+                }
+            }
+        }
+    }
     
-    // Normalize whitespace
-    let normalized = normalize_whitespace(&processed);
+    fn title(&mut self, text: &str, style: (char, bool)) {
+        let level = match self.title_styles.iter().position(|known| *known == style) {
+            Some(index) => index + 1,
+            None => {
+                self.title_styles.push(style);
+                self.title_styles.len()
+            }
+        };
+        self.builder.start_heading();
+        self.builder.push_text(&rst_inline(text));
+        self.builder.end_heading(level);
+    }
     
-    Ok(normalized)
+    /// Handle an explicit markup block: `head` follows the "..", `body` is dedented
+    fn directive(&mut self, head: &str, body: &[&str]) {
+        // Footnotes and citations keep their text
+        if let Some(footnote) = head.strip_prefix('[') {
+            let text = footnote.split_once(']').map_or("", |(_, text)| text);
+            self.builder.push_text(&rst_inline(text));
+            self.builder.push_break();
+            self.read(body);
+            return;
+        }
+        
+        // Comments, hyperlink targets and substitution definitions hold no prose
+        let Some((name, arguments)) = head.split_once("::")
+            .filter(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace))
+            .filter(|_| !head.starts_with(['_', '|']))
+        else {
+            return;
+        };
+        // Sphinx domain directives like `py:function` behave like plain ones
+        let kind = name.rsplit(':').next().unwrap_or(name);
+        
+        // A field list right after the directive line holds its options
+        let options = body.iter().take_while(|line| line.starts_with(':')).count();
+        let content = &body[options..];
+        
+        if RST_CODE_DIRECTIVES.contains(&kind) {
+            self.builder.push_code(arguments.split_whitespace().next().map(str::to_string), &content.join("\n"));
+        } else if !RST_DROPPED_DIRECTIVES.contains(&kind) {
+            self.builder.push_text(&rst_inline(arguments.trim()));
+            self.builder.push_break();
+            self.read(content);
+        }
+    }
+}
+
+/// Leading whitespace width of a line in bytes
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Index just past the block indented deeper than `indent` starting at `start`
+fn block_end(lines: &[&str], start: usize, indent: usize) -> usize {
+    let mut end = start;
+    for (offset, line) in lines[start..].iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indentation(line) <= indent {
+            break;
+        }
+        end = start + offset + 1;
+    }
+    end
+}
+
+/// Strip the indentation shared by all non-blank lines
+fn dedent<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+    let common = lines.iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indentation(line))
+        .min()
+        .unwrap_or(0);
+    lines.iter()
+        .map(|line| line.get(common..).unwrap_or_else(|| line.trim_start()))
+        .collect()
+}
+
+/// Character of an RST section adornment line like `=====` or `~~~~~`
+fn adornment(line: &str) -> Option<char> {
+    let line = line.trim_end();
+    let first = line.chars().next()?;
+    let valid = line.len() >= 2
+        && first.is_ascii_punctuation()
+        && line.chars().all(|c| c == first);
+    valid.then_some(first)
+}
+
+/// Borders of simple (`=== ===`) and grid (`+---+---+`) tables
+fn is_table_border(line: &str) -> bool {
+    let line = line.trim();
+    line.contains(['-', '=']) && line.contains([' ', '+']) && line.chars().all(|c| "+-= ".contains(c))
+}
+
+/// Text of a paragraph line ending in `::` as it is displayed, or `None`
+fn literal_block_intro(line: &str) -> Option<&str> {
+    let text = line.strip_suffix("::")?;
+    // "Example::" reads "Example:", while a detached " ::" disappears
+    Some(match text.strip_suffix(' ') {
+        Some(text) => text.trim_end(),
+        None if text.is_empty() => text,
+        None => &line[..line.len() - 1],
+    })
+}
+
+/// Replace inline RST markup (roles, literals, references, emphasis) with its text
+fn rst_inline(text: &str) -> String {
+    RST_INLINE_MARKUP.iter().fold(text.to_string(), |text, (pattern, replacement)| {
+        pattern.replace_all(&text, *replacement).into_owned()
+    })
+}
+
+/// Extract one section per documented or public item of a Rust source file.
+///
+/// Each section holds the item path, its signature (struct and enum bodies
+/// included) and its `///` docs; `//!` docs become a section for the module.
+/// Headings are module paths, with the type name for methods.
+fn rust_sections(content: &str, file_path: &Path) -> Result<Vec<Section>> {
+    let file = syn::parse_file(content).context("Failed to parse Rust source")?;
+    let module = rust_module_path(file_path);
+    
+    let mut sections = Vec::new();
+    push_rust_module(&mut sections, &module, &file.attrs);
+    collect_rust_items(&mut sections, &module, &file.items);
+    Ok(sections)
+}
+
+/// Module path of a source file, e.g. `my_crate::config` for `my-crate/src/config.rs`
+fn rust_module_path(file_path: &Path) -> String {
+    let components: Vec<String> = file_path.with_extension("")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    
+    // Files under `src/` are named after the crate directory holding it
+    let (crate_name, modules) = match components.iter().rposition(|component| component == "src") {
+        Some(src) => (src.checked_sub(1).map(|index| components[index].as_str()), &components[src + 1..]),
+        None => (None, &components[components.len().saturating_sub(1)..]),
+    };
+    let crate_name = crate_name
+        .filter(|name| !matches!(*name, "." | ".."))
+        .map_or_else(|| "crate".to_string(), |name| name.replace('-', "_"));
+    
+    std::iter::once(crate_name)
+        .chain(modules.iter().filter(|module| !matches!(module.as_str(), "lib" | "main" | "mod")).cloned())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn collect_rust_items(sections: &mut Vec<Section>, module: &str, items: &[Item]) {
+    for item in items {
+        match item {
+            Item::Fn(function) => {
+                let signature = Item::Fn(syn::ItemFn { block: Box::new(empty_block()), ..function.clone() });
+                push_rust_item(sections, module, &function.sig.ident.to_string(), &function.attrs, &function.vis, signature);
+            }
+            Item::Struct(item_struct) => {
+                let name = item_struct.ident.to_string();
+                push_rust_item(sections, module, &name, &item_struct.attrs, &item_struct.vis, item.clone());
+            }
+            Item::Enum(item_enum) => {
+                let name = item_enum.ident.to_string();
+                push_rust_item(sections, module, &name, &item_enum.attrs, &item_enum.vis, item.clone());
+            }
+            Item::Trait(item_trait) => {
+                let name = item_trait.ident.to_string();
+                let header = Item::Trait(syn::ItemTrait { items: Vec::new(), ..item_trait.clone() });
+                push_rust_item(sections, module, &name, &item_trait.attrs, &item_trait.vis, header);
+                
+                let parent = format!("{}::{}", module, name);
+                for trait_item in &item_trait.items {
+                    if let TraitItem::Fn(method) = trait_item {
+                        let signature = method_signature(&method.attrs, Visibility::Inherited, &method.sig);
+                        // Trait methods are as visible as the trait
+                        push_rust_item(sections, &parent, &method.sig.ident.to_string(), &method.attrs, &item_trait.vis, signature);
+                    }
+                }
+            }
+            Item::Impl(item_impl) => {
+                let parent = match item_impl.self_ty.as_ref() {
+                    syn::Type::Path(type_path) => type_path.path.segments.last()
+                        .map_or_else(|| module.to_string(), |segment| format!("{}::{}", module, segment.ident)),
+                    _ => module.to_string(),
+                };
+                for impl_item in &item_impl.items {
+                    if let ImplItem::Fn(method) = impl_item {
+                        let signature = method_signature(&method.attrs, method.vis.clone(), &method.sig);
+                        push_rust_item(sections, &parent, &method.sig.ident.to_string(), &method.attrs, &method.vis, signature);
+                    }
+                }
+            }
+            Item::Mod(item_mod) => {
+                if let Some((_, items)) = &item_mod.content {
+                    let path = format!("{}::{}", module, item_mod.ident);
+                    push_rust_module(sections, &path, &item_mod.attrs);
+                    collect_rust_items(sections, &path, items);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
-/// Process HTML content
-fn process_html(content: &str) -> Result<String> {
-    // Basic HTML tag removal - in a real implementation, you'd use an HTML parser
-    // like scraper or html5ever for proper parsing
-    let mut processed = content.to_string();
+/// Add a section for a module's docs, if it has any
+fn push_rust_module(sections: &mut Vec<Section>, path: &str, attrs: &[Attribute]) {
+    let docs = rust_docs(attrs);
+    if !docs.is_empty() {
+        let mut blocks = vec![Block::Prose(path.to_string())];
+        blocks.extend(rust_doc_blocks(&docs));
+        sections.push(Section { heading: Some(path.to_string()), blocks, page: None, anchor: None });
+    }
+}
+
+/// Add a section for an item that is documented or public
+fn push_rust_item(
+    sections: &mut Vec<Section>,
+    parent: &str,
+    name: &str,
+    attrs: &[Attribute],
+    visibility: &Visibility,
+    signature: Item,
+) {
+    let docs = rust_docs(attrs);
+    if docs.is_empty() && !matches!(visibility, Visibility::Public(_)) {
+        return;
+    }
     
-    // Remove common HTML tags (very basic approach)
-    let tags_to_remove = [
-        "<script", "</script>", "<style", "</style>", "<nav", "</nav>",
-        "<header", "</header>", "<footer", "</footer>", "<aside", "</aside>",
+    let mut blocks = vec![
+        Block::Prose(format!("{}::{}", parent, name)),
+        Block::Code { language: Some("rust".to_string()), code: rust_signature(signature) },
     ];
+    blocks.extend(rust_doc_blocks(&docs));
+    sections.push(Section { heading: Some(parent.to_string()), blocks, page: None, anchor: None });
+}
+
+/// Joined text of `///` or `//!` doc attributes
+fn rust_docs(attrs: &[Attribute]) -> String {
+    let lines: Vec<String> = attrs.iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(name_value) => match &name_value.value {
+                Expr::Lit(syn::ExprLit { lit: Lit::Str(text), .. }) => Some(text.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    // `/// text` is stored as " text"
+    lines.iter()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Doc comment Markdown as blocks; untagged code blocks are Rust doctests
+fn rust_doc_blocks(docs: &str) -> Vec<Block> {
+    markdown_sections(docs).into_iter()
+        .flat_map(|section| section.blocks)
+        .map(|block| match block {
+            Block::Code { language: None, code } => Block::Code { language: Some("rust".to_string()), code },
+            block => block,
+        })
+        .collect()
+}
+
+/// Method signature as a free function item, for rendering
+fn method_signature(attrs: &[Attribute], vis: Visibility, sig: &syn::Signature) -> Item {
+    Item::Fn(syn::ItemFn {
+        attrs: attrs.to_vec(),
+        vis,
+        sig: sig.clone(),
+        block: Box::new(empty_block()),
+    })
+}
+
+fn empty_block() -> syn::Block {
+    syn::Block { brace_token: Default::default(), stmts: Vec::new() }
+}
+
+/// Render an item without its docs; empty bodies are left off
+fn rust_signature(mut item: Item) -> String {
+    if let Some(attrs) = rust_item_attrs(&mut item) {
+        attrs.retain(|attr| !attr.path().is_ident("doc"));
+    }
+    let rendered = prettyplease::unparse(&syn::File { shebang: None, attrs: Vec::new(), items: vec![item] });
+    let rendered = rendered.trim_end();
+    rendered.strip_suffix("{}").unwrap_or(rendered).trim_end().to_string()
+}
+
+fn rust_item_attrs(item: &mut Item) -> Option<&mut Vec<Attribute>> {
+    match item {
+        Item::Fn(item) => Some(&mut item.attrs),
+        Item::Struct(item) => Some(&mut item.attrs),
+        Item::Enum(item) => Some(&mut item.attrs),
+        Item::Trait(item) => Some(&mut item.attrs),
+        _ => None,
+    }
+}
+
+/// Split a Jupyter notebook into one or more sections per cell.
+///
+/// Markdown headings carry over into the breadcrumb of later cells, code
+/// cells are kept as code in the kernel's language, and each section is
+/// anchored at its 1-based cell number.
+fn notebook_sections(content: &str, include_outputs: bool) -> Result<Vec<Section>> {
+    let notebook: serde_json::Value = serde_json::from_str(content).context("Invalid notebook JSON")?;
+    let cells = notebook.get("cells")
+        .and_then(|cells| cells.as_array())
+        .context("Notebook has no cells")?;
+    let language = ["/metadata/language_info/name", "/metadata/kernelspec/language"].iter()
+        .find_map(|pointer| notebook.pointer(pointer).and_then(|language| language.as_str()))
+        .unwrap_or("python");
     
-    for tag in &tags_to_remove {
-        // This is a very basic approach - a real implementation would use proper HTML parsing
-        while let Some(start) = processed.find(tag) {
-            if let Some(end) = processed[start..].find('>') {
-                let tag_end = start + end + 1;
-                processed.drain(start..tag_end);
-                processed.insert(start, ' ');
-            } else {
-                break;
+    let mut builder = SectionBuilder::default();
+    for (index, cell) in cells.iter().enumerate() {
+        builder.start_anchor(format!("cell-{}", index + 1));
+        let source = notebook_text(cell.get("source"));
+        match cell.get("cell_type").and_then(|kind| kind.as_str()) {
+            Some("markdown") => feed_markdown(&mut builder, &source),
+            Some("code") => {
+                builder.push_code(Some(language.to_string()), &source);
+                if include_outputs {
+                    for output in cell.get("outputs").and_then(|outputs| outputs.as_array()).into_iter().flatten() {
+                        builder.push_text("Output:");
+                        builder.push_code(None, &notebook_output(output));
+                    }
+                }
             }
+            _ => builder.push_text(&source),
+        }
+    }
+    
+    Ok(builder.finish())
+}
+
+/// Notebook strings are stored either whole or as a list of lines
+fn notebook_text(value: Option<&serde_json::Value>) -> String {
+    match value {
+        Some(serde_json::Value::String(text)) => text.clone(),
+        Some(serde_json::Value::Array(lines)) => lines.iter().filter_map(|line| line.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Plain-text rendering of a code cell output
+fn notebook_output(output: &serde_json::Value) -> String {
+    match output.get("output_type").and_then(|kind| kind.as_str()) {
+        Some("stream") => notebook_text(output.get("text")),
+        Some("error") => {
+            let field = |name| output.get(name).and_then(|value: &serde_json::Value| value.as_str()).unwrap_or("");
+            format!("{}: {}", field("ename"), field("evalue"))
         }
+        _ => notebook_text(output.pointer("/data/text~1plain")),
+    }
+}
+
+/// HTTP methods that can hold an operation in an OpenAPI path item
+const OPENAPI_METHODS: &[&str] = &["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+/// Split an OpenAPI (or Swagger) document into one section per operation.
+///
+/// Each operation is headed by its method and path and anchored at its
+/// `operationId`. JSON and YAML files that are not API specs yield nothing.
+fn openapi_sections(spec: serde_json::Value) -> Vec<Section> {
+    if spec.get("openapi").or_else(|| spec.get("swagger")).is_none() {
+        debug!("Skipping JSON/YAML document that is not an OpenAPI spec");
+        return Vec::new();
+    }
+    
+    let text = |value: &serde_json::Value, pointer: &str| {
+        value.pointer(pointer).and_then(|value| value.as_str()).unwrap_or("").to_string()
+    };
+    let title = text(&spec, "/info/title");
+    let mut sections = Vec::new();
+    
+    let overview = normalize_whitespace(&format!(
+        "{} {} {}", title, text(&spec, "/info/version"), text(&spec, "/info/description")
+    ));
+    if !overview.is_empty() {
+        sections.push(Section {
+            heading: (!title.is_empty()).then(|| title.clone()),
+            blocks: vec![Block::Prose(overview)],
+            page: None,
+            anchor: None,
+        });
+    }
+    
+    let paths = spec.get("paths").and_then(|paths| paths.as_object()).into_iter().flatten();
+    for (path, path_item) in paths {
+        let shared_parameters = path_item.get("parameters");
+        for method in OPENAPI_METHODS {
+            let Some(operation) = path_item.get(*method) else {
+                continue;
+            };
+            let heading = format!("{} {}", method.to_uppercase(), path);
+            let anchor = operation.get("operationId")
+                .and_then(|id| id.as_str())
+                .map_or_else(|| heading.clone(), str::to_string);
+            let description = describe_operation(&spec, &heading, operation, shared_parameters);
+            sections.push(Section {
+                heading: Some(heading),
+                blocks: vec![Block::Prose(normalize_whitespace(&description))],
+                page: None,
+                anchor: Some(anchor),
+            });
+        }
+    }
+    
+    sections
+}
+
+/// Prose summary of an operation: description, parameters, body and responses
+fn describe_operation(
+    spec: &serde_json::Value,
+    heading: &str,
+    operation: &serde_json::Value,
+    shared_parameters: Option<&serde_json::Value>,
+) -> String {
+    let field = |value: &serde_json::Value, name: &str| {
+        value.get(name).and_then(|value| value.as_str()).unwrap_or("").to_string()
+    };
+    let mut parts = vec![heading.to_string(), field(operation, "operationId")];
+    
+    let tags: Vec<&str> = operation.get("tags").and_then(|tags| tags.as_array()).into_iter().flatten()
+        .filter_map(|tag| tag.as_str())
+        .collect();
+    if !tags.is_empty() {
+        parts.push(format!("Tags: {}.", tags.join(", ")));
+    }
+    parts.push(field(operation, "summary"));
+    parts.push(field(operation, "description"));
+    
+    let parameters = [shared_parameters, operation.get("parameters")].into_iter()
+        .flatten()
+        .filter_map(|parameters| parameters.as_array())
+        .flatten()
+        .map(|parameter| openapi_resolve(spec, parameter));
+    for parameter in parameters {
+        let required = if parameter.get("required").and_then(|required| required.as_bool()) == Some(true) {
+            ", required"
+        } else {
+            ""
+        };
+        let schema = parameter.get("schema").unwrap_or(parameter);
+        parts.push(format!(
+            "Parameter {} ({}{}, {}): {}",
+            field(parameter, "name"), field(parameter, "in"), required,
+            openapi_schema_name(schema), field(parameter, "description")
+        ));
+    }
+    
+    if let Some(body) = operation.get("requestBody").map(|body| openapi_resolve(spec, body)) {
+        let content = body.get("content").and_then(|content| content.as_object()).into_iter().flatten();
+        for (media_type, media) in content {
+            let schema = media.get("schema").map_or_else(String::new, openapi_schema_name);
+            parts.push(format!("Request body ({}): {}", media_type, schema));
+        }
+        parts.push(field(body, "description"));
+    }
+    
+    let responses = operation.get("responses").and_then(|responses| responses.as_object()).into_iter().flatten();
+    for (status, response) in responses {
+        parts.push(format!("Response {}: {}", status, field(openapi_resolve(spec, response), "description")));
     }
     
-    // Remove remaining HTML tags
-    processed = regex::Regex::new(r"<[^>]*>")
-        .map_err(|e| anyhow!("Regex error: {}", e))?
-        .replace_all(&processed, " ")
-        .to_string();
+    parts.retain(|part| !part.is_empty());
+    parts.join("\n")
+}
+
+/// Follow a local `$ref` (e.g. `#/components/parameters/Id`) within the spec
+fn openapi_resolve<'a>(spec: &'a serde_json::Value, value: &'a serde_json::Value) -> &'a serde_json::Value {
+    value.get("$ref")
+        .and_then(|reference| reference.as_str())
+        .and_then(|reference| reference.strip_prefix('#'))
+        .and_then(|pointer| spec.pointer(pointer))
+        .unwrap_or(value)
+}
+
+/// Short name of a schema: its `$ref` target, type, or item type for arrays
+fn openapi_schema_name(schema: &serde_json::Value) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(|reference| reference.as_str()) {
+        return reference.rsplit('/').next().unwrap_or(reference).to_string();
+    }
+    match schema.get("type").and_then(|kind| kind.as_str()) {
+        Some("array") => match schema.get("items") {
+            Some(items) => format!("array of {}", openapi_schema_name(items)),
+            None => "array".to_string(),
+        },
+        Some(kind) => kind.to_string(),
+        None => "object".to_string(),
+    }
+}
+
+/// Split a CSV file into groups of rows that fit in one chunk.
+///
+/// Every value is labelled with its column name, the header row becomes the
+/// heading, and each group is anchored at its 1-based data row range.
+fn csv_sections(content: &str, chunk_size: usize, tokenizer: &dyn Tokenizer) -> Result<Vec<Section>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers: Vec<String> = reader.headers()
+        .context("Failed to read CSV header")?
+        .iter()
+        .map(|header| header.trim().to_string())
+        .collect();
+    let heading = headers.join(", ");
     
-    // Decode HTML entities (basic)
-    processed = processed
-        .replace("&amp;", "&")
-        .replace("&lt;", "<")
-        .replace("&gt;", ">")
-        .replace("&quot;", "\"")
-        .replace("&#39;", "'")
-        .replace("&nbsp;", " ");
+    let mut sections = Vec::new();
+    let mut rows: Vec<String> = Vec::new();
+    let mut first_row = 1;
+    let mut tokens = 0;
+    for (index, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Malformed CSV row {}", index + 1))?;
+        let row = record.iter()
+            .enumerate()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .map(|(column, value)| match headers.get(column) {
+                Some(header) => format!("{}: {}", header, value.trim()),
+                None => value.trim().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let row = format!("Row {}: {}.", index + 1, row);
+        let row_tokens = tokenizer.count(&row);
+        
+        if !rows.is_empty() && tokens + row_tokens > chunk_size {
+            sections.push(csv_section(&heading, &rows, first_row));
+            first_row = index + 1;
+            rows.clear();
+            tokens = 0;
+        }
+        rows.push(row);
+        tokens += row_tokens;
+    }
+    if !rows.is_empty() {
+        sections.push(csv_section(&heading, &rows, first_row));
+    }
     
-    Ok(normalize_whitespace(&processed))
+    Ok(sections)
 }
 
-/// Process plain text content
-fn process_plain_text(content: &str) -> String {
-    normalize_whitespace(content)
+fn csv_section(heading: &str, rows: &[String], first_row: usize) -> Section {
+    Section {
+        heading: (!heading.is_empty()).then(|| heading.to_string()),
+        blocks: vec![Block::Prose(normalize_whitespace(&rows.join(" ")))],
+        page: None,
+        anchor: Some(format!("rows-{}-{}", first_row, first_row + rows.len() - 1)),
+    }
 }
 
-/// Strip YAML frontmatter from markdown content
-fn strip_frontmatter(content: &str) -> &str {
-    if content.starts_with("---") {
-        if let Some(end) = content[3..].find("---") {
+/// Split YAML frontmatter from markdown content, returning it and the body
+fn split_frontmatter(content: &str) -> (Option<&str>, &str) {
+    if let Some(rest) = content.strip_prefix("---") {
+        if let Some(end) = rest.find("---") {
             let frontmatter_end = end + 6; // 3 for first "---" + 3 for second "---"
             if frontmatter_end < content.len() {
-                return &content[frontmatter_end..];
+                return (Some(&rest[..end]), &content[frontmatter_end..]);
             }
         }
     }
-    content
+    (None, content)
+}
+
+/// Metadata of a file, taken from its front matter or HTML head where present.
+///
+/// `sections` supply the fallback title; `git_commit` is recorded as given.
+fn doc_metadata(
+    content: &[u8],
+    file_path: &Path,
+    processor: Processor,
+    sections: &[Section],
+    git_commit: Option<&str>,
+) -> DocMetadata {
+    let mut metadata = DocMetadata {
+        file_type: processor.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string()),
+        modified: fs::metadata(file_path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|age| age.as_secs()),
+        git_commit: git_commit.map(str::to_string),
+        ..DocMetadata::default()
+    };
+    
+    match (processor, std::str::from_utf8(content)) {
+        (Processor::Markdown, Ok(text)) => {
+            if let Some(frontmatter) = split_frontmatter(text).0 {
+                frontmatter_metadata(frontmatter, &mut metadata);
+            }
+        }
+        (Processor::Html, Ok(text)) => html_metadata(text, &mut metadata),
+        _ => {}
+    }
+    
+    // Top of the first heading breadcrumb, else the file name
+    metadata.title = metadata.title
+        .or_else(|| sections.iter()
+            .find_map(|section| section.heading.as_deref())
+            .and_then(|heading| heading.split(" > ").next())
+            .map(str::to_string))
+        .or_else(|| file_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()));
+    metadata
+}
+
+/// Fill `metadata` from YAML front matter; invalid front matter is ignored
+fn frontmatter_metadata(frontmatter: &str, metadata: &mut DocMetadata) {
+    let Ok(serde_yaml::Value::Mapping(fields)) = serde_yaml::from_str(frontmatter) else {
+        return;
+    };
+    let scalar = |key: &str| fields.get(key).and_then(yaml_scalar);
+    
+    metadata.title = scalar("title");
+    metadata.language = scalar("lang").or_else(|| scalar("language"));
+    metadata.version = scalar("version");
+    // Either a list or a comma-separated string
+    metadata.tags = match fields.get("tags") {
+        Some(serde_yaml::Value::Sequence(tags)) => tags.iter().filter_map(yaml_scalar).collect(),
+        Some(tags) => yaml_scalar(tags)
+            .map(|tags| tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+}
+
+/// Text of a scalar YAML value; numbers keep their YAML form, e.g. `version: 2`
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(text) => Some(text.trim().to_string()).filter(|text| !text.is_empty()),
+        serde_yaml::Value::Number(number) => Some(number.to_string()),
+        serde_yaml::Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// Fill `metadata` from the HTML `<title>` and `<html lang>`
+fn html_metadata(content: &str, metadata: &mut DocMetadata) {
+    let document = Html::parse_document(content);
+    let title = Selector::parse("head > title").expect("valid title selector");
+    metadata.title = document.select(&title)
+        .next()
+        .map(|title| normalize_whitespace(&title.text().collect::<String>()))
+        .filter(|title| !title.is_empty());
+    metadata.language = document.root_element()
+        .attr("lang")
+        .map(str::trim)
+        .filter(|lang| !lang.is_empty())
+        .map(str::to_string);
+}
+
+/// HEAD commit of the Git repository containing the docs directory, if any
+fn docs_commit(docs_dir: &Path) -> Option<String> {
+    let repo = Repository::discover(docs_dir).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
 }
 
 /// Normalize whitespace in text
@@ -378,60 +1808,30 @@ fn normalize_whitespace(text: &str) -> String {
         .to_string()
 }
 
-/// Create chunks from processed content
-fn create_chunks(
-    content: &str,
-    source: &str,
-    chunk_size: usize,
-    chunk_overlap: usize,
-) -> Result<Vec<Chunk>> {
-    if content.is_empty() {
-        return Ok(Vec::new());
-    }
-    
-    if chunk_size <= chunk_overlap {
-        return Err(anyhow!("chunk_size must be greater than chunk_overlap"));
-    }
-    
-    let tokens: Vec<&str> = content.split_whitespace().collect();
-    
-    if tokens.is_empty() {
-        return Ok(Vec::new());
-    }
-    
+/// Create chunks from document sections.
+///
+/// Each section is split by `chunker`, so chunks never span sections and
+/// carry their section's heading.
+fn create_chunks(sections: &[Section], source: &str, chunker: &dyn Chunker) -> Result<Vec<Chunk>> {
     let mut chunks = Vec::new();
-    let mut start = 0;
-    let mut chunk_index = 0;
-    
-    while start < tokens.len() {
-        let end = std::cmp::min(start + chunk_size, tokens.len());
-        let chunk_tokens = &tokens[start..end];
-        let chunk_text = chunk_tokens.join(" ");
-        
-        // Skip very short chunks (less than 10 tokens)
-        if chunk_tokens.len() >= 10 {
-            let chunk = Chunk {
+    for section in sections {
+        let section_source = match &section.anchor {
+            Some(anchor) => format!("{}#{}", source, anchor),
+            None => source.to_string(),
+        };
+        for text in chunker.split(&section.blocks)? {
+            let chunk_index = chunks.len();
+            chunks.push(Chunk {
                 id: format!("{}:chunk{}", source, chunk_index),
-                text: chunk_text,
-                source: source.to_string(),
-                heading: None, // TODO: Extract headings from content
+                text,
+                source: section_source.clone(),
+                heading: section.heading.clone(),
                 position: chunk_index,
-            };
-            
-            chunks.push(chunk);
-            chunk_index += 1;
+                page: section.page,
+                alternate_sources: Vec::new(),
+                metadata: DocMetadata::default(),
+            });
         }
-        
-        // Move start position forward
-        if end >= tokens.len() {
-            break;
-        }
-        
-        start = if chunk_size > chunk_overlap {
-            start + chunk_size - chunk_overlap
-        } else {
-            start + 1
-        };
     }
     
     Ok(chunks)
@@ -440,6 +1840,91 @@ fn create_chunks(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tokenizer::Words;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    
+    const TEST_OPTIONS: ExtractOptions = ExtractOptions { chunk_size: 500, tokenizer: &Words, notebook_outputs: false };
+    
+    fn windows(chunk_size: usize, chunk_overlap: usize) -> chunking::TokenWindows {
+        chunking::TokenWindows { tokenizer: Arc::new(Words), chunk_size, chunk_overlap }
+    }
+    
+    /// Write `files` into the upstream working tree and commit them on `main`
+    fn commit_files(repo: &Repository, files: &[(&str, &str)], message: &str) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        let mut index = repo.index().unwrap();
+        for (path, content) in files {
+            let full_path = workdir.join(path);
+            fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            fs::write(&full_path, content).unwrap();
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.refname_to_id("refs/heads/main").ok()
+            .map(|oid| repo.find_commit(oid).unwrap());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("refs/heads/main"), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+    
+    #[test]
+    fn test_sync_docs_sparse_clone_and_fast_forward() {
+        let upstream_dir = TempDir::new().unwrap();
+        let upstream = Repository::init(upstream_dir.path()).unwrap();
+        commit_files(&upstream, &[
+            ("README.md", "# Project"),
+            ("docs/guide.md", "# Guide v1"),
+            ("src/lib.rs", "pub fn f() {}"),
+        ], "initial");
+        
+        let docs_parent = TempDir::new().unwrap();
+        let docs_dir = docs_parent.path().join("docs-checkout");
+        let source = DocsSource {
+            url: upstream_dir.path().to_string_lossy().to_string(),
+            branch: "main".to_string(),
+            sparse_path: Some("docs".to_string()),
+            depth: None,
+        };
+        
+        sync_docs(&docs_dir, Some(&source)).unwrap();
+        assert_eq!(fs::read_to_string(docs_dir.join("docs/guide.md")).unwrap(), "# Guide v1");
+        assert!(!docs_dir.join("README.md").exists());
+        assert!(!docs_dir.join("src/lib.rs").exists());
+        
+        // A new upstream commit is picked up by the next sync
+        let head = commit_files(&upstream, &[
+            ("docs/guide.md", "# Guide v2"),
+            ("docs/faq.md", "# FAQ"),
+            ("README.md", "# Project v2"),
+        ], "update docs");
+        
+        sync_docs(&docs_dir, Some(&source)).unwrap();
+        assert_eq!(fs::read_to_string(docs_dir.join("docs/guide.md")).unwrap(), "# Guide v2");
+        assert!(docs_dir.join("docs/faq.md").exists());
+        assert!(!docs_dir.join("README.md").exists());
+        
+        let checkout = Repository::open(&docs_dir).unwrap();
+        assert_eq!(checkout.head().unwrap().target(), Some(head));
+    }
+    
+    #[test]
+    fn test_sync_docs_rejects_non_empty_plain_directory() {
+        let docs_dir = TempDir::new().unwrap();
+        fs::write(docs_dir.path().join("notes.md"), "local notes").unwrap();
+        let source = DocsSource {
+            url: "/nonexistent".to_string(),
+            branch: "main".to_string(),
+            sparse_path: None,
+            depth: None,
+        };
+        
+        assert!(sync_docs(docs_dir.path(), Some(&source)).is_err());
+        assert!(docs_dir.path().join("notes.md").exists());
+    }
     
     #[test]
     fn test_normalize_whitespace() {
@@ -449,16 +1934,55 @@ mod tests {
     }
     
     #[test]
-    fn test_strip_frontmatter() {
+    fn test_split_frontmatter() {
         let input = "---\ntitle: Test\n---\n\nContent here";
         let expected = "\n\nContent here";
-        assert_eq!(strip_frontmatter(input), expected);
+        assert_eq!(split_frontmatter(input), (Some("\ntitle: Test\n"), expected));
+        assert_eq!(split_frontmatter("No front matter"), (None, "No front matter"));
+    }
+    
+    #[test]
+    fn test_markdown_metadata_from_frontmatter() {
+        let input = "---\ntitle: Getting Started\nlang: en\ntags: [setup, cli]\nversion: 2\n---\n\n# Install\n\nRun it.";
+        let sections = markdown_sections(input);
+        let metadata = doc_metadata(input.as_bytes(), Path::new("guide.md"), Processor::Markdown, &sections, Some("abc123"));
+        assert_eq!(metadata, DocMetadata {
+            title: Some("Getting Started".to_string()),
+            file_type: "markdown".to_string(),
+            language: Some("en".to_string()),
+            modified: None,
+            git_commit: Some("abc123".to_string()),
+            tags: vec!["setup".to_string(), "cli".to_string()],
+            version: Some("2".to_string()),
+        });
+        
+        // Comma-separated tags, and the title falls back to the first heading
+        let input = "---\ntags: setup, cli\n---\n\n# Install\n\n## Linux\n\nRun it.";
+        let sections = markdown_sections(input);
+        let metadata = doc_metadata(input.as_bytes(), Path::new("guide.md"), Processor::Markdown, &sections, None);
+        assert_eq!(metadata.title.as_deref(), Some("Install"));
+        assert_eq!(metadata.tags, vec!["setup", "cli"]);
+        
+        let metadata = doc_metadata(b"plain", Path::new("notes/readme.txt"), Processor::Text, &unsectioned("plain"), None);
+        assert_eq!(metadata.title.as_deref(), Some("readme"));
+        assert_eq!(metadata.file_type, "text");
+    }
+    
+    #[test]
+    fn test_html_metadata_from_head() {
+        let input = r#"<html lang="de"><head><title>Vec in std::vec - Rust</title></head>
+            <body><main><h1>Struct Vec</h1><p>A growable array.</p></main></body></html>"#;
+        let sections = html_sections(input);
+        let metadata = doc_metadata(input.as_bytes(), Path::new("vec.html"), Processor::Html, &sections, None);
+        assert_eq!(metadata.title.as_deref(), Some("Vec in std::vec - Rust"));
+        assert_eq!(metadata.language.as_deref(), Some("de"));
     }
     
     #[test]
     fn test_create_chunks() {
         let content = "This is a test content with many words that should be split into chunks";
-        let chunks = create_chunks(content, "test.md", 5, 1).unwrap();
+        let sections = unsectioned(content);
+        let chunks = create_chunks(&sections, "test.md", &windows(5, 1)).unwrap();
         
         assert!(!chunks.is_empty());
         assert_eq!(chunks[0].source, "test.md");
@@ -467,12 +1991,51 @@ mod tests {
     }
     
     #[test]
-    fn test_is_supported_file() {
-        assert!(is_supported_file(Path::new("test.md")));
-        assert!(is_supported_file(Path::new("test.html")));
-        assert!(is_supported_file(Path::new("test.txt")));
-        assert!(!is_supported_file(Path::new("test.pdf")));
-        assert!(!is_supported_file(Path::new("test.jpg")));
+    fn test_chunk_file() {
+        assert_eq!(chunk_file("guide/intro.md:chunk12"), "guide/intro.md");
+        assert_eq!(chunk_file("notes:chunky.md:chunk0"), "notes:chunky.md");
+    }
+    
+    #[test]
+    fn test_markdown_sections_carry_heading_breadcrumbs() {
+        let markdown = "Intro text.\n\n# User Guide\n\nWelcome.\n\n## Troubleshooting\n\n\
+                        ### Common Issues\n\nCheck the `port`.\n\n## Best Practices\n\nBack up.";
+        let sections = markdown_sections(markdown);
+        
+        let headings: Vec<Option<&str>> = sections.iter().map(|s| s.heading.as_deref()).collect();
+        assert_eq!(headings, vec![
+            None,
+            Some("User Guide"),
+            Some("User Guide > Troubleshooting > Common Issues"),
+            Some("User Guide > Best Practices"),
+        ]);
+        assert_eq!(sections[0].blocks, vec![Block::Prose("Intro text.".to_string())]);
+        assert_eq!(sections[2].blocks, vec![
+            Block::Prose("Common Issues".to_string()),
+            Block::Prose("Check the port.".to_string()),
+        ]);
+    }
+    
+    #[test]
+    fn test_oversized_sections_fall_back_to_token_windows() {
+        let long_text = (0..25).map(|i| format!("word{}", i)).collect::<Vec<_>>().join(" ");
+        let markdown = format!("# Short\n\nTiny section.\n\n# Long\n\n{}", long_text);
+        let sections = markdown_sections(&markdown);
+        let chunks = create_chunks(&sections, "guide.md", &windows(10, 2)).unwrap();
+        
+        // The short section is kept whole despite being under 10 tokens
+        assert_eq!(chunks[0].text, "Short\n\nTiny section.");
+        assert_eq!(chunks[0].heading.as_deref(), Some("Short"));
+        
+        let long_chunks = &chunks[1..];
+        assert_eq!(long_chunks.len(), 3);
+        assert!(long_chunks.iter().all(|c| c.heading.as_deref() == Some("Long")));
+        assert!(long_chunks[0].text.starts_with("Long\n\nword0"));
+        assert!(long_chunks[1].text.starts_with("word7 word8"));
+        assert!(long_chunks[2].text.ends_with("word24"));
+        let positions: Vec<usize> = chunks.iter().map(|c| c.position).collect();
+        assert_eq!(positions, vec![0, 1, 2, 3]);
+        assert_eq!(chunks[3].id, "guide.md:chunk3");
     }
     
     #[test]
@@ -489,7 +2052,14 @@ mod tests {
     #[test]
     fn test_process_markdown() {
         let markdown = "# Header\n\nThis is **bold** text with `code`.\n\n```rust\nfn main() {}\n```\n\nMore text.";
-        let processed = process_markdown(markdown).unwrap();
+        let processed = markdown_sections(markdown).into_iter()
+            .flat_map(|section| section.blocks)
+            .filter_map(|block| match block {
+                Block::Prose(prose) => Some(prose),
+                Block::Code { .. } => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
         
         assert!(!processed.contains('#'));
         assert!(!processed.contains("**"));
@@ -498,4 +2068,447 @@ mod tests {
         assert!(processed.contains("bold"));
         assert!(processed.contains("code"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_code_blocks_are_kept_verbatim() {
+        let markdown = "# API\n\nCall it like this:\n\n```rust\nfn main() {\n    app::start();\n}\n```\n\n\
+                        Or indented:\n\n    $ app  start\n\nDone.";
+        let sections = markdown_sections(markdown);
+        assert_eq!(sections[0].blocks, vec![
+            Block::Prose("API".to_string()),
+            Block::Prose("Call it like this:".to_string()),
+            Block::Code {
+                language: Some("rust".to_string()),
+                code: "fn main() {\n    app::start();\n}".to_string(),
+            },
+            Block::Prose("Or indented:".to_string()),
+            Block::Code { language: None, code: "$ app  start".to_string() },
+            Block::Prose("Done.".to_string()),
+        ]);
+        
+        let chunks = create_chunks(&sections, "api.md", &windows(500, 50)).unwrap();
+        assert_eq!(chunks[0].text, "API\n\nCall it like this:\n\n```rust\nfn main() {\n    app::start();\n}\n```\n\n\
+                                    Or indented:\n\n```\n$ app  start\n```\n\nDone.");
+    }
+    
+    #[test]
+    fn test_code_blocks_split_only_over_hard_limit() {
+        let code = (0..6).map(|i| format!("let x{} = {};", i, i)).collect::<Vec<_>>().join("\n");
+        let markdown = format!("Before the code block.\n\n```rust\n{}\n```\n\nAfter.", code);
+        let sections = markdown_sections(&markdown);
+        
+        // 24 code tokens fit within twice the chunk size, so the block stays whole
+        let chunks = create_chunks(&sections, "a.md", &windows(16, 2)).unwrap();
+        let with_code: Vec<&Chunk> = chunks.iter().filter(|c| c.text.contains("```")).collect();
+        assert_eq!(with_code.len(), 1);
+        assert!(with_code[0].text.contains(&code));
+        
+        // Beyond the limit the block is split at line boundaries, each piece fenced
+        let chunks = create_chunks(&sections, "a.md", &windows(10, 2)).unwrap();
+        let pieces: Vec<&str> = chunks.iter()
+            .filter_map(|c| c.text.split("```rust\n").nth(1))
+            .map(|rest| rest.split("\n```").next().unwrap())
+            .collect();
+        assert!(pieces.len() > 1);
+        assert!(pieces.iter().all(|piece| piece.lines().all(|line| line.ends_with(';'))));
+        assert!(pieces.iter().any(|piece| piece.starts_with("let x0")));
+        assert!(pieces.iter().any(|piece| piece.ends_with("let x5 = 5;")));
+    }
+    
+    #[test]
+    fn test_html_sections_extract_rustdoc_main_content() {
+        let html = r##"<!DOCTYPE html><html><head><title>Config</title><style>body { color: red }</style></head>
+            <body><nav class="sidebar"><a href="../index.html">crate</a><ul><li>Sidebar item</li></ul></nav>
+            <main><div class="width-limiter"><section id="main-content" class="content">
+              <div class="main-heading"><h1>Struct <a href="">Config</a><button id="copy-path">Copy item path</button></h1>
+              <span class="sub-heading"><a class="src" href="../src/config.rs.html">Source</a></span></div>
+              <p>Settings for the <code>app</code> &mdash; loaded at&nbsp;startup &amp; cached.</p>
+              <h2 id="fields">Fields<a href="#fields" class="anchor">&sect;</a></h2>
+              <h3>port: <code>u16</code></h3><div class="docblock"><p>Port to listen on.</p>
+              <pre class="rust rust-example-rendered"><code><span class="kw">let</span> port = <span>8080</span>;
+println!(<span class="string">"{port}"</span>);</code></pre></div>
+            </section></div></main><footer>Generated by rustdoc</footer></body></html>"##;
+        let sections = html_sections(html);
+        
+        let headings: Vec<Option<&str>> = sections.iter().map(|s| s.heading.as_deref()).collect();
+        assert_eq!(headings, vec![Some("Struct Config"), Some("Struct Config > Fields > port: u16")]);
+        assert_eq!(sections[0].blocks, vec![
+            Block::Prose("Struct Config".to_string()),
+            Block::Prose("Settings for the app \u{2014} loaded at startup & cached.".to_string()),
+        ]);
+        assert_eq!(sections[1].blocks, vec![
+            Block::Prose("port: u16".to_string()),
+            Block::Prose("Port to listen on.".to_string()),
+            Block::Code {
+                language: Some("rust".to_string()),
+                code: "let port = 8080;\nprintln!(\"{port}\");".to_string(),
+            },
+        ]);
+        
+        let text: String = format!("{:?}", sections);
+        for noise in ["Sidebar item", "Copy item path", "Source", "Generated by rustdoc", "color: red", "\u{a7}"] {
+            assert!(!text.contains(noise), "{:?} leaked into {}", noise, text);
+        }
+    }
+    
+    #[test]
+    fn test_html_sections_fall_back_to_body() {
+        let html = "<html><body><header>Site title</header><h1>Guide</h1>\
+                    <p>Line one<br>line two</p><pre><code class=\"language-toml\">port = 8080</code></pre>\
+                    <script>track()</script></body></html>";
+        let sections = html_sections(html);
+        
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].heading.as_deref(), Some("Guide"));
+        assert_eq!(sections[0].blocks, vec![
+            Block::Prose("Guide".to_string()),
+            Block::Prose("Line one line two".to_string()),
+            Block::Code { language: Some("toml".to_string()), code: "port = 8080".to_string() },
+        ]);
+    }
+    
+    #[test]
+    fn test_rst_sections_follow_titles_and_strip_markup() {
+        let rst = r#"
+=========
+ Project
+=========
+
+.. _install:
+
+Installation
+============
+
+Install with **pip** (see :ref:`the FAQ <faq>` and `PyPI <https://pypi.org>`_)::
+
+    pip install project
+
+.. note::
+   Requires :py:func:`~project.setup` first [#]_.
+
+.. code-block:: python
+   :linenos:
+
+   import project
+   project.setup(debug=True)
+
+.. toctree::
+   :maxdepth: 2
+
+   usage
+   api
+
+.. This comment is not indexed.
+
+Configuration
+-------------
+
+Set ``PROJECT_HOME``.
+
+====  =======
+Key   Meaning
+====  =======
+"#;
+        let sections = rst_sections(rst);
+        
+        let headings: Vec<Option<&str>> = sections.iter().map(|s| s.heading.as_deref()).collect();
+        assert_eq!(headings, vec![
+            Some("Project > Installation"),
+            Some("Project > Installation > Configuration"),
+        ]);
+        assert_eq!(sections[0].blocks, vec![
+            Block::Prose("Installation".to_string()),
+            Block::Prose("Install with pip (see the FAQ and PyPI):".to_string()),
+            Block::Code { language: None, code: "pip install project".to_string() },
+            Block::Prose("Requires project.setup first.".to_string()),
+            Block::Code {
+                language: Some("python".to_string()),
+                code: "import project\nproject.setup(debug=True)".to_string(),
+            },
+        ]);
+        assert_eq!(sections[1].blocks, vec![
+            Block::Prose("Configuration".to_string()),
+            Block::Prose("Set PROJECT_HOME.".to_string()),
+            Block::Prose("Key Meaning".to_string()),
+        ]);
+    }
+    
+    #[test]
+    fn test_rst_title_levels_follow_first_use() {
+        let rst = "Guide\n*****\n\nIntro.\n\nSetup\n=====\n\nSteps.\n\nDetails\n-------\n\nMore.\n\n\
+                   Usage\n=====\n\nRun it.\n\n----------\n\nEnd.";
+        let headings: Vec<Option<String>> = rst_sections(rst).into_iter().map(|s| s.heading).collect();
+        assert_eq!(headings, vec![
+            Some("Guide".to_string()),
+            Some("Guide > Setup".to_string()),
+            Some("Guide > Setup > Details".to_string()),
+            Some("Guide > Usage".to_string()),
+        ]);
+    }
+    
+    /// Minimal PDF with one line of Helvetica text per page
+    fn pdf_with_pages(pages: &[&str]) -> Vec<u8> {
+        let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 4 + 2 * i)).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+        for (i, text) in pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                5 + 2 * i
+            ));
+            let stream = format!("BT /F1 12 Tf 72 720 Td ({}) Tj ET", text);
+            objects.push(format!("<< /Length {} >>\nstream\n{}\nendstream", stream.len(), stream));
+        }
+        
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            pdf.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend(format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1, xref
+        ).as_bytes());
+        pdf
+    }
+    
+    #[test]
+    fn test_pdf_chunks_record_page_numbers() {
+        let pdf = pdf_with_pages(&["Vendor specification overview", "", "Timeout defaults to 30 seconds"]);
+        let sections = process_file_content(&pdf, Path::new("spec.pdf"), Processor::Pdf, TEST_OPTIONS).unwrap();
+        let chunks = create_chunks(&sections, "spec.pdf", &windows(500, 50)).unwrap();
+        
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, "Vendor specification overview");
+        assert_eq!(chunks[0].page, Some(1));
+        assert_eq!(chunks[1].text, "Timeout defaults to 30 seconds");
+        assert_eq!(chunks[1].citation(), "spec.pdf p.3");
+        
+        assert!(process_file_content(b"%PDF-1.4 truncated", Path::new("broken.pdf"), Processor::Pdf, TEST_OPTIONS).is_err());
+    }
+    
+    #[test]
+    fn test_rust_module_path() {
+        assert_eq!(rust_module_path(Path::new("docs/my-crate/src/lib.rs")), "my_crate");
+        assert_eq!(rust_module_path(Path::new("my-crate/src/net/mod.rs")), "my_crate::net");
+        assert_eq!(rust_module_path(Path::new("./src/net/tcp.rs")), "crate::net::tcp");
+        assert_eq!(rust_module_path(Path::new("examples/demo.rs")), "crate::demo");
+    }
+    
+    #[test]
+    fn test_rust_sections_pair_docs_with_signatures() {
+        let source = r#"
+//! Server configuration.
+
+/// Settings for the server.
+#[derive(Debug)]
+pub struct Config {
+    /// Port to listen on
+    pub port: u16,
+}
+
+impl Config {
+    /// Create a config listening on `port`.
+    ///
+    /// ```
+    /// let config = Config::new(8080);
+    /// ```
+    pub fn new(port: u16) -> Self {
+        Self { port }
+    }
+
+    fn internal(&self) {}
+}
+
+/// Something that can be started.
+pub trait Service {
+    /// Start serving.
+    fn start(&self) -> Result<(), String>;
+}
+
+mod detail {
+    pub fn helper() {}
+}
+"#;
+        let sections = rust_sections(source, Path::new("server/src/config.rs")).unwrap();
+        let paths: Vec<&Block> = sections.iter().map(|section| &section.blocks[0]).collect();
+        assert_eq!(paths, vec![
+            &Block::Prose("server::config".to_string()),
+            &Block::Prose("server::config::Config".to_string()),
+            &Block::Prose("server::config::Config::new".to_string()),
+            &Block::Prose("server::config::Service".to_string()),
+            &Block::Prose("server::config::Service::start".to_string()),
+            &Block::Prose("server::config::detail::helper".to_string()),
+        ]);
+        
+        assert_eq!(sections[1].heading.as_deref(), Some("server::config"));
+        assert_eq!(sections[1].blocks[1], Block::Code {
+            language: Some("rust".to_string()),
+            code: "#[derive(Debug)]\npub struct Config {\n    /// Port to listen on\n    pub port: u16,\n}".to_string(),
+        });
+        assert_eq!(sections[1].blocks[2], Block::Prose("Settings for the server.".to_string()));
+        
+        let new = &sections[2];
+        assert_eq!(new.heading.as_deref(), Some("server::config::Config"));
+        assert_eq!(new.blocks[1..], [
+            Block::Code { language: Some("rust".to_string()), code: "pub fn new(port: u16) -> Self".to_string() },
+            Block::Prose("Create a config listening on port.".to_string()),
+            Block::Code { language: Some("rust".to_string()), code: "let config = Config::new(8080);".to_string() },
+        ]);
+        
+        assert_eq!(sections[3].blocks[1], Block::Code {
+            language: Some("rust".to_string()),
+            code: "pub trait Service".to_string(),
+        });
+        assert_eq!(sections[4].blocks[1], Block::Code {
+            language: Some("rust".to_string()),
+            code: "fn start(&self) -> Result<(), String>".to_string(),
+        });
+        
+        assert!(rust_sections("fn broken(", Path::new("src/lib.rs")).is_err());
+    }
+    
+    #[test]
+    fn test_notebook_sections_follow_cells() {
+        let notebook = r###"{
+            "metadata": {"kernelspec": {"language": "python"}},
+            "cells": [
+                {"cell_type": "markdown", "source": ["# Loading data\n", "Read the CSV first."]},
+                {"cell_type": "code", "source": "import pandas as pd\ndf = pd.read_csv('data.csv')",
+                 "outputs": [{"output_type": "stream", "name": "stdout", "text": ["3 rows\n"]}]},
+                {"cell_type": "markdown", "source": "## Cleaning"},
+                {"cell_type": "code", "source": ["df = df.dropna()"],
+                 "outputs": [{"output_type": "error", "ename": "KeyError", "evalue": "'x'", "traceback": []}]}
+            ]
+        }"###;
+        let sections = notebook_sections(notebook, false).unwrap();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].heading.as_deref(), Some("Loading data"));
+        assert_eq!(sections[0].anchor.as_deref(), Some("cell-1"));
+        assert_eq!(sections[1].heading.as_deref(), Some("Loading data"));
+        assert_eq!(sections[1].blocks, vec![Block::Code {
+            language: Some("python".to_string()),
+            code: "import pandas as pd\ndf = pd.read_csv('data.csv')".to_string(),
+        }]);
+        assert_eq!(sections[2].heading.as_deref(), Some("Loading data > Cleaning"));
+        assert_eq!(sections[2].anchor.as_deref(), Some("cell-4"));
+        
+        let chunks = create_chunks(&sections, "analysis.ipynb", &windows(500, 50)).unwrap();
+        assert_eq!(chunks[1].source, "analysis.ipynb#cell-2");
+        
+        let with_outputs = notebook_sections(notebook, true).unwrap();
+        assert_eq!(with_outputs[1].blocks[1..], [
+            Block::Prose("Output:".to_string()),
+            Block::Code { language: None, code: "3 rows".to_string() },
+        ]);
+        assert_eq!(with_outputs[2].blocks.last(), Some(&Block::Code {
+            language: None,
+            code: "KeyError: 'x'".to_string(),
+        }));
+    }
+    
+    #[test]
+    fn test_openapi_sections_describe_operations() {
+        let spec = r#"
+openapi: 3.0.0
+info:
+  title: Pet Store
+  version: 1.0.0
+paths:
+  /pets/{petId}:
+    parameters:
+      - $ref: '#/components/parameters/PetId'
+    get:
+      operationId: getPet
+      tags: [pets]
+      summary: Fetch a pet
+      parameters:
+        - name: fields
+          in: query
+          schema:
+            type: array
+            items:
+              type: string
+      responses:
+        '200':
+          description: The pet
+        '404':
+          description: No such pet
+    delete:
+      summary: Remove a pet
+      responses:
+        '204':
+          description: Deleted
+components:
+  parameters:
+    PetId:
+      name: petId
+      in: path
+      required: true
+      description: Pet identifier
+      schema:
+        type: integer
+"#;
+        let sections = process_file_content(spec.as_bytes(), Path::new("api.yaml"), Processor::Openapi, TEST_OPTIONS).unwrap();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].heading.as_deref(), Some("Pet Store"));
+        
+        let get = &sections[1];
+        assert_eq!(get.heading.as_deref(), Some("GET /pets/{petId}"));
+        assert_eq!(get.anchor.as_deref(), Some("getPet"));
+        let Block::Prose(text) = &get.blocks[0] else { panic!("expected prose") };
+        assert!(text.contains("Tags: pets. Fetch a pet"));
+        assert!(text.contains("Parameter petId (path, required, integer): Pet identifier"));
+        assert!(text.contains("Parameter fields (query, array of string):"));
+        assert!(text.contains("Response 404: No such pet"));
+        
+        assert_eq!(sections[2].anchor.as_deref(), Some("DELETE /pets/{petId}"));
+        
+        let package = br#"{"name": "docs-site", "version": "1.0.0"}"#;
+        assert!(process_file_content(package, Path::new("package.json"), Processor::Openapi, TEST_OPTIONS).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_csv_sections_group_labelled_rows() {
+        let csv = "name, port, notes\nweb,8080,public\ndb,5432,\ncache,6379,in memory\n";
+        let options = ExtractOptions { chunk_size: 15, ..TEST_OPTIONS };
+        let sections = process_file_content(csv.as_bytes(), Path::new("services.csv"), Processor::Csv, options).unwrap();
+        
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].heading.as_deref(), Some("name, port, notes"));
+        assert_eq!(sections[0].anchor.as_deref(), Some("rows-1-2"));
+        assert_eq!(sections[0].blocks, vec![Block::Prose(
+            "Row 1: name: web; port: 8080; notes: public. Row 2: name: db; port: 5432.".to_string()
+        )]);
+        assert_eq!(sections[1].anchor.as_deref(), Some("rows-3-3"));
+    }
+    
+    #[test]
+    fn test_linked_files_follow_shared_duplicates() {
+        let links: HashMap<String, Vec<String>> = [
+            ("a.md", vec!["b.md"]),
+            ("b.md", vec!["a.md", "c.md"]),
+            ("c.md", vec!["b.md"]),
+            ("d.md", vec!["e.md"]),
+            ("e.md", vec!["d.md"]),
+        ]
+        .into_iter()
+        .map(|(file, linked)| (file.to_string(), linked.into_iter().map(String::from).collect()))
+        .collect();
+        
+        let changed = ["c.md".to_string(), "f.md".to_string()];
+        let mut linked: Vec<String> = linked_files(&links, &changed).into_iter().collect();
+        linked.sort();
+        assert_eq!(linked, vec!["a.md", "b.md", "c.md", "f.md"]);
+    }
+}
@@ -11,10 +11,16 @@ use log::{debug, info, warn, error};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+use crate::tokenizer::Tokenizer;
+
 /// Configuration for OpenAI API requests
 const OPENAI_API_URL: &str = "https://api.openai.com/v1/chat/completions";
-const DEFAULT_MODEL: &str = "gpt-4o";
+pub const DEFAULT_MODEL: &str = "gpt-4o";
 const DEFAULT_MAX_TOKENS: u32 = 2048;
+/// Context window of `DEFAULT_MODEL` in tokens, shared by the prompt and the answer
+const DEFAULT_CONTEXT_WINDOW: usize = 128_000;
+/// Tokens the chat format adds around each message and to prime the reply
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
 const DEFAULT_TEMPERATURE: f32 = 0.1;
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
 
@@ -70,6 +76,7 @@ struct ApiError {
 /// # Arguments
 /// * `api_key` - OpenAI API key for authentication
 /// * `prompt` - The assembled prompt string to send to the LLM
+/// * `tokenizer` - Tokenizer used to fit `max_tokens` into the context window
 ///
 /// # Returns
 /// * `Ok(String)` - The generated answer from the LLM
@@ -81,12 +88,12 @@ struct ApiError {
 /// # async fn example() -> Result<()> {
 /// let api_key = "your-api-key";
 /// let prompt = "What is Rust?";
-/// let answer = query_llm(api_key, prompt).await?;
+/// let answer = query_llm(api_key, prompt, &CharEstimate).await?;
 /// println!("Answer: {}", answer);
 /// # Ok(())
 /// # }
 /// ```
-pub async fn query_llm(api_key: &str, prompt: &str) -> Result<String> {
+pub async fn query_llm(api_key: &str, prompt: &str, tokenizer: &dyn Tokenizer) -> Result<String> {
     info!("query_llm - Starting LLM query process");
     debug!("query_llm - Received prompt with length: {} chars", prompt.len());
     
@@ -108,7 +115,7 @@ pub async fn query_llm(api_key: &str, prompt: &str) -> Result<String> {
 
     // Build the request
     debug!("query_llm - Building chat completion request");
-    let request = build_chat_request(prompt);
+    let request = build_chat_request(prompt, tokenizer)?;
     debug!("query_llm - Request built successfully: {:?}", request);
 
     // Create HTTP client
@@ -180,7 +187,7 @@ pub async fn query_llm(api_key: &str, prompt: &str) -> Result<String> {
 }
 
 /// Build a chat completion request from the prompt
-fn build_chat_request(prompt: &str) -> ChatCompletionRequest {
+fn build_chat_request(prompt: &str, tokenizer: &dyn Tokenizer) -> Result<ChatCompletionRequest> {
     debug!("build_chat_request - Creating system message");
     let system_message = Message {
         role: "system".to_string(),
@@ -193,19 +200,42 @@ fn build_chat_request(prompt: &str) -> ChatCompletionRequest {
         content: prompt.to_string(),
     };
 
+    let max_tokens = completion_budget(&[&system_message.content, &user_message.content], tokenizer)?;
     debug!("build_chat_request - Building request with model: {}, max_tokens: {}, temperature: {}", 
-           DEFAULT_MODEL, DEFAULT_MAX_TOKENS, DEFAULT_TEMPERATURE);
+           DEFAULT_MODEL, max_tokens, DEFAULT_TEMPERATURE);
     
     let request = ChatCompletionRequest {
         model: DEFAULT_MODEL.to_string(),
         messages: vec![system_message, user_message],
-        max_tokens: DEFAULT_MAX_TOKENS,
+        max_tokens,
         temperature: DEFAULT_TEMPERATURE,
         stream: false,
     };
 
     debug!("build_chat_request - Request created successfully with {} messages", request.messages.len());
-    request
+    Ok(request)
+}
+
+/// Tokens left for the answer once the messages take their share of the
+/// context window, capped at `DEFAULT_MAX_TOKENS`
+fn completion_budget(messages: &[&str], tokenizer: &dyn Tokenizer) -> Result<u32> {
+    let prompt_tokens: usize = messages.iter()
+        .map(|message| tokenizer.count(message) + MESSAGE_OVERHEAD_TOKENS)
+        .sum();
+    debug!("completion_budget - Prompt uses {} {} tokens of {}", prompt_tokens, tokenizer.name(), DEFAULT_CONTEXT_WINDOW);
+    
+    let available = DEFAULT_CONTEXT_WINDOW.saturating_sub(prompt_tokens);
+    if available == 0 {
+        error!("completion_budget - Prompt of {} tokens leaves no room for an answer", prompt_tokens);
+        return Err(anyhow!(
+            "Prompt of {} tokens exceeds the {}-token context window of {}",
+            prompt_tokens, DEFAULT_CONTEXT_WINDOW, DEFAULT_MODEL
+        ));
+    }
+    if available < DEFAULT_MAX_TOKENS as usize {
+        warn!("completion_budget - Only {} tokens left for the answer", available);
+    }
+    Ok(available.min(DEFAULT_MAX_TOKENS as usize) as u32)
 }
 
 /// Extract the answer text from the OpenAI response
@@ -272,20 +302,6 @@ async fn query_local_llm(_model_path: &str, _prompt: &str) -> Result<String> {
     Err(anyhow!("Local LLM support not yet implemented"))
 }
 
-/// Estimate the number of tokens in a text string
-/// This is a rough approximation used for token budget management
-#[allow(dead_code)]
-fn estimate_tokens(text: &str) -> usize {
-    debug!("estimate_tokens - Estimating tokens for text length: {}", text.len());
-    
-    // Rough approximation: 1 token ≈ 4 characters for English text
-    // This is not exact but good enough for budget estimation
-    let estimated = (text.len() as f64 / 4.0).ceil() as usize;
-    
-    debug!("estimate_tokens - Estimated {} tokens", estimated);
-    estimated
-}
-
 /// Configuration struct for customizing LLM behavior
 #[allow(dead_code)]
 pub struct LlmConfig {
@@ -313,6 +329,7 @@ pub async fn query_llm_with_config(
     api_key: &str,
     prompt: &str,
     config: &LlmConfig,
+    tokenizer: &dyn Tokenizer,
 ) -> Result<String> {
     info!("query_llm_with_config - Starting LLM query with custom config");
     debug!("query_llm_with_config - Config: model={}, max_tokens={}, temperature={}, timeout={:?}", 
@@ -321,27 +338,33 @@ pub async fn query_llm_with_config(
     // This would be similar to query_llm but with customizable parameters
     // For now, just delegate to the main function
     warn!("query_llm_with_config - Custom config not yet implemented, delegating to default query_llm");
-    query_llm(api_key, prompt).await
+    query_llm(api_key, prompt, tokenizer).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tokenizer::CharEstimate;
 
     #[test]
-    fn test_estimate_tokens() {
-        debug!("test_estimate_tokens - Running token estimation tests");
-        assert_eq!(estimate_tokens("hello world"), 3);
-        assert_eq!(estimate_tokens(""), 0);
-        assert_eq!(estimate_tokens("a"), 1);
-        debug!("test_estimate_tokens - All tests passed");
+    fn test_completion_budget() {
+        debug!("test_completion_budget - Testing max_tokens calculation");
+        assert_eq!(completion_budget(&["hello world"], &CharEstimate).unwrap(), DEFAULT_MAX_TOKENS);
+        
+        // A prompt near the context window leaves only the remainder for the answer
+        let long_prompt = "a".repeat((DEFAULT_CONTEXT_WINDOW - 1000) * 4);
+        assert_eq!(completion_budget(&[&long_prompt], &CharEstimate).unwrap(), 1000 - MESSAGE_OVERHEAD_TOKENS as u32);
+        
+        let too_long = "a".repeat(DEFAULT_CONTEXT_WINDOW * 4);
+        assert!(completion_budget(&[&too_long], &CharEstimate).is_err());
+        debug!("test_completion_budget - All tests passed");
     }
 
     #[test]
     fn test_build_chat_request() {
         debug!("test_build_chat_request - Testing chat request building");
         let prompt = "What is Rust?";
-        let request = build_chat_request(prompt);
+        let request = build_chat_request(prompt, &CharEstimate).unwrap();
         
         assert_eq!(request.model, DEFAULT_MODEL);
         assert_eq!(request.messages.len(), 2);
@@ -406,7 +429,7 @@ mod tests {
     #[tokio::test]
     async fn test_query_llm_empty_api_key() {
         debug!("test_query_llm_empty_api_key - Testing empty API key handling");
-        let result = query_llm("", "test prompt").await;
+        let result = query_llm("", "test prompt", &CharEstimate).await;
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("API key is required"));
         debug!("test_query_llm_empty_api_key - Test passed");
@@ -415,7 +438,7 @@ mod tests {
     #[tokio::test]
     async fn test_query_llm_empty_prompt() {
         debug!("test_query_llm_empty_prompt - Testing empty prompt handling");
-        let result = query_llm("test-key", "").await;
+        let result = query_llm("test-key", "", &CharEstimate).await;
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("Prompt cannot be empty"));
         debug!("test_query_llm_empty_prompt - Test passed");
@@ -1,18 +1,27 @@
 mod cli;
 mod config;
 mod ingest;
+mod chunking;
+mod dedup;
+mod tokenizer;
 mod indexer;
 mod prompt;
 mod retriever;
+mod sources;
 mod llm;
 mod embeddings;
+mod embedder;
+mod embedding_store;
+mod hnsw;
+mod native_embedder;
 
-use anyhow::Result;
-use crate::cli::{Cli, Command, SearchStrategy};
+use anyhow::{Context, Result};
+use crate::cli::{Cli, Command, Fusion, SearchStrategy};
 use clap::Parser;
 use dotenv::dotenv;
 use env_logger::init as logger_init;
 use log::info;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -25,31 +34,57 @@ async fn main() -> Result<()> {
     info!("Invoked command: {:?}", cli.command);
     
     match cli.command {
-        Command::Init { skip_embeddings } => {
+        Command::Init {
+            skip_embeddings, ref repo_url, ref branch, ref sparse_path, depth,
+            embed_batch_size, embed_concurrency, embed_retries,
+            hnsw_m, hnsw_ef_construction, no_hnsw, ref quantization, rescore,
+        } => {
             info!("🛠️ Initializing docs & index…");
             
             // 1) Sync docs folder (clone or pull)
-            ingest::sync_docs(&cli.docs_dir)?;
+            let source = repo_url.as_ref().map(|url| ingest::DocsSource {
+                url: url.clone(),
+                branch: branch.clone(),
+                sparse_path: sparse_path.clone(),
+                depth,
+            });
+            ingest::sync_docs(&cli.docs_dir, source.as_ref())?;
             
-            // 2) Read & chunk
-            let chunks = ingest::ingest_docs(&cli)?;
+            // 2) Read & chunk new or changed files
+            let changeset = ingest::ingest_docs(&cli)?;
             
             if skip_embeddings {
                 // 3a) Build traditional BM25-only index
-                indexer::build_index(&cli, &chunks)?;
+                indexer::build_index(&cli, &changeset)?;
                 info!("✅ BM25 index built at `{}`", cli.index_dir.display());
             } else {
                 // 3b) Build enhanced index with embeddings
-                embeddings::build_enhanced_index(&cli, &chunks).await?;
+                let embedder: Arc<dyn embedder::Embedder> = Arc::from(embedder::from_cli(&cli)?);
+                let options = embeddings::EmbedOptions {
+                    batch_size: embed_batch_size,
+                    concurrency: embed_concurrency,
+                    max_retries: embed_retries,
+                };
+                let vector_options = embeddings::VectorIndexOptions {
+                    format: embedding_store::StoreFormat {
+                        quantization: *quantization,
+                        full_precision: rescore,
+                    },
+                    hnsw: (!no_hnsw).then_some(hnsw::HnswParams {
+                        m: hnsw_m,
+                        ef_construction: hnsw_ef_construction,
+                    }),
+                };
+                embeddings::build_enhanced_index(&cli, &changeset, embedder, &options, vector_options).await?;
                 info!("✅ Enhanced index with embeddings built at `{}`", cli.index_dir.display());
             }
         }
-        Command::Query { ref query, ref strategy, alpha } => {
+        Command::Query { ref query, ref strategy, alpha, ref fusion, rrf_k, bm25_weight, semantic_weight, context_expansion } => {
             info!("🔍 Opening index at `{}`…", cli.index_dir.display());
             
             // Check if embeddings are available
-            let embeddings_path = cli.index_dir.join("embeddings.json");
-            let has_embeddings = embeddings_path.exists();
+            let has_embeddings = embeddings::store_path(&cli.index_dir).exists()
+                || cli.index_dir.join("embeddings.json").exists();
             
             if !has_embeddings && matches!(strategy, SearchStrategy::Semantic | SearchStrategy::Hybrid) {
                 anyhow::bail!("Embeddings not found! Run 'init' without --skip-embeddings first.");
@@ -58,12 +93,20 @@ async fn main() -> Result<()> {
             let chunks = if has_embeddings && !matches!(strategy, SearchStrategy::Bm25) {
                 // Use hybrid search
                 info!("Using hybrid search with strategy: {:?}", strategy);
-                let hybrid_searcher = embeddings::HybridSearcher::new(&cli)?;
+                let hybrid_searcher = embeddings::HybridSearcher::new(&cli, embedder::from_cli(&cli)?)?;
+                let fusion = match fusion {
+                    Fusion::Linear => embeddings::FusionMode::Linear,
+                    Fusion::Rrf => embeddings::FusionMode::Rrf(embeddings::RrfParams {
+                        k: rrf_k,
+                        bm25_weight,
+                        semantic_weight,
+                    }),
+                };
                 
                 let search_results = match strategy {
                     SearchStrategy::Auto => {
                         // Let the system decide based on query analysis
-                        hybrid_searcher.hybrid_search(query, cli.top_k)?
+                        hybrid_searcher.hybrid_search(query, cli.top_k, fusion)?
                     }
                     SearchStrategy::Semantic => {
                         // Force pure semantic search
@@ -77,7 +120,7 @@ async fn main() -> Result<()> {
                     SearchStrategy::Hybrid => {
                         // Force hybrid with custom alpha or default
                         let alpha = alpha.unwrap_or(0.5);
-                        hybrid_searcher.hybrid_search_with_alpha(query, cli.top_k, alpha)?
+                        hybrid_searcher.hybrid_search_with_alpha(query, cli.top_k, alpha, fusion)?
                     }
                     SearchStrategy::Bm25 => {
                         // This branch shouldn't be reached due to the outer check
@@ -106,15 +149,54 @@ async fn main() -> Result<()> {
                 retriever::bm25_search(&retriever_index, query, cli.top_k)?
             };
             
-            // 2) Assemble prompt
-            let prompt = prompt::build_prompt(&chunks, query);
+            // 2) Swap matched chunks for the text around them
+            let chunks = if context_expansion == retriever::ContextExpansion::None {
+                chunks
+            } else {
+                let indexer_index = indexer::open_index(&cli)?;
+                let retriever_index = retriever::Index::new(indexer_index.tantivy_index)?;
+                retriever::expand_context(&retriever_index, chunks, context_expansion)?
+            };
+            
+            // 3) Assemble prompt, budgeted in the LLM's tokens
+            let tokenizer = tokenizer::from_cli(&cli);
+            let mut config = prompt::PromptConfig { tokenizer: tokenizer.clone(), ..Default::default() };
+            if context_expansion != retriever::ContextExpansion::None {
+                // Parents are sized by the expansion; only the overall budget limits them
+                config.max_chunk_tokens = config.max_context_tokens;
+            }
+            let prompt = prompt::build_prompt_with_config(&chunks, query, &config);
             
-            // 3) Call LLM
-            let answer = llm::query_llm(&cli.openai_api_key, &prompt).await?;
+            // 4) Call LLM
+            let answer = llm::query_llm(&cli.openai_api_key, &prompt, tokenizer.as_ref()).await?;
             
-            // 4) Print result
+            // 5) Print result
             println!("\n{}", answer);
         }
+        Command::BenchAnn { queries, ref ef, m, ef_construction } => {
+            let store = embedding_store::EmbeddingStore::open(&embeddings::store_path(&cli.index_dir))
+                .context("No embedding store found. Run 'init' first.")?;
+            
+            let graph = match m {
+                Some(m) => hnsw::Hnsw::build(&store, hnsw::HnswParams { m, ef_construction }),
+                None => hnsw::Hnsw::load(&embeddings::ann_index_path(&cli.index_dir))
+                    .context("No HNSW index found. Run 'init' or pass --m to build one.")?,
+            };
+            if !graph.matches(&store) {
+                anyhow::bail!("HNSW index is out of date with the embedding store; re-run 'init'");
+            }
+            
+            let params = graph.params();
+            info!("📏 Benchmarking HNSW (M={}, ef_construction={}) over {} embeddings",
+                  params.m, params.ef_construction, store.len());
+            let reports = hnsw::recall_benchmark(&store, &graph, queries, cli.top_k, ef);
+            
+            println!("{:>6}  {:>10}  {:>12}  {:>12}", "ef", format!("recall@{}", cli.top_k), "hnsw (µs)", "exact (µs)");
+            for report in reports {
+                println!("{:>6}  {:>10.4}  {:>12.1}  {:>12.1}",
+                         report.ef, report.recall, report.avg_query_micros, report.avg_exact_micros);
+            }
+        }
     }
     
     Ok(())
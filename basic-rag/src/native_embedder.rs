@@ -0,0 +1,285 @@
+// src/native_embedder.rs
+
+//! In-process sentence embeddings with Candle
+//!
+//! Loads a BERT-family sentence-transformer (e.g. `all-MiniLM-L6-v2`) from a
+//! local directory and runs tokenization, the encoder and pooling on the CPU,
+//! so indexing works on machines without Python. The directory is the model's
+//! Hugging Face layout:
+//! - `config.json` and `tokenizer.json`
+//! - `model.safetensors` (or `pytorch_model.bin`)
+//! - optionally `sentence_bert_config.json` for the maximum sequence length
+
+use anyhow::{anyhow, Context, Result};
+use candle_core::{DType, Device, IndexOp, Tensor, D};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config};
+use log::{debug, info};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+use crate::cli::Pooling;
+use crate::embedder::Embedder;
+
+/// Subset of `sentence_bert_config.json` we honour
+#[derive(Deserialize)]
+struct SentenceBertConfig {
+    max_seq_length: usize,
+}
+
+/// BERT sentence-transformer running on the CPU via Candle
+pub struct NativeEmbedder {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+    pooling: Pooling,
+    normalize: bool,
+    dimension: usize,
+    model_id: String,
+}
+
+impl NativeEmbedder {
+    /// Load the model in `model_dir`; `normalize` L2-normalizes the pooled vectors.
+    ///
+    /// `model_id` is the configured model name rather than the directory's, so a
+    /// store built by another backend for the same model stays usable.
+    pub fn load(model_dir: &Path, model_id: &str, pooling: Pooling, normalize: bool) -> Result<Self> {
+        info!("Loading native embedding model from {:?}", model_dir);
+        let device = Device::Cpu;
+
+        let config: Config = serde_json::from_str(
+            &fs::read_to_string(model_dir.join("config.json"))
+                .with_context(|| format!("Failed to read config.json in {:?}", model_dir))?,
+        ).context("Failed to parse model config.json")?;
+
+        // Sentence-transformers caps inputs below the position embedding limit
+        let max_length = fs::read_to_string(model_dir.join("sentence_bert_config.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str::<SentenceBertConfig>(&content).ok())
+            .map(|st_config| st_config.max_seq_length)
+            .unwrap_or(config.max_position_embeddings);
+
+        let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+            .map_err(|e| anyhow!("Failed to load tokenizer.json: {}", e))?;
+        tokenizer
+            .with_padding(Some(PaddingParams {
+                strategy: PaddingStrategy::BatchLongest,
+                ..Default::default()
+            }))
+            .with_truncation(Some(TruncationParams {
+                max_length,
+                ..Default::default()
+            }))
+            .map_err(|e| anyhow!("Failed to configure tokenizer: {}", e))?;
+
+        let safetensors = model_dir.join("model.safetensors");
+        let vb = if safetensors.exists() {
+            // SAFETY: the weights file is not modified while mapped
+            unsafe { VarBuilder::from_mmaped_safetensors(&[safetensors], DType::F32, &device)? }
+        } else {
+            VarBuilder::from_pth(model_dir.join("pytorch_model.bin"), DType::F32, &device)
+                .with_context(|| format!("No model.safetensors or pytorch_model.bin in {:?}", model_dir))?
+        };
+        let model = BertModel::load(vb, &config)
+            .context("Failed to load BERT weights")?;
+
+        info!(
+            "✅ Native embedding model {} ready ({} dimensions, {:?} pooling, max {} tokens)",
+            model_id, config.hidden_size, pooling, max_length
+        );
+        Ok(Self {
+            model,
+            tokenizer,
+            device,
+            pooling,
+            normalize,
+            dimension: config.hidden_size,
+            model_id: model_id.to_string(),
+        })
+    }
+}
+
+impl Embedder for NativeEmbedder {
+    fn encode_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        debug!("Encoding {} texts natively", texts.len());
+
+        let encodings = self.tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| anyhow!("Tokenization failed: {}", e))?;
+
+        let stack = |field: fn(&tokenizers::Encoding) -> &[u32]| -> Result<Tensor> {
+            let rows = encodings.iter()
+                .map(|encoding| Tensor::new(field(encoding), &self.device))
+                .collect::<candle_core::Result<Vec<_>>>()?;
+            Ok(Tensor::stack(&rows, 0)?)
+        };
+        let input_ids = stack(|e| e.get_ids())?;
+        let type_ids = stack(|e| e.get_type_ids())?;
+        let attention_mask = stack(|e| e.get_attention_mask())?;
+
+        let hidden = self.model.forward(&input_ids, &type_ids, Some(&attention_mask))?;
+        let mut pooled = pool(&hidden, &attention_mask, &self.pooling)?;
+        if self.normalize {
+            pooled = l2_normalize(&pooled)?;
+        }
+
+        Ok(pooled.to_vec2::<f32>()?)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+}
+
+/// Reduce `[batch, seq, hidden]` token states to `[batch, hidden]`, ignoring padding
+fn pool(hidden: &Tensor, attention_mask: &Tensor, pooling: &Pooling) -> Result<Tensor> {
+    let mask = attention_mask.to_dtype(DType::F32)?.unsqueeze(D::Minus1)?;
+
+    let pooled = match pooling {
+        Pooling::Mean => {
+            let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+            let counts = mask.sum(1)?.clamp(1e-9, f32::MAX)?;
+            summed.broadcast_div(&counts)?
+        }
+        Pooling::Cls => hidden.i((.., 0))?,
+        Pooling::Max => {
+            // Push padded positions far below any real activation
+            let penalty = ((mask - 1.0)? * 1e9)?;
+            hidden.broadcast_add(&penalty)?.max(1)?
+        }
+    };
+
+    Ok(pooled)
+}
+
+/// Scale each row to unit L2 norm
+fn l2_normalize(vectors: &Tensor) -> Result<Tensor> {
+    let norms = vectors.sqr()?.sum_keepdim(1)?.sqrt()?.clamp(1e-12, f32::MAX)?;
+    Ok(vectors.broadcast_div(&norms)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    /// Two sequences of three tokens with hidden size 2; the second has one padded position
+    fn fixture() -> (Tensor, Tensor) {
+        let hidden = Tensor::new(
+            &[
+                [[1.0f32, 2.0], [3.0, 4.0], [5.0, 6.0]],
+                [[2.0, 0.0], [4.0, 2.0], [100.0, 100.0]],
+            ],
+            &Device::Cpu,
+        ).unwrap();
+        let mask = Tensor::new(&[[1u32, 1, 1], [1, 1, 0]], &Device::Cpu).unwrap();
+        (hidden, mask)
+    }
+
+    #[test]
+    fn test_mean_pooling_ignores_padding() {
+        let (hidden, mask) = fixture();
+        let pooled = pool(&hidden, &mask, &Pooling::Mean).unwrap().to_vec2::<f32>().unwrap();
+        assert_eq!(pooled, vec![vec![3.0, 4.0], vec![3.0, 1.0]]);
+    }
+
+    #[test]
+    fn test_cls_and_max_pooling() {
+        let (hidden, mask) = fixture();
+        let cls = pool(&hidden, &mask, &Pooling::Cls).unwrap().to_vec2::<f32>().unwrap();
+        assert_eq!(cls, vec![vec![1.0, 2.0], vec![2.0, 0.0]]);
+
+        let max = pool(&hidden, &mask, &Pooling::Max).unwrap().to_vec2::<f32>().unwrap();
+        assert_eq!(max, vec![vec![5.0, 6.0], vec![4.0, 2.0]]);
+    }
+
+    #[test]
+    fn test_l2_normalize() {
+        let vectors = Tensor::new(&[[3.0f32, 4.0], [0.0, 0.0]], &Device::Cpu).unwrap();
+        let normalized = l2_normalize(&vectors).unwrap().to_vec2::<f32>().unwrap();
+        assert_eq!(normalized[0], vec![0.6, 0.8]);
+        assert_eq!(normalized[1], vec![0.0, 0.0]);
+    }
+
+    /// Write a randomly initialised two-dimensional BERT model into `dir`
+    fn write_tiny_model(dir: &Path) {
+        let config = serde_json::json!({
+            "vocab_size": 3, "hidden_size": 2, "num_hidden_layers": 1, "num_attention_heads": 1,
+            "intermediate_size": 4, "hidden_act": "gelu", "hidden_dropout_prob": 0.0,
+            "max_position_embeddings": 16, "type_vocab_size": 2, "initializer_range": 0.02,
+            "layer_norm_eps": 1e-12, "pad_token_id": 0,
+        });
+        fs::write(dir.join("config.json"), config.to_string()).unwrap();
+        let tokenizer = serde_json::json!({
+            "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+            "normalizer": null, "pre_tokenizer": {"type": "Whitespace"}, "post_processor": null,
+            "decoder": null,
+            "model": {
+                "type": "WordPiece", "unk_token": "[UNK]", "continuing_subword_prefix": "##",
+                "max_input_chars_per_word": 100, "vocab": {"[UNK]": 0, "hello": 1, "world": 2},
+            },
+        });
+        fs::write(dir.join("tokenizer.json"), tokenizer.to_string()).unwrap();
+
+        let varmap = candle_nn::VarMap::new();
+        let config: Config = serde_json::from_value(config).unwrap();
+        BertModel::load(VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu), &config).unwrap();
+        varmap.save(dir.join("model.safetensors")).unwrap();
+    }
+
+    #[test]
+    fn test_model_id_is_the_configured_name() {
+        let model_dir = tempfile::TempDir::new().unwrap();
+        write_tiny_model(model_dir.path());
+
+        let embedder = NativeEmbedder::load(model_dir.path(), "all-MiniLM-L6-v2", Pooling::Mean, true).unwrap();
+        assert_eq!(embedder.model_id(), "all-MiniLM-L6-v2");
+        assert_eq!(embedder.dimension(), 2);
+        assert_eq!(embedder.encode_batch(&["hello world".to_string()]).unwrap()[0].len(), 2);
+    }
+
+    /// Compares against sentence-transformers when `BASIC_RAG_TEST_MODEL_DIR` points
+    /// at a local copy of the model and Python has sentence-transformers installed.
+    #[test]
+    fn test_matches_sentence_transformers() {
+        let Ok(model_dir) = std::env::var("BASIC_RAG_TEST_MODEL_DIR") else {
+            return;
+        };
+        let texts = vec![
+            "How do I configure logging?".to_string(),
+            "Sprites are rendered in batches by the 2D renderer.".to_string(),
+        ];
+
+        let script = "import sys, json\n\
+                      from sentence_transformers import SentenceTransformer\n\
+                      model = SentenceTransformer(sys.argv[1])\n\
+                      print(json.dumps(model.encode(json.loads(sys.argv[2])).tolist()))";
+        let output = Command::new("python3")
+            .arg("-c").arg(script)
+            .arg(&model_dir)
+            .arg(serde_json::to_string(&texts).unwrap())
+            .output()
+            .expect("python3 is required for this comparison");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let expected: Vec<Vec<f32>> = serde_json::from_slice(&output.stdout).unwrap();
+
+        let embedder = NativeEmbedder::load(Path::new(&model_dir), "all-MiniLM-L6-v2", Pooling::Mean, true).unwrap();
+        assert_eq!(embedder.model_id(), "all-MiniLM-L6-v2");
+        let actual = embedder.encode_batch(&texts).unwrap();
+
+        for (a, e) in actual.iter().zip(&expected) {
+            assert_eq!(a.len(), e.len());
+            let max_diff = a.iter().zip(e).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+            assert!(max_diff < 1e-4, "max difference {} exceeds tolerance", max_diff);
+        }
+    }
+}
@@ -12,7 +12,10 @@
 //! - Source attribution for traceability
 //! - Graceful handling of edge cases (no chunks, oversized content)
 
+use std::sync::Arc;
+
 use crate::ingest::Chunk;
+use crate::tokenizer::{CharEstimate, Tokenizer};
 
 /// Configuration for prompt building behavior
 #[derive(Debug, Clone)]
@@ -29,6 +32,8 @@ pub struct PromptConfig {
     pub include_positions: bool,
     /// Prompt template style
     pub template_style: PromptTemplateStyle,
+    /// Tokenizer the budgets above are measured with
+    pub tokenizer: Arc<dyn Tokenizer>,
 }
 
 impl Default for PromptConfig {
@@ -40,6 +45,7 @@ impl Default for PromptConfig {
             include_sources: true,
             include_positions: false,
             template_style: PromptTemplateStyle::ChatCompletion,
+            tokenizer: Arc::new(CharEstimate),
         }
     }
 }
@@ -64,6 +70,7 @@ pub enum PromptTemplateStyle {
 ///
 /// # Returns
 /// A single `String` ready to send as the prompt to the LLM.
+#[allow(dead_code)]
 pub fn build_prompt(chunks: &[Chunk], question: &str) -> String {
     build_prompt_with_config(chunks, question, &PromptConfig::default())
 }
@@ -79,8 +86,9 @@ pub fn build_prompt(chunks: &[Chunk], question: &str) -> String {
 /// A single `String` ready to send as the prompt to the LLM.
 pub fn build_prompt_with_config(chunks: &[Chunk], question: &str, config: &PromptConfig) -> String {
     // Calculate available tokens for chunk content
-    let question_tokens = estimate_tokens(question);
-    let system_tokens = estimate_tokens(get_system_instructions());
+    let tokenizer = config.tokenizer.as_ref();
+    let question_tokens = tokenizer.count(question);
+    let system_tokens = tokenizer.count(get_system_instructions());
     let used_reserved = question_tokens + system_tokens + 100; // 100 for formatting overhead
     
     let available_tokens = config.max_context_tokens.saturating_sub(
@@ -105,18 +113,18 @@ fn prepare_chunks(chunks: &[Chunk], available_tokens: usize, config: &PromptConf
     
     for (index, chunk) in chunks.iter().enumerate() {
         // Estimate tokens for this chunk including formatting
-        let chunk_text = truncate_chunk_text(&chunk.text, config.max_chunk_tokens);
+        let chunk_text = truncate_chunk_text(&chunk.text, config.max_chunk_tokens, config.tokenizer.as_ref());
         let formatted_chunk = format_chunk_for_estimation(chunk, &chunk_text, index, config);
-        let chunk_tokens = estimate_tokens(&formatted_chunk);
+        let chunk_tokens = config.tokenizer.count(&formatted_chunk);
         
         // Check if we can fit this chunk
         if used_tokens + chunk_tokens > available_tokens {
             // If this is the first chunk and it's too big, include a truncated version
             if prepared.is_empty() {
-                let truncated_text = truncate_to_token_limit(&chunk.text, available_tokens / 2);
+                let truncated_text = truncate_to_token_limit(&chunk.text, available_tokens / 2, config.tokenizer.as_ref());
                 prepared.push(PreparedChunk {
                     text: truncated_text,
-                    source: chunk.source.clone(),
+                    source: chunk.citation(),
                     position: chunk.position,
                     index,
                     truncated: true,
@@ -127,7 +135,7 @@ fn prepare_chunks(chunks: &[Chunk], available_tokens: usize, config: &PromptConf
         
         prepared.push(PreparedChunk {
             text: chunk_text.clone(),
-            source: chunk.source.clone(),
+            source: chunk.citation(),
             position: chunk.position,
             index,
             truncated: chunk_text.len() < chunk.text.len(), 
@@ -170,7 +178,7 @@ fn build_chat_completion_prompt(chunks: &[PreparedChunk], question: &str, config
             format_chunk_into_prompt(chunk, &mut prompt, config);
         }
         
-        prompt.push_str("\n");
+        prompt.push('\n');
     }
     
     prompt.push_str(&format!("Question: {}\n\n", question));
@@ -197,7 +205,7 @@ fn build_completion_prompt(chunks: &[PreparedChunk], question: &str, config: &Pr
             format_chunk_into_prompt(chunk, &mut prompt, config);
         }
         
-        prompt.push_str("\n");
+        prompt.push('\n');
     }
     
     // Question and answer prompt
@@ -219,7 +227,7 @@ fn build_conversational_prompt(chunks: &[PreparedChunk], question: &str, config:
         for chunk in chunks {
             format_chunk_into_prompt(chunk, &mut prompt, config);
         }
-        prompt.push_str("\n");
+        prompt.push('\n');
     }
     
     prompt.push_str(&format!("My question is: {}\n\n", question));
@@ -248,10 +256,13 @@ fn format_chunk_into_prompt(chunk: &PreparedChunk, prompt: &mut String, config:
         }
         
         prompt.push_str(")\n");
+    } else if starts_with_fence(&chunk.text) {
+        // A fence only opens a code block at the start of a line
+        prompt.push('\n');
     }
     
-    // Add the chunk text
-    prompt.push_str(&chunk.text);
+    // Add the chunk text, with code blocks as fenced code
+    prompt.push_str(&close_open_fence(&chunk.text));
     prompt.push_str("\n\n");
 }
 
@@ -260,21 +271,56 @@ fn format_chunk_for_estimation(chunk: &Chunk, text: &str, index: usize, config:
     let mut formatted = format!("[{}] ", index + 1);
     
     if config.include_sources {
-        formatted.push_str(&format!("(source: {}", chunk.source));
+        formatted.push_str(&format!("(source: {}", chunk.citation()));
         
         if config.include_positions {
             formatted.push_str(&format!(", chunk: {}", chunk.position));
         }
         
         formatted.push_str(")\n");
+    } else if starts_with_fence(text) {
+        formatted.push('\n');
     }
     
-    formatted.push_str(text);
+    formatted.push_str(&close_open_fence(text));
     formatted.push_str("\n\n");
     
     formatted
 }
 
+/// Backtick run opening a fenced code block on this line, if any
+fn fence_of(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let length = line.len() - line.trim_start_matches('`').len();
+    (length >= 3).then(|| &line[..length])
+}
+
+/// Whether the text opens with a fenced code block
+fn starts_with_fence(text: &str) -> bool {
+    text.lines().next().and_then(fence_of).is_some()
+}
+
+/// Close a code block left open when truncation cut a chunk mid-block.
+///
+/// Ingestion stores code blocks as Markdown fences with their language tag,
+/// so complete blocks are already rendered as fenced code.
+fn close_open_fence(text: &str) -> String {
+    let mut open: Option<&str> = None;
+    for line in text.lines() {
+        match (open, fence_of(line)) {
+            (None, Some(fence)) => open = Some(fence),
+            // Only a bare fence at least as long as the opening one closes the block
+            (Some(opening), Some(fence)) if fence.len() >= opening.len() && line.trim() == fence => open = None,
+            _ => {}
+        }
+    }
+    
+    match open {
+        Some(fence) => format!("{}\n{}", text.trim_end(), fence),
+        None => text.to_string(),
+    }
+}
+
 /// Get system instructions for the LLM
 fn get_system_instructions() -> &'static str {
     "You are a helpful assistant that answers questions based on provided documentation excerpts. \
@@ -287,45 +333,18 @@ fn get_system_instructions() -> &'static str {
      Always finish with a working example/use case of what the user asked."
 }
 
-/// Estimate the number of tokens in a text string.
-/// This is a rough approximation: 1 token ≈ 4 characters for English text.
-/// For more accurate counting, you'd want to use the actual tokenizer for your model.
-fn estimate_tokens(text: &str) -> usize {
-    // Simple heuristic: average of 4 characters per token
-    // This is conservative for most models (GPT-3.5/4, Claude, etc.)
-    (text.len() as f64 / 4.0).ceil() as usize
-}
-
-/// Safe truncate at char boundary, fallback if too short.
-fn safe_truncate(text: &str, max_chars: usize) -> &str {
-    if text.is_char_boundary(max_chars) {
-        &text[..max_chars]
-    } else {
-        // Walk backwards until we find a valid char boundary
-        let mut idx = max_chars;
-        while idx > 0 && !text.is_char_boundary(idx) {
-            idx -= 1;
-        }
-        &text[..idx]
-    }
-}
-
 /// Truncate chunk text to fit within a token limit while preserving readability.
-fn truncate_chunk_text(text: &str, max_tokens: usize) -> String {
-    let max_chars = max_tokens * 4; // Conservative estimate
-    
-    if text.len() <= max_chars {
+fn truncate_chunk_text(text: &str, max_tokens: usize, tokenizer: &dyn Tokenizer) -> String {
+    let truncated = tokenizer.truncate(text, max_tokens);
+    if truncated.len() == text.len() {
         return text.to_string();
     }
     
-    // Try to truncate at sentence boundaries
-    let truncated = safe_truncate(text, max_chars);    
-    
     // Find the last sentence boundary (period, exclamation, or question mark followed by space)
-    if let Some(last_sentence) = truncated.rfind(|c: char| c == '.' || c == '!' || c == '?') {
+    if let Some(last_sentence) = truncated.rfind(['.', '!', '?']) {
         let candidate = &truncated[..=last_sentence];
         // Make sure we're not truncating too aggressively (at least 50% of target length)
-        if candidate.len() >= max_chars / 2 {
+        if candidate.len() >= truncated.len() / 2 {
             return candidate.to_string();
         }
     }
@@ -333,27 +352,24 @@ fn truncate_chunk_text(text: &str, max_tokens: usize) -> String {
     // If no good sentence boundary, truncate at word boundary
     if let Some(last_space) = truncated.rfind(' ') {
         let candidate = &truncated[..last_space];
-        if candidate.len() >= max_chars / 2 {
+        if candidate.len() >= truncated.len() / 2 {
             return format!("{}...", candidate);
         }
     }
     
-    // Fallback: hard truncate with ellipsis
-    format!("{}...", &text[..max_chars.saturating_sub(3)])
+    // Fallback: hard truncate, leaving a token for the ellipsis
+    format!("{}...", tokenizer.truncate(text, max_tokens.saturating_sub(1)))
 }
 
 /// Truncate text to fit within a specific token limit (more aggressive than chunk truncation)
-fn truncate_to_token_limit(text: &str, max_tokens: usize) -> String {
-    let max_chars = max_tokens * 4;
-    
-    if text.len() <= max_chars {
+fn truncate_to_token_limit(text: &str, max_tokens: usize, tokenizer: &dyn Tokenizer) -> String {
+    let truncated = tokenizer.truncate(text, max_tokens);
+    if truncated.len() == text.len() {
         return text.to_string();
     }
     
-    // More aggressive truncation for token limits
-    let truncated = &text[..max_chars.saturating_sub(10)];
-    
-    // Try to end at a reasonable boundary
+    // Leave room for the ending and try to end at a reasonable boundary
+    let truncated = tokenizer.truncate(truncated, max_tokens.saturating_sub(2));
     if let Some(last_period) = truncated.rfind(". ") {
         return format!("{}.", &truncated[..last_period]);
     }
@@ -368,6 +384,7 @@ fn truncate_to_token_limit(text: &str, max_tokens: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ingest::DocMetadata;
 
     fn create_test_chunk(id: &str, text: &str, source: &str, position: usize) -> Chunk {
         Chunk {
@@ -376,6 +393,9 @@ mod tests {
             source: source.to_string(),
             heading: None,
             position,
+            page: None,
+            alternate_sources: Vec::new(),
+            metadata: DocMetadata::default(),
         }
     }
 
@@ -425,17 +445,23 @@ mod tests {
     }
 
     #[test]
-    fn test_token_estimation() {
-        assert_eq!(estimate_tokens(""), 0);
-        assert_eq!(estimate_tokens("test"), 1);
-        assert_eq!(estimate_tokens("this is a test"), 4);
-        assert_eq!(estimate_tokens(&"a".repeat(100)), 25);
+    fn test_budget_uses_configured_tokenizer() {
+        // 250 words, but about 313 tokens at 4 bytes per token
+        let text = "word ".repeat(250);
+        let chunks = vec![create_test_chunk("test:1", text.trim_end(), "test.md", 0)];
+        
+        let estimated = build_prompt(&chunks, "Question?");
+        assert!(!estimated.contains(text.trim_end()));
+        
+        let config = PromptConfig { tokenizer: Arc::new(crate::tokenizer::Words), ..Default::default() };
+        let counted = build_prompt_with_config(&chunks, "Question?", &config);
+        assert!(counted.contains(text.trim_end()));
     }
 
     #[test]
     fn test_chunk_truncation() {
         let long_text = "This is a very long text. ".repeat(50);
-        let truncated = truncate_chunk_text(&long_text, 10); // 10 tokens = ~40 chars
+        let truncated = truncate_chunk_text(&long_text, 10, &CharEstimate); // 10 tokens = ~40 chars
         
         assert!(truncated.len() < long_text.len());
         assert!(truncated.len() <= 40);
@@ -493,4 +519,35 @@ mod tests {
         assert!(prompt_with.contains("test.md"));
         assert!(!prompt_without.contains("test.md"));
     }
+    
+    #[test]
+    fn test_pdf_chunks_cite_page() {
+        let mut chunk = create_test_chunk("1", "Timeout defaults to 30 seconds.", "spec.pdf", 0);
+        chunk.page = Some(12);
+        
+        let prompt = build_prompt(&[chunk], "What is the timeout?");
+        assert!(prompt.contains("(source: spec.pdf p.12)"));
+    }
+    
+    #[test]
+    fn test_code_blocks_rendered_as_fenced_code() {
+        let code_chunk = "```rust\nfn main() {\n    app::start();\n}\n```\n\nStarts the app.";
+        let chunks = vec![create_test_chunk("1", code_chunk, "api.md", 0)];
+        let config = PromptConfig {
+            include_sources: false,
+            ..Default::default()
+        };
+        
+        let prompt = build_prompt_with_config(&chunks, "How do I start the app?", &config);
+        assert!(prompt.contains("[1] \n```rust\nfn main() {\n    app::start();\n}\n```"));
+    }
+    
+    #[test]
+    fn test_truncated_code_block_is_closed() {
+        assert_eq!(close_open_fence("Intro\n\n```python\nprint(1)\nprint("), "Intro\n\n```python\nprint(1)\nprint(\n```");
+        assert_eq!(close_open_fence("````md\n```\nstill code"), "````md\n```\nstill code\n````");
+        
+        let complete = "```\ncode\n```\n\nText";
+        assert_eq!(close_open_fence(complete), complete);
+    }
 }
\ No newline at end of file
@@ -7,17 +7,21 @@
 
 use anyhow::{Context, Result};
 use log::{debug, warn, info};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use tantivy::schema::{Field, Value};
 use tantivy::{
-    collector::TopDocs,
-    query::QueryParser,
+    collector::{DocSetCollector, TopDocs},
+    query::{AllQuery, QueryParser, RegexQuery, TermSetQuery},
     Index as TantivyIndex,
     IndexReader,
     ReloadPolicy,
     Searcher,
     TantivyDocument,
+    Term,
 };
-use crate::ingest::Chunk;
+use crate::indexer::MetadataFields;
+use crate::ingest::{chunk_file, Chunk};
 
 /// Wrapper around Tantivy Index with cached field handles
 pub struct Index {
@@ -28,6 +32,12 @@ pub struct Index {
     pub source_field: Field,
     pub position_field: Field,
     pub heading_field: Field,
+    /// Absent in indexes created before page numbers were recorded
+    pub page_field: Option<Field>,
+    /// Absent in indexes created before near-duplicates were folded
+    pub alternate_source_field: Option<Field>,
+    /// Absent in indexes created before schema version 2
+    pub metadata_fields: Option<MetadataFields>,
 }
 
 impl Index {
@@ -72,6 +82,15 @@ impl Index {
             .get_field("heading")
             .context("Index schema missing 'heading' field")?;
         debug!("Index::new - Found 'heading' field");
+        
+        let page_field = schema.get_field("page").ok();
+        debug!("Index::new - 'page' field present: {}", page_field.is_some());
+        
+        let alternate_source_field = schema.get_field("alternate_source").ok();
+        debug!("Index::new - 'alternate_source' field present: {}", alternate_source_field.is_some());
+        
+        let metadata_fields = MetadataFields::from_schema(&schema);
+        debug!("Index::new - metadata fields present: {}", metadata_fields.is_some());
 
         info!("Index::new - Successfully created Index wrapper with all required fields");
         Ok(Index {
@@ -82,6 +101,9 @@ impl Index {
             source_field,
             position_field,
             heading_field,
+            page_field,
+            alternate_source_field,
+            metadata_fields,
         })
     }
 
@@ -94,18 +116,40 @@ impl Index {
     }
 }
 
+/// A retrieved chunk together with its raw BM25 score from Tantivy
+#[derive(Debug, Clone)]
+pub struct ScoredChunk {
+    pub chunk: Chunk,
+    pub score: f32,
+}
+
 /// Perform BM25 search on the index and return the top-K matching chunks.
 ///
+/// Convenience wrapper around [`bm25_search_scored`] for callers that only
+/// need the chunks, in descending relevance order.
+pub fn bm25_search(
+    index: &Index,
+    query: &str,
+    top_k: usize,
+) -> Result<Vec<Chunk>> {
+    Ok(bm25_search_scored(index, query, top_k)?
+        .into_iter()
+        .map(|scored| scored.chunk)
+        .collect())
+}
+
+/// Perform BM25 search on the index and return the top-K chunks with their scores.
+///
 /// This function:
 /// 1. Parses the query string using Tantivy's QueryParser
 /// 2. Executes BM25 search with the specified limit
 /// 3. Retrieves matching documents and converts them back to Chunk objects
-/// 4. Returns chunks sorted by relevance score (highest first)
-pub fn bm25_search(
+/// 4. Returns chunks paired with their raw BM25 score, highest first
+pub fn bm25_search_scored(
     index: &Index,
     query: &str,
     top_k: usize,
-) -> Result<Vec<Chunk>> {
+) -> Result<Vec<ScoredChunk>> {
     info!("bm25_search - Starting BM25 search for query: '{}' (top {})", query, top_k);
     debug!("bm25_search - Query length: {} characters", query.len());
 
@@ -173,7 +217,7 @@ pub fn bm25_search(
         match document_to_chunk(&retrieved_doc, index, *score) {
             Ok(chunk) => {
                 debug!("bm25_search - Successfully converted document to chunk with ID: {}", chunk.id);
-                chunks.push(chunk);
+                chunks.push(ScoredChunk { chunk, score: *score });
             },
             Err(e) => {
                 warn!("bm25_search - Failed to convert document to chunk: {}", e);
@@ -187,6 +231,218 @@ pub fn bm25_search(
     Ok(chunks)
 }
 
+/// Load chunks by id from the index, e.g. to resolve embedding store hits.
+///
+/// Ids that are not in the index are skipped.
+pub fn fetch_chunks(index: &Index, ids: &[&str]) -> Result<HashMap<String, Chunk>> {
+    debug!("fetch_chunks - Looking up {} chunk ids", ids.len());
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    
+    let searcher = index.searcher();
+    let terms = ids.iter().map(|id| Term::from_field_text(index.id_field, id));
+    let query = TermSetQuery::new(terms);
+    let top_docs = searcher
+        .search(&query, &TopDocs::with_limit(ids.len()))
+        .context("Failed to look up chunks by id")?;
+    
+    let mut chunks = HashMap::with_capacity(top_docs.len());
+    for (score, doc_address) in top_docs {
+        let doc = searcher
+            .doc(doc_address)
+            .context("Failed to retrieve document")?;
+        let chunk = document_to_chunk(&doc, index, score)?;
+        chunks.insert(chunk.id.clone(), chunk);
+    }
+    
+    debug!("fetch_chunks - Found {} of {} chunks", chunks.len(), ids.len());
+    Ok(chunks)
+}
+
+/// Load every chunk in the index, ordered by file and position
+pub fn all_chunks(index: &Index) -> Result<Vec<Chunk>> {
+    let searcher = index.searcher();
+    let doc_addresses = searcher
+        .search(&AllQuery, &DocSetCollector)
+        .context("Failed to list indexed chunks")?;
+    
+    let mut chunks = Vec::with_capacity(doc_addresses.len());
+    for doc_address in doc_addresses {
+        let doc = searcher
+            .doc(doc_address)
+            .context("Failed to retrieve document")?;
+        chunks.push(document_to_chunk(&doc, index, 0.0)?);
+    }
+    chunks.sort_by(|a, b| (chunk_file(&a.id), a.position).cmp(&(chunk_file(&b.id), b.position)));
+    
+    debug!("all_chunks - Loaded {} chunks", chunks.len());
+    Ok(chunks)
+}
+
+/// How much surrounding text replaces each retrieved chunk in the prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextExpansion {
+    /// The matched chunk only
+    None,
+    /// The matched chunk and up to N chunks either side of it in the same file
+    Neighbors(usize),
+    /// The matched chunk's section: adjacent chunks with the same source and heading
+    Section,
+}
+
+impl FromStr for ContextExpansion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "none" => Ok(Self::None),
+            "section" => Ok(Self::Section),
+            other => other.strip_prefix("neighbors:")
+                .and_then(|radius| radius.parse().ok())
+                .map(Self::Neighbors)
+                .ok_or_else(|| format!("expected none, neighbors:N or section, got `{}`", value)),
+        }
+    }
+}
+
+/// Replace retrieved chunks with their parent windows (small-to-big retrieval).
+///
+/// Small chunks give precise matches, but the prompt gets the text around
+/// them, found through `Chunk::position`. Parents keep the matched chunk's id,
+/// source and heading and stay in relevance order; hits already inside an
+/// earlier parent are dropped.
+pub fn expand_context(index: &Index, hits: Vec<Chunk>, expansion: ContextExpansion) -> Result<Vec<Chunk>> {
+    // No radius for sections: they may run to either end of the file
+    let radius = match expansion {
+        ContextExpansion::None => return Ok(hits),
+        ContextExpansion::Neighbors(radius) => Some(radius),
+        ContextExpansion::Section => None,
+    };
+    debug!("expand_context - Expanding {} hits ({:?})", hits.len(), expansion);
+    
+    let mut covered: HashSet<String> = HashSet::new();
+    let mut parents = Vec::with_capacity(hits.len());
+    for hit in hits {
+        if covered.contains(&hit.id) {
+            debug!("expand_context - {} is already part of an earlier parent", hit.id);
+            continue;
+        }
+        
+        let file = chunk_file(&hit.id);
+        if format!("{}:chunk{}", file, hit.position) != hit.id {
+            warn!("expand_context - Chunk id {} does not match its position; not expanding", hit.id);
+            parents.push(hit);
+            continue;
+        }
+        
+        // Siblings by position, within the radius or across the whole file
+        let siblings: HashMap<usize, Chunk> = match radius {
+            Some(radius) => {
+                let ids: Vec<String> = (hit.position.saturating_sub(radius)..=hit.position + radius)
+                    .map(|position| format!("{}:chunk{}", file, position))
+                    .collect();
+                let id_refs: Vec<&str> = ids.iter().map(String::as_str).collect();
+                fetch_chunks(index, &id_refs)?.into_values().map(|chunk| (chunk.position, chunk)).collect()
+            }
+            None => file_chunks(index, file)?,
+        };
+        let first = radius.map_or(0, |radius| hit.position.saturating_sub(radius));
+        let last = radius.map_or_else(
+            || siblings.keys().copied().max().unwrap_or(hit.position),
+            |radius| hit.position + radius,
+        );
+        
+        // None for positions with no chunk, such as folded near-duplicates
+        let belongs = |position: usize| {
+            siblings.get(&position).map(|chunk| {
+                radius.is_some() || (chunk.source == hit.source && chunk.heading == hit.heading)
+            })
+        };
+        
+        // Grow the window from the hit, skipping missing positions, until a
+        // chunk from another section or the end of the range is reached
+        let (mut start, mut end) = (hit.position, hit.position);
+        for position in (first..hit.position).rev() {
+            match belongs(position) {
+                Some(true) => start = position,
+                Some(false) => break,
+                None => {}
+            }
+        }
+        for position in hit.position + 1..=last {
+            match belongs(position) {
+                Some(true) => end = position,
+                Some(false) => break,
+                None => {}
+            }
+        }
+        
+        let mut text = String::new();
+        for position in start..=end {
+            let chunk = match siblings.get(&position) {
+                _ if position == hit.position => &hit,
+                Some(sibling) => sibling,
+                None => continue,
+            };
+            text = join_overlapping(&text, &chunk.text);
+            covered.insert(chunk.id.clone());
+        }
+        debug!("expand_context - {} expanded to chunks {}..={}", hit.id, start, end);
+        parents.push(Chunk { text, ..hit });
+    }
+    
+    Ok(parents)
+}
+
+/// Load every chunk of `file` from the index, keyed by position
+fn file_chunks(index: &Index, file: &str) -> Result<HashMap<usize, Chunk>> {
+    let pattern = format!("{}:chunk[0-9]+", regex::escape(file));
+    let query = RegexQuery::from_pattern(&pattern, index.id_field)
+        .with_context(|| format!("Failed to build chunk id pattern for {}", file))?;
+    
+    let searcher = index.searcher();
+    let doc_addresses = searcher
+        .search(&query, &DocSetCollector)
+        .context("Failed to list chunks of file")?;
+    
+    let mut chunks = HashMap::with_capacity(doc_addresses.len());
+    for doc_address in doc_addresses {
+        let doc = searcher
+            .doc(doc_address)
+            .context("Failed to retrieve document")?;
+        let chunk = document_to_chunk(&doc, index, 0.0)?;
+        chunks.insert(chunk.position, chunk);
+    }
+    
+    debug!("file_chunks - Loaded {} chunks of {}", chunks.len(), file);
+    Ok(chunks)
+}
+
+/// Append `next` to `text`, dropping the words that overlapping windows repeat
+fn join_overlapping(text: &str, next: &str) -> String {
+    if text.is_empty() {
+        return next.to_string();
+    }
+    
+    // Longest suffix of `text` starting at a word that `next` begins with, ending at a word
+    let overlap = text.char_indices()
+        .filter(|(i, _)| *i == 0 || text[..*i].ends_with(char::is_whitespace))
+        .map(|(i, _)| &text[i..])
+        .find(|suffix| {
+            next.starts_with(suffix)
+                && next[suffix.len()..].chars().next().is_none_or(char::is_whitespace)
+        })
+        .map_or(0, str::len);
+    
+    let rest = next[overlap..].trim_start();
+    match (overlap, rest.is_empty()) {
+        (_, true) => text.to_string(),
+        (0, false) => format!("{}\n\n{}", text, rest),
+        (_, false) => format!("{} {}", text, rest),
+    }
+}
+
 /// Convert a Tantivy Document back to a Chunk object
 fn document_to_chunk(doc: &TantivyDocument, index: &Index, score: f32) -> Result<Chunk> {
     debug!("document_to_chunk - Converting document with score: {}", score);
@@ -250,6 +506,23 @@ fn document_to_chunk(doc: &TantivyDocument, index: &Index, score: f32) -> Result
         None => debug!("document_to_chunk - No heading found (optional field)"),
     }
 
+    // Page is only stored for paginated sources
+    let page = index.page_field
+        .and_then(|field| doc.get_first(field))
+        .and_then(|v| v.as_value().as_u64())
+        .map(|page| page as usize);
+
+    // Citations of near-duplicate chunks folded into this one
+    let alternate_sources = index.alternate_source_field
+        .map(|field| doc.get_all(field)
+            .filter_map(|v| v.as_value().as_str().map(|s| s.to_string()))
+            .collect())
+        .unwrap_or_default();
+
+    let metadata = index.metadata_fields
+        .map(|fields| fields.read(doc))
+        .unwrap_or_default();
+
     debug!("document_to_chunk - Successfully extracted all fields, creating chunk");
     Ok(Chunk {
         id,
@@ -257,6 +530,9 @@ fn document_to_chunk(doc: &TantivyDocument, index: &Index, score: f32) -> Result
         source,
         heading,
         position,
+        page,
+        alternate_sources,
+        metadata,
     })
 }
 
@@ -314,6 +590,173 @@ fn sanitize_query(query: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
+
+    /// Build a small on-disk index containing the given (id, text) pairs
+    fn build_test_index(dir: &std::path::Path, docs: &[(&str, &str)]) -> Index {
+        let index = crate::indexer::Index::create_in_dir(dir).unwrap();
+        let mut writer = index.writer(15_000_000).unwrap();
+        for (position, (id, text)) in docs.iter().enumerate() {
+            let mut doc = TantivyDocument::default();
+            doc.add_text(index.id_field, id);
+            doc.add_text(index.text_field, text);
+            doc.add_text(index.source_field, "test.md");
+            doc.add_text(index.heading_field, "");
+            doc.add_u64(index.position_field, position as u64);
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+        Index::new(index.tantivy_index).unwrap()
+    }
+
+    #[test]
+    fn test_bm25_search_scored_returns_descending_scores() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = build_test_index(temp_dir.path(), &[
+            ("a", "sprites sprites sprites render the sprite batch"),
+            ("b", "a single mention of sprites among many other unrelated words here"),
+            ("c", "nothing relevant at all"),
+        ]);
+
+        let results = bm25_search_scored(&index, "sprites", 10).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].chunk.id, "a");
+        assert!(results[0].score > results[1].score);
+        assert!(results.iter().all(|r| r.score > 0.0));
+
+        let chunks = bm25_search(&index, "sprites", 10).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].id, "a");
+    }
+
+    #[test]
+    fn test_fetch_chunks_by_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = build_test_index(temp_dir.path(), &[
+            ("docs/a.md_0", "first chunk"),
+            ("docs/a.md_1", "second chunk"),
+            ("docs/b.md_0", "third chunk"),
+        ]);
+
+        let chunks = fetch_chunks(&index, &["docs/a.md_1", "docs/b.md_0", "missing"]).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks["docs/a.md_1"].text, "second chunk");
+        assert_eq!(chunks["docs/b.md_0"].position, 2);
+    }
+
+    #[test]
+    fn test_all_chunks_in_file_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = build_test_index(temp_dir.path(), &[
+            ("docs/b.md:chunk0", "third chunk"),
+            ("docs/a.md:chunk0", "first chunk"),
+            ("docs/a.md:chunk1", "second chunk"),
+        ]);
+
+        let ids: Vec<String> = all_chunks(&index).unwrap().into_iter().map(|chunk| chunk.id).collect();
+        assert_eq!(ids, vec!["docs/a.md:chunk0", "docs/a.md:chunk1", "docs/b.md:chunk0"]);
+    }
+
+    /// Build an index of one file's chunks from (heading, text) pairs, positioned in order
+    fn build_file_index(dir: &std::path::Path, file: &str, chunks: &[(&str, &str)]) -> Index {
+        let index = crate::indexer::Index::create_in_dir(dir).unwrap();
+        let mut writer = index.writer(15_000_000).unwrap();
+        for (position, (heading, text)) in chunks.iter().enumerate() {
+            let mut doc = TantivyDocument::default();
+            doc.add_text(index.id_field, format!("{}:chunk{}", file, position));
+            doc.add_text(index.text_field, text);
+            doc.add_text(index.source_field, file);
+            doc.add_text(index.heading_field, heading);
+            doc.add_u64(index.position_field, position as u64);
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+        Index::new(index.tantivy_index).unwrap()
+    }
+
+    #[test]
+    fn test_context_expansion_from_str() {
+        assert_eq!("none".parse(), Ok(ContextExpansion::None));
+        assert_eq!("neighbors:2".parse(), Ok(ContextExpansion::Neighbors(2)));
+        assert_eq!("section".parse(), Ok(ContextExpansion::Section));
+        assert!("neighbors".parse::<ContextExpansion>().is_err());
+    }
+
+    #[test]
+    fn test_join_overlapping_drops_repeated_words() {
+        assert_eq!(join_overlapping("", "a b"), "a b");
+        assert_eq!(join_overlapping("one two three", "two three four"), "one two three four");
+        assert_eq!(join_overlapping("one two", "twofold"), "one two\n\ntwofold");
+        assert_eq!(join_overlapping("one two three", "two three"), "one two three");
+    }
+
+    #[test]
+    fn test_expand_context_returns_parent_windows() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = build_file_index(temp_dir.path(), "guide.md", &[
+            ("Intro", "Welcome to the guide."),
+            ("Setup", "Install the tool first."),
+            ("Setup", "tool first. Then run setup."),
+            ("Setup", "Finally restart the shell."),
+            ("Usage", "Call the tool with a path."),
+        ]);
+        let hits = |positions: &[usize]| -> Vec<Chunk> {
+            let ids: Vec<String> = positions.iter().map(|p| format!("guide.md:chunk{}", p)).collect();
+            let mut found = fetch_chunks(&index, &ids.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+            ids.iter().map(|id| found.remove(id).unwrap()).collect()
+        };
+
+        let parents = expand_context(&index, hits(&[2, 3]), ContextExpansion::Neighbors(1)).unwrap();
+        assert_eq!(parents.len(), 1, "a hit inside an earlier parent is dropped");
+        assert_eq!(parents[0].id, "guide.md:chunk2");
+        assert_eq!(parents[0].text, "Install the tool first. Then run setup.\n\nFinally restart the shell.");
+
+        let parents = expand_context(&index, hits(&[3, 0]), ContextExpansion::Section).unwrap();
+        assert_eq!(parents[0].text, "Install the tool first. Then run setup.\n\nFinally restart the shell.");
+        assert_eq!(parents[0].heading.as_deref(), Some("Setup"));
+        assert_eq!(parents[1].text, "Welcome to the guide.");
+
+        let unchanged = expand_context(&index, hits(&[4]), ContextExpansion::None).unwrap();
+        assert_eq!(unchanged[0].text, "Call the tool with a path.");
+    }
+
+    #[test]
+    fn test_expand_context_skips_missing_positions() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = build_file_index(temp_dir.path(), "guide.md", &[
+            ("Setup", "Install the tool first."),
+            ("Setup", "Licensed under MIT."),
+            ("Setup", "Then run setup."),
+            ("Usage", "Call the tool with a path."),
+        ]);
+        // Remove the chunk folded into another file's copy
+        let mut writer: tantivy::IndexWriter = index.tantivy_index.writer(15_000_000).unwrap();
+        writer.delete_term(Term::from_field_text(index.id_field, "guide.md:chunk1"));
+        writer.commit().unwrap();
+        let index = Index::new(index.tantivy_index).unwrap();
+
+        let hit = fetch_chunks(&index, &["guide.md:chunk0"]).unwrap().remove("guide.md:chunk0").unwrap();
+        let parents = expand_context(&index, vec![hit.clone()], ContextExpansion::Neighbors(2)).unwrap();
+        assert_eq!(parents[0].text, "Install the tool first.\n\nThen run setup.");
+
+        let parents = expand_context(&index, vec![hit], ContextExpansion::Section).unwrap();
+        assert_eq!(parents[0].text, "Install the tool first.\n\nThen run setup.");
+    }
+
+    #[test]
+    fn test_section_expansion_covers_long_sections() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = "docs/set-up & run (v2)+notes#1~.md";
+        let texts: Vec<String> = (0..20).map(|i| format!("Step {}.", i)).collect();
+        let mut chunks: Vec<(&str, &str)> = texts.iter().map(|text| ("Setup", text.as_str())).collect();
+        chunks.push(("Usage", "Call the tool with a path."));
+        let index = build_file_index(temp_dir.path(), file, &chunks);
+
+        let id = format!("{}:chunk19", file);
+        let hit = fetch_chunks(&index, &[id.as_str()]).unwrap().remove(&id).unwrap();
+        let parents = expand_context(&index, vec![hit], ContextExpansion::Section).unwrap();
+        assert_eq!(parents[0].text, texts.join("\n\n"));
+    }
 
     #[test]
     fn test_sanitize_query() {
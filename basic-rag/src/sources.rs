@@ -0,0 +1,368 @@
+// src/sources.rs
+
+//! Selection of the files to ingest
+//!
+//! Walks the docs directory honouring `.gitignore` and `.ragignore` files,
+//! keeps the paths matched by the include/exclude globs and assigns each one
+//! a [`Processor`] from the file-type registry. Globs are matched against
+//! paths relative to the docs directory; a glob without a `/` matches at any
+//! depth, like in `.gitignore`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cli::Cli;
+
+/// Name of the ingestion-specific ignore file, read like `.gitignore`
+const RAG_IGNORE_FILE: &str = ".ragignore";
+
+/// Built-in file types, consulted after any configured rules
+const DEFAULT_FILE_TYPES: &[(&str, Processor)] = &[
+    ("*.md", Processor::Markdown),
+    ("*.markdown", Processor::Markdown),
+    ("*.html", Processor::Html),
+    ("*.htm", Processor::Html),
+    ("*.txt", Processor::Text),
+    ("*.rst", Processor::Rst),
+    ("*.pdf", Processor::Pdf),
+    ("*.rs", Processor::Rust),
+    ("*.ipynb", Processor::Notebook),
+    ("*.json", Processor::Openapi),
+    ("*.yaml", Processor::Openapi),
+    ("*.yml", Processor::Openapi),
+    ("*.csv", Processor::Csv),
+];
+
+/// Extractor used to split a file into sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Processor {
+    /// Markdown, split at headings
+    Markdown,
+    /// HTML main content, split at `h1`–`h6`
+    Html,
+    /// reStructuredText, split at section titles
+    Rst,
+    /// PDF text, one section per page
+    Pdf,
+    /// Rust source, one section per documented or public item
+    Rust,
+    /// Jupyter notebook cells
+    Notebook,
+    /// OpenAPI spec in JSON or YAML, one section per operation
+    Openapi,
+    /// CSV rows grouped to the chunk size
+    Csv,
+    /// Plain text without structure
+    Text,
+}
+
+/// Maps files matching `glob` to `processor`, e.g. `*.mdx=markdown`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FileTypeRule {
+    pub glob: String,
+    pub processor: Processor,
+}
+
+impl FromStr for FileTypeRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (glob, processor) = rule.rsplit_once('=')
+            .ok_or_else(|| format!("expected GLOB=PROCESSOR, got `{}`", rule))?;
+        let processor = <Processor as clap::ValueEnum>::from_str(processor.trim(), true)?;
+        Ok(Self { glob: glob.trim().to_string(), processor })
+    }
+}
+
+/// File selection settings, read from `--ingest-config` and extended by CLI flags
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestConfig {
+    /// Only ingest files matching one of these globs (all files when empty)
+    pub include: Vec<String>,
+    /// Never ingest files matching these globs
+    pub exclude: Vec<String>,
+    /// File types checked before the built-in ones; the first match wins
+    pub file_types: Vec<FileTypeRule>,
+    /// Skip files listed in `.gitignore`, `.ignore` and `.ragignore` files
+    pub respect_ignore_files: bool,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            file_types: Vec::new(),
+            respect_ignore_files: true,
+        }
+    }
+}
+
+impl IngestConfig {
+    /// Load a JSON or YAML config file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ingest config {:?}", path))?;
+        let extension = path.extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        match extension.as_str() {
+            "json" => serde_json::from_str(&content)
+                .with_context(|| format!("Invalid ingest config {:?}", path)),
+            "yaml" | "yml" => serde_yaml::from_str(&content)
+                .with_context(|| format!("Invalid ingest config {:?}", path)),
+            _ => bail!("Ingest config {:?} must be a .json, .yaml or .yml file", path),
+        }
+    }
+
+    /// Config file from the CLI (if any) with the CLI's patterns added.
+    ///
+    /// CLI file types take precedence over those in the file.
+    pub fn from_cli(cli: &Cli) -> Result<Self> {
+        let mut config = match &cli.ingest_config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        config.include.extend(cli.include.iter().cloned());
+        config.exclude.extend(cli.exclude.iter().cloned());
+        config.file_types.splice(0..0, cli.file_type.iter().cloned());
+        if cli.no_ignore_files {
+            config.respect_ignore_files = false;
+        }
+
+        Ok(config)
+    }
+}
+
+/// Maps paths to processors; configured rules come before the built-in ones
+pub struct FileTypeRegistry {
+    globs: GlobSet,
+    processors: Vec<Processor>,
+}
+
+impl FileTypeRegistry {
+    pub fn new(rules: &[FileTypeRule]) -> Result<Self> {
+        let rules = rules.iter()
+            .map(|rule| (rule.glob.as_str(), rule.processor))
+            .chain(DEFAULT_FILE_TYPES.iter().copied());
+
+        let mut globs = GlobSetBuilder::new();
+        let mut processors = Vec::new();
+        for (glob, processor) in rules {
+            // Extensions are compared case-insensitively (`spec.PDF`)
+            globs.add(build_glob(glob, true)?);
+            processors.push(processor);
+        }
+
+        Ok(Self {
+            globs: globs.build().context("Failed to build file-type registry")?,
+            processors,
+        })
+    }
+
+    /// Processor of the first rule matching `relative_path`
+    pub fn processor_for(&self, relative_path: &Path) -> Option<Processor> {
+        self.globs.matches(relative_path)
+            .into_iter()
+            .min()
+            .map(|index| self.processors[index])
+    }
+}
+
+/// A file selected for ingestion
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// Path relative to the docs directory, used as the chunk source
+    pub relative_path: String,
+    pub processor: Processor,
+}
+
+/// Walk `docs_dir` and return the files to ingest, sorted by path
+pub fn discover_files(docs_dir: &Path, config: &IngestConfig) -> Result<Vec<SourceFile>> {
+    let include = (!config.include.is_empty())
+        .then(|| build_glob_set(&config.include))
+        .transpose()?;
+    let exclude = build_glob_set(&config.exclude)?;
+    let registry = FileTypeRegistry::new(&config.file_types)?;
+
+    let mut walker = WalkBuilder::new(docs_dir);
+    walker
+        .standard_filters(config.respect_ignore_files)
+        .hidden(false)
+        // Docs directories are often plain folders or subfolders of a repo
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if config.respect_ignore_files {
+        walker.add_custom_ignore_filename(RAG_IGNORE_FILE);
+    }
+
+    let mut files = Vec::new();
+    let mut skipped = 0;
+    for entry in walker.build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Skipping unreadable path under {:?}: {}", docs_dir, e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+
+        let path = entry.into_path();
+        let relative = path.strip_prefix(docs_dir).unwrap_or(&path).to_path_buf();
+        let selected = include.as_ref().is_none_or(|include| include.is_match(&relative))
+            && !exclude.is_match(&relative);
+        let processor = if selected { registry.processor_for(&relative) } else { None };
+
+        match processor {
+            Some(processor) => files.push(SourceFile {
+                relative_path: relative.to_string_lossy().to_string(),
+                path,
+                processor,
+            }),
+            None => {
+                debug!("Not ingesting {:?}", relative);
+                skipped += 1;
+            }
+        }
+    }
+
+    info!("📂 Selected {} files to ingest ({} skipped by patterns or file type)", files.len(), skipped);
+    Ok(files)
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut globs = GlobSetBuilder::new();
+    for pattern in patterns {
+        globs.add(build_glob(pattern, false)?);
+    }
+    globs.build().context("Failed to build glob set")
+}
+
+/// `*` stays within one path component; globs without a `/` match at any depth
+fn build_glob(pattern: &str, case_insensitive: bool) -> Result<globset::Glob> {
+    let pattern = pattern.trim_start_matches("./");
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|e| anyhow!("Invalid glob `{}`: {}", pattern, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, relative: &str, content: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn discovered(root: &Path, config: &IngestConfig) -> Vec<(String, Processor)> {
+        discover_files(root, config).unwrap()
+            .into_iter()
+            .map(|file| (file.relative_path, file.processor))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_registry() {
+        let registry = FileTypeRegistry::new(&[]).unwrap();
+        assert_eq!(registry.processor_for(Path::new("test.md")), Some(Processor::Markdown));
+        assert_eq!(registry.processor_for(Path::new("api/test.html")), Some(Processor::Html));
+        assert_eq!(registry.processor_for(Path::new("notes/test.txt")), Some(Processor::Text));
+        assert_eq!(registry.processor_for(Path::new("spec.PDF")), Some(Processor::Pdf));
+        assert_eq!(registry.processor_for(Path::new("analysis.ipynb")), Some(Processor::Notebook));
+        assert_eq!(registry.processor_for(Path::new("openapi.yml")), Some(Processor::Openapi));
+        assert_eq!(registry.processor_for(Path::new("test.docx")), None);
+        assert_eq!(registry.processor_for(Path::new("test.jpg")), None);
+    }
+
+    #[test]
+    fn test_configured_file_types_take_precedence() {
+        let rules: Vec<FileTypeRule> = ["*.mdx=markdown", "config/*.yaml = text"].iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        let registry = FileTypeRegistry::new(&rules).unwrap();
+
+        assert_eq!(registry.processor_for(Path::new("guide/intro.mdx")), Some(Processor::Markdown));
+        assert_eq!(registry.processor_for(Path::new("config/app.yaml")), Some(Processor::Text));
+        assert_eq!(registry.processor_for(Path::new("config/nested/app.yaml")), Some(Processor::Openapi));
+
+        assert!("*.mdx".parse::<FileTypeRule>().is_err());
+        assert!("*.mdx=docx".parse::<FileTypeRule>().is_err());
+    }
+
+    #[test]
+    fn test_discover_files_applies_patterns_and_ignore_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write(root, "docs/guide.md", "# Guide");
+        write(root, "docs/build/generated.md", "# Generated");
+        write(root, "docs/drafts/todo.md", "# Todo");
+        write(root, "docs/logo.png", "");
+        write(root, "crates/core/README.md", "# Core");
+        write(root, "crates/core/src/README.md", "# Nested");
+        write(root, "src/main.rs", "fn main() {}");
+        write(root, ".gitignore", "build/\n");
+        write(root, "docs/.ragignore", "drafts/\n");
+
+        let config = IngestConfig {
+            include: vec!["docs/**".to_string(), "crates/*/README.md".to_string()],
+            ..IngestConfig::default()
+        };
+        assert_eq!(discovered(root, &config), vec![
+            ("crates/core/README.md".to_string(), Processor::Markdown),
+            ("docs/guide.md".to_string(), Processor::Markdown),
+        ]);
+
+        let config = IngestConfig {
+            exclude: vec!["*.rs".to_string(), "crates/**".to_string()],
+            respect_ignore_files: false,
+            ..IngestConfig::default()
+        };
+        assert_eq!(discovered(root, &config), vec![
+            ("docs/build/generated.md".to_string(), Processor::Markdown),
+            ("docs/drafts/todo.md".to_string(), Processor::Markdown),
+            ("docs/guide.md".to_string(), Processor::Markdown),
+        ]);
+    }
+
+    #[test]
+    fn test_ingest_config_from_yaml() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("ingest.yaml");
+        fs::write(&path, "include: [docs/**]\nfile_types:\n  - glob: '*.mdx'\n    processor: markdown\n").unwrap();
+
+        let config = IngestConfig::from_file(&path).unwrap();
+        assert_eq!(config.include, vec!["docs/**"]);
+        assert_eq!(config.file_types, vec![FileTypeRule { glob: "*.mdx".to_string(), processor: Processor::Markdown }]);
+        assert!(config.respect_ignore_files);
+
+        fs::write(&path, "includes: [docs/**]\n").unwrap();
+        assert!(IngestConfig::from_file(&path).is_err());
+    }
+}
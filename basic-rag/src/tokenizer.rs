@@ -0,0 +1,313 @@
+// src/tokenizer.rs
+
+//! Token counting for Basic RAG
+//!
+//! One [`Tokenizer`] is shared by chunking, prompt budgeting and the LLM
+//! client so that chunk sizes, context budgets and `max_tokens` agree.
+//! [`Bpe`] reproduces OpenAI's byte-pair encodings from the `.tiktoken`
+//! vocabulary files (`cl100k_base.tiktoken`, `o200k_base.tiktoken`) in
+//! `--tokenizer-dir`; without them [`CharEstimate`] assumes 4 bytes per token.
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use fancy_regex::Regex;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::cli::{Cli, TokenizerKind};
+use crate::llm;
+
+/// Bytes per token assumed when no vocabulary is available
+const BYTES_PER_TOKEN: usize = 4;
+
+/// Pre-tokenization pattern of `cl100k_base`
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// Pre-tokenization pattern of `o200k_base`
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+
+/// Counts and truncates text in model tokens
+pub trait Tokenizer: Send + Sync {
+    /// Number of tokens in `text`
+    fn count(&self, text: &str) -> usize;
+
+    /// Longest prefix of `text` that fits in `max_tokens` tokens
+    fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str;
+
+    /// Name of the encoding, recorded with the index so re-chunking is detected
+    fn name(&self) -> &str;
+}
+
+impl fmt::Debug for dyn Tokenizer + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tokenizer({})", self.name())
+    }
+}
+
+/// Load the tokenizer selected on the command line, falling back to
+/// [`CharEstimate`] when its vocabulary file cannot be read
+pub fn from_cli(cli: &Cli) -> Arc<dyn Tokenizer> {
+    let encoding = match cli.tokenizer {
+        TokenizerKind::Heuristic => return Arc::new(CharEstimate),
+        TokenizerKind::Cl100k => Encoding::Cl100k,
+        TokenizerKind::O200k => Encoding::O200k,
+        TokenizerKind::Auto => Encoding::for_model(llm::DEFAULT_MODEL),
+    };
+
+    let path = cli.tokenizer_dir.join(encoding.file_name());
+    match Bpe::from_file(&path, encoding) {
+        Ok(bpe) => {
+            debug!("Counting tokens with {} from {:?}", bpe.name(), path);
+            Arc::new(bpe)
+        }
+        Err(e) if matches!(cli.tokenizer, TokenizerKind::Auto) => {
+            debug!("No {} vocabulary ({:#}); estimating tokens from length", encoding.name(), e);
+            Arc::new(CharEstimate)
+        }
+        Err(e) => {
+            warn!("Failed to load {} tokenizer, estimating tokens from length: {:#}", encoding.name(), e);
+            Arc::new(CharEstimate)
+        }
+    }
+}
+
+/// OpenAI byte-pair encodings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// GPT-4 and GPT-3.5 models
+    Cl100k,
+    /// GPT-4o, GPT-4.1 and o-series models
+    O200k,
+}
+
+impl Encoding {
+    /// Encoding used by an OpenAI chat model
+    pub fn for_model(model: &str) -> Self {
+        let o200k_prefixes = ["gpt-4o", "gpt-4.1", "gpt-4.5", "gpt-5", "o1", "o3", "o4"];
+        match o200k_prefixes.iter().any(|prefix| model.starts_with(prefix)) {
+            true => Encoding::O200k,
+            false => Encoding::Cl100k,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Cl100k => "cl100k_base",
+            Encoding::O200k => "o200k_base",
+        }
+    }
+
+    /// Vocabulary file name, as published by OpenAI
+    pub fn file_name(self) -> String {
+        format!("{}.tiktoken", self.name())
+    }
+
+    fn pattern(self) -> &'static str {
+        match self {
+            Encoding::Cl100k => CL100K_PATTERN,
+            Encoding::O200k => O200K_PATTERN,
+        }
+    }
+}
+
+/// Estimates 4 bytes per token, matching English text closely enough for budgets
+#[derive(Debug, Clone, Copy)]
+pub struct CharEstimate;
+
+impl Tokenizer for CharEstimate {
+    fn count(&self, text: &str) -> usize {
+        text.len().div_ceil(BYTES_PER_TOKEN)
+    }
+
+    fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        let mut end = text.len().min(max_tokens * BYTES_PER_TOKEN);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        &text[..end]
+    }
+
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+}
+
+/// Byte-pair encoding with a tiktoken vocabulary
+pub struct Bpe {
+    name: String,
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: Regex,
+}
+
+impl Bpe {
+    /// Load a `.tiktoken` file: one base64 token and its rank per line
+    pub fn from_file(path: &Path, encoding: Encoding) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tokenizer vocabulary {:?}", path))?;
+        let mut ranks = HashMap::new();
+        for (number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let parsed = line.split_once(' ').and_then(|(token, rank)| {
+                let token = base64::engine::general_purpose::STANDARD.decode(token).ok()?;
+                Some((token, rank.trim().parse::<u32>().ok()?))
+            });
+            let (token, rank) = parsed
+                .ok_or_else(|| anyhow!("Invalid vocabulary entry on line {} of {:?}", number + 1, path))?;
+            ranks.insert(token, rank);
+        }
+        Self::new(encoding.name(), ranks, encoding.pattern())
+    }
+
+    /// Build an encoding from token ranks and its pre-tokenization pattern
+    pub fn new(name: &str, ranks: HashMap<Vec<u8>, u32>, pattern: &str) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            ranks,
+            pattern: Regex::new(pattern).context("Invalid pre-tokenization pattern")?,
+        })
+    }
+
+    /// Tokens in one pre-tokenized piece, merging the lowest-ranked pair first
+    fn piece_tokens(&self, piece: &[u8]) -> usize {
+        if self.ranks.contains_key(piece) {
+            return 1;
+        }
+        // Token boundaries within the piece, starting from single bytes
+        let mut bounds: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let best = (0..bounds.len().saturating_sub(2))
+                .filter_map(|i| self.ranks.get(&piece[bounds[i]..bounds[i + 2]]).map(|rank| (*rank, i)))
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => return bounds.len() - 1,
+            }
+        }
+    }
+
+    /// End offset and cumulative token count after each piece of `text`
+    fn pieces<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let mut total = 0;
+        self.pattern.find_iter(text)
+            .map_while(Result::ok)
+            .map(move |piece| {
+                total += self.piece_tokens(piece.as_str().as_bytes());
+                (piece.end(), total)
+            })
+    }
+}
+
+impl Tokenizer for Bpe {
+    fn count(&self, text: &str) -> usize {
+        let (end, tokens) = self.pieces(text).last().unwrap_or((0, 0));
+        // Text the pattern gave up on (backtracking limit) is estimated
+        tokens + CharEstimate.count(&text[end..])
+    }
+
+    fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        let end = self.pieces(text)
+            .take_while(|(_, tokens)| *tokens <= max_tokens)
+            .last()
+            .map_or(0, |(end, _)| end);
+        &text[..end]
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Counts whitespace-separated words, so chunking tests read in words
+#[cfg(test)]
+pub struct Words;
+
+#[cfg(test)]
+impl Tokenizer for Words {
+    fn count(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        match text.split_whitespace().nth(max_tokens) {
+            Some(word) => text[..word.as_ptr() as usize - text.as_ptr() as usize].trim_end(),
+            None => text,
+        }
+    }
+
+    fn name(&self) -> &str {
+        "words"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use tempfile::TempDir;
+
+    /// A tiny vocabulary: all single bytes plus a few merges
+    fn write_vocabulary(dir: &Path) -> std::path::PathBuf {
+        let mut tokens: Vec<Vec<u8>> = (0..=255u8).map(|byte| vec![byte]).collect();
+        tokens.extend(["he", "ll", "hell", "hello", " w", " wor", " world"].map(|token| token.as_bytes().to_vec()));
+        let lines: Vec<String> = tokens.iter()
+            .enumerate()
+            .map(|(rank, token)| format!("{} {}", STANDARD.encode(token), rank))
+            .collect();
+        let path = dir.join("cl100k_base.tiktoken");
+        fs::write(&path, lines.join("\n")).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_char_estimate() {
+        assert_eq!(CharEstimate.count(""), 0);
+        assert_eq!(CharEstimate.count("test"), 1);
+        assert_eq!(CharEstimate.count("this is a test"), 4);
+        assert_eq!(CharEstimate.count(&"a".repeat(100)), 25);
+        assert_eq!(CharEstimate.truncate("héllo wörld", 1), "hél");
+    }
+
+    #[test]
+    fn test_bpe_merges_by_rank() {
+        let dir = TempDir::new().unwrap();
+        let bpe = Bpe::from_file(&write_vocabulary(dir.path()), Encoding::Cl100k).unwrap();
+
+        assert_eq!(bpe.name(), "cl100k_base");
+        assert_eq!(bpe.count("hello world"), 2);
+        // Only "he" merges, leaving "l" and "p" as single bytes
+        assert_eq!(bpe.count("help"), 3);
+        assert_eq!(bpe.count("hello world, hello"), 5);
+        assert_eq!(bpe.truncate("hello world, hello", 2), "hello world");
+        assert_eq!(bpe.truncate("hello world", 0), "");
+    }
+
+    #[test]
+    fn test_encoding_for_model() {
+        assert_eq!(Encoding::for_model("gpt-4o-mini"), Encoding::O200k);
+        assert_eq!(Encoding::for_model("gpt-4-turbo"), Encoding::Cl100k);
+        assert_eq!(Encoding::O200k.file_name(), "o200k_base.tiktoken");
+
+        // With no merges every byte is a token; both patterns cover all of the text
+        for encoding in [Encoding::Cl100k, Encoding::O200k] {
+            let bytes = Bpe::new(encoding.name(), HashMap::new(), encoding.pattern()).unwrap();
+            assert_eq!(bytes.count("Don't panic!\n  x = 42;"), 22);
+        }
+    }
+
+    #[test]
+    fn test_invalid_vocabulary_is_rejected() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("o200k_base.tiktoken");
+        fs::write(&path, "not-base64! x").unwrap();
+        assert!(Bpe::from_file(&path, Encoding::O200k).is_err());
+    }
+}
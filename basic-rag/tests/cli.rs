@@ -0,0 +1,196 @@
+use assert_cmd::Command;
+use std::fs;
+use std::path::Path;
+use tantivy::collector::Count;
+use tantivy::query::{QueryParser, TermQuery};
+use tantivy::schema::IndexRecordOption;
+use tantivy::{Index, Term};
+use tempfile::TempDir;
+
+/// Run `init` for BM25 only from `cwd` on `root/docs`, indexing into `root/index`.
+///
+/// `options` are global flags placed before the subcommand.
+fn init(cwd: &Path, root: &Path, options: &[&str]) {
+    Command::cargo_bin("basic-rag")
+        .unwrap()
+        .current_dir(cwd)
+        .env("OPENAI_API_KEY", "test-key")
+        .arg("--docs-dir").arg(root.join("docs"))
+        .arg("--index-dir").arg(root.join("index"))
+        .args(options)
+        .args(["init", "--skip-embeddings"])
+        .assert()
+        .success();
+}
+
+/// Number of indexed chunks from `source`
+fn chunks_from(index: &Index, source: &str) -> usize {
+    let field = index.schema().get_field("source").unwrap();
+    let query = TermQuery::new(Term::from_field_text(field, source), IndexRecordOption::Basic);
+    index.reader().unwrap().searcher().search(&query, &Count).unwrap()
+}
+
+/// Number of indexed chunks whose text matches `word`
+fn chunks_matching(index: &Index, word: &str) -> usize {
+    let field = index.schema().get_field("text").unwrap();
+    let query = QueryParser::for_index(index, vec![field]).parse_query(word).unwrap();
+    index.reader().unwrap().searcher().search(&query, &Count).unwrap()
+}
+
+#[test]
+fn reinit_after_editing_one_file_keeps_the_others() {
+    let workdir = TempDir::new().unwrap();
+    let docs = workdir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    fs::write(docs.join("install.md"), "# Install\n\nRun the installer and accept the licence.").unwrap();
+    fs::write(docs.join("usage.md"), "# Usage\n\nStart the server with the serve command.").unwrap();
+    fs::write(docs.join("faq.md"), "# FAQ\n\nAnswers to frequently asked questions.").unwrap();
+
+    init(workdir.path(), workdir.path(), &[]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_from(&index, "install.md"), 1);
+    assert_eq!(chunks_from(&index, "usage.md"), 1);
+    assert_eq!(chunks_from(&index, "faq.md"), 1);
+
+    fs::write(docs.join("install.md"), "# Install\n\nUse the package manager instead.").unwrap();
+    fs::remove_file(docs.join("faq.md")).unwrap();
+    init(workdir.path(), workdir.path(), &[]);
+
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_from(&index, "install.md"), 1);
+    assert_eq!(chunks_from(&index, "usage.md"), 1, "unchanged file was dropped from the index");
+    assert_eq!(chunks_from(&index, "faq.md"), 0, "deleted file is still indexed");
+    assert_eq!(chunks_matching(&index, "licence"), 0);
+    assert_eq!(chunks_matching(&index, "package"), 1);
+    assert_eq!(chunks_matching(&index, "server"), 1);
+}
+
+#[test]
+fn changing_chunk_size_rebuilds_the_index() {
+    let workdir = TempDir::new().unwrap();
+    let docs = workdir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    let words: Vec<String> = (0..40).map(|i| format!("word{}", i)).collect();
+    fs::write(docs.join("long.md"), words.join(" ")).unwrap();
+
+    init(workdir.path(), workdir.path(), &[]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_from(&index, "long.md"), 1);
+    assert!(workdir.path().join("index/ingest_state.json").exists());
+    assert!(!workdir.path().join("state.json").exists());
+
+    // Same docs and index from another working directory: nothing to re-ingest
+    let elsewhere = TempDir::new().unwrap();
+    init(elsewhere.path(), workdir.path(), &[]);
+    let index_dir = workdir.path().join("index");
+    let index = Index::open_in_dir(&index_dir).unwrap();
+    assert_eq!(chunks_from(&index, "long.md"), 1);
+    assert!(!elsewhere.path().join("state.json").exists());
+
+    // Each word is two tokens at 4 bytes per token, so 10 words per chunk
+    init(workdir.path(), workdir.path(), &["--chunk-size", "20", "--chunk-overlap", "0"]);
+    let index = Index::open_in_dir(&index_dir).unwrap();
+    assert_eq!(chunks_from(&index, "long.md"), 4);
+    assert_eq!(chunks_matching(&index, "word39"), 1);
+}
+
+#[test]
+fn shared_boilerplate_is_indexed_once() {
+    let workdir = TempDir::new().unwrap();
+    let docs = workdir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    let footer = "## License\n\nLicensed under either of Apache License, Version 2.0 or MIT license at your option. \
+                  Contributions are dual licensed as above, without any additional terms or conditions.";
+    fs::write(docs.join("alpha.md"), format!("# Alpha\n\nAlpha parses the input.\n\n{}", footer)).unwrap();
+    fs::write(docs.join("beta.md"), format!("# Beta\n\nBeta renders the output.\n\n{}", footer)).unwrap();
+
+    init(workdir.path(), workdir.path(), &[]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_matching(&index, "dual"), 1);
+    assert_eq!(chunks_from(&index, "alpha.md"), 2);
+    assert_eq!(chunks_from(&index, "beta.md"), 1);
+    let alternate = index.schema().get_field("alternate_source").unwrap();
+    let query = TermQuery::new(Term::from_field_text(alternate, "beta.md"), IndexRecordOption::Basic);
+    assert_eq!(index.reader().unwrap().searcher().search(&query, &Count).unwrap(), 1);
+
+    // Dropping the footer from the file that kept it brings back the other copy
+    fs::write(docs.join("alpha.md"), "# Alpha\n\nAlpha parses the input.").unwrap();
+    init(workdir.path(), workdir.path(), &[]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_matching(&index, "dual"), 1);
+    assert_eq!(chunks_from(&index, "alpha.md"), 1);
+    assert_eq!(chunks_from(&index, "beta.md"), 2);
+
+    init(workdir.path(), workdir.path(), &["--no-dedup"]);
+    fs::write(docs.join("alpha.md"), format!("# Alpha\n\nAlpha parses the input.\n\n{}", footer)).unwrap();
+    init(workdir.path(), workdir.path(), &["--no-dedup"]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_matching(&index, "dual"), 2);
+}
+
+#[test]
+fn new_file_boilerplate_folds_into_the_indexed_copy() {
+    let workdir = TempDir::new().unwrap();
+    let docs = workdir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    let footer = "## License\n\nLicensed under either of Apache License, Version 2.0 or MIT license at your option. \
+                  Contributions are dual licensed as above, without any additional terms or conditions.";
+    fs::write(docs.join("alpha.md"), format!("# Alpha\n\nAlpha parses the input.\n\n{}", footer)).unwrap();
+    init(workdir.path(), workdir.path(), &[]);
+
+    fs::write(docs.join("gamma.md"), format!("# Gamma\n\nGamma checks the types.\n\n{}", footer)).unwrap();
+    init(workdir.path(), workdir.path(), &[]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_matching(&index, "dual"), 1);
+    assert_eq!(chunks_from(&index, "alpha.md"), 2);
+    assert_eq!(chunks_from(&index, "gamma.md"), 1);
+    let alternate = index.schema().get_field("alternate_source").unwrap();
+    let query = TermQuery::new(Term::from_field_text(alternate, "gamma.md"), IndexRecordOption::Basic);
+    assert_eq!(index.reader().unwrap().searcher().search(&query, &Count).unwrap(), 1);
+
+    // The link was recorded, so dropping the kept copy brings back gamma's
+    fs::write(docs.join("alpha.md"), "# Alpha\n\nAlpha parses the input.").unwrap();
+    init(workdir.path(), workdir.path(), &[]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    assert_eq!(chunks_matching(&index, "dual"), 1);
+    assert_eq!(chunks_from(&index, "gamma.md"), 2);
+}
+
+#[test]
+fn hnsw_m_below_two_is_rejected() {
+    for args in [&["init", "--hnsw-m", "1"][..], &["bench-ann", "--m", "0"]] {
+        Command::cargo_bin("basic-rag")
+            .unwrap()
+            .env("OPENAI_API_KEY", "test-key")
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("is not in 2.."));
+    }
+}
+
+#[test]
+fn front_matter_is_indexed_as_metadata() {
+    let workdir = TempDir::new().unwrap();
+    let docs = workdir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    fs::write(
+        docs.join("guide.md"),
+        "---\ntitle: Getting Started\ntags: [setup, cli]\nversion: \"2.1\"\n---\n\n# Install\n\nRun the installer.",
+    ).unwrap();
+    fs::write(docs.join("notes.txt"), "Plain notes without front matter.").unwrap();
+
+    init(workdir.path(), workdir.path(), &[]);
+    let index = Index::open_in_dir(workdir.path().join("index")).unwrap();
+    let count = |field: &str, value: &str| {
+        let field = index.schema().get_field(field).unwrap();
+        let query = TermQuery::new(Term::from_field_text(field, value), IndexRecordOption::Basic);
+        index.reader().unwrap().searcher().search(&query, &Count).unwrap()
+    };
+    assert_eq!(count("title", "Getting Started"), 1);
+    assert_eq!(count("tag", "cli"), 1);
+    assert_eq!(count("doc_version", "2.1"), 1);
+    assert_eq!(count("file_type", "markdown"), 1);
+    assert_eq!(count("file_type", "text"), 1);
+    assert_eq!(count("title", "notes"), 1);
+}